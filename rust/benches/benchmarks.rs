@@ -3,7 +3,7 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use pixestl::color::{find_closest_color, CieLab, ColorDistance, ColorDistanceMethod, Rgb};
 use pixestl::lithophane::{Mesh, Vector3};
-use pixestl::palette::quantize_pixels;
+use pixestl::palette::{create_multi_combi, quantize_pixels, ColorIndex, ColorLayer};
 
 fn bench_cielab_conversion(c: &mut Criterion) {
     let colors: Vec<Rgb> = (0..256)
@@ -47,6 +47,34 @@ fn bench_find_closest_color(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_color_index(c: &mut Criterion) {
+    let palette: Vec<Rgb> = (0..20_000)
+        .map(|i| {
+            Rgb::new(
+                ((i * 37) % 256) as u8,
+                ((i * 73 + i / 256) % 256) as u8,
+                ((i * 113 + i / 97) % 256) as u8,
+            )
+        })
+        .collect();
+    let target = Rgb::new(128, 64, 200);
+
+    let mut group = c.benchmark_group("nearest_20k_colors");
+
+    group.bench_function("brute_force_cielab", |b| {
+        b.iter(|| find_closest_color(&target, &palette, ColorDistanceMethod::CieLab))
+    });
+
+    let index = ColorIndex::new(&palette, ColorDistanceMethod::CieLab);
+    group.bench_function("kd_tree_cielab", |b| b.iter(|| index.nearest(&target)));
+
+    group.bench_function("kd_tree_build_cielab", |b| {
+        b.iter(|| ColorIndex::new(&palette, ColorDistanceMethod::CieLab))
+    });
+
+    group.finish();
+}
+
 fn bench_quantize_pixels(c: &mut Criterion) {
     let palette: Vec<Rgb> = (0..20)
         .map(|i| {
@@ -75,6 +103,27 @@ fn bench_quantize_pixels(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_create_multi_combi(c: &mut Criterion) {
+    // 10 filaments with 6 layer counts each
+    let layers: Vec<ColorLayer> = (0..10)
+        .flat_map(|f| {
+            (1..=6).map(move |n| {
+                ColorLayer::new(
+                    format!("#{:06X}", f * 0x111111),
+                    n,
+                    f64::from(f) * 36.0,
+                    80.0,
+                    90.0 - f64::from(n) * 8.0,
+                )
+            })
+        })
+        .collect();
+
+    c.bench_function("create_multi_combi_10x6", |b| {
+        b.iter(|| create_multi_combi(None, &layers, 6))
+    });
+}
+
 fn bench_mesh_cube(c: &mut Criterion) {
     c.bench_function("mesh_cube_1000", |b| {
         b.iter(|| {
@@ -118,7 +167,9 @@ criterion_group!(
     bench_cielab_conversion,
     bench_delta_e,
     bench_find_closest_color,
+    bench_color_index,
     bench_quantize_pixels,
+    bench_create_multi_combi,
     bench_mesh_cube,
     bench_mesh_merge,
 );
@@ -2,13 +2,19 @@
 
 use crate::color::ColorDistanceMethod;
 use crate::error::Result;
-use crate::image::load_image;
-use crate::lithophane::{LithophaneConfig, NamedLayer, PixelCreationMethod as LithoPixelMethod};
+use crate::filament::{FilamentMapping, FilamentProperties, FilamentUsage};
+use crate::image::{check_ratio, load_image, pixel_art_scale, ResampleFilter};
+use crate::lithophane::{
+    Backlight, DepthCompensation, GenerationReport, LithophaneConfig, NamedLayer,
+    PixelCreationMethod as LithoPixelMethod, RegionMask,
+};
 use crate::palette::{
+    ColorLocks, CombinationReport, GamutMappingMethod, MixingModel, Palette, PaletteCache,
     PaletteColorEntry, PaletteLoader, PaletteLoaderConfig,
-    PixelCreationMethod as PalettePixelMethod,
+    PixelCreationMethod as PalettePixelMethod, PosterizeMethod, StackingStrategy,
+    DEFAULT_LOCK_TOLERANCE,
 };
-use crate::stl::{export_to_3mf, export_to_dir, export_to_zip, StlFormat};
+use crate::stl::{export_to_3mf_with_properties, export_to_dir, export_to_zip, StlFormat};
 use clap::{Parser, ValueEnum};
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -32,6 +38,7 @@ impl From<CliStlFormat> for StlFormat {
 pub enum CliColorDistance {
     Rgb,
     CieLab,
+    Luminance,
 }
 
 impl From<CliColorDistance> for ColorDistanceMethod {
@@ -39,6 +46,111 @@ impl From<CliColorDistance> for ColorDistanceMethod {
         match method {
             CliColorDistance::Rgb => ColorDistanceMethod::Rgb,
             CliColorDistance::CieLab => ColorDistanceMethod::CieLab,
+            CliColorDistance::Luminance => ColorDistanceMethod::Luminance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CliGamutMethod {
+    Lightness,
+    Chroma,
+}
+
+impl From<CliGamutMethod> for GamutMappingMethod {
+    fn from(method: CliGamutMethod) -> Self {
+        match method {
+            CliGamutMethod::Lightness => GamutMappingMethod::Lightness,
+            CliGamutMethod::Chroma => GamutMappingMethod::Chroma,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CliPosterizeMethod {
+    Kmeans,
+    MedianCut,
+}
+
+impl From<CliPosterizeMethod> for PosterizeMethod {
+    fn from(method: CliPosterizeMethod) -> Self {
+        match method {
+            CliPosterizeMethod::Kmeans => PosterizeMethod::KMeans,
+            CliPosterizeMethod::MedianCut => PosterizeMethod::MedianCut,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CliResampleFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<CliResampleFilter> for ResampleFilter {
+    fn from(filter: CliResampleFilter) -> Self {
+        match filter {
+            CliResampleFilter::Nearest => ResampleFilter::Nearest,
+            CliResampleFilter::Triangle => ResampleFilter::Triangle,
+            CliResampleFilter::CatmullRom => ResampleFilter::CatmullRom,
+            CliResampleFilter::Gaussian => ResampleFilter::Gaussian,
+            CliResampleFilter::Lanczos3 => ResampleFilter::Lanczos3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CliStacking {
+    Classic,
+    DiffuserTop,
+    DiffuserBottom,
+    DarkFirst,
+    LightFirst,
+    AsMeasured,
+}
+
+impl From<CliStacking> for StackingStrategy {
+    fn from(strategy: CliStacking) -> Self {
+        match strategy {
+            CliStacking::Classic => StackingStrategy::Classic,
+            CliStacking::DiffuserTop => StackingStrategy::DiffuserTop,
+            CliStacking::DiffuserBottom => StackingStrategy::DiffuserBottom,
+            CliStacking::DarkFirst => StackingStrategy::DarkFirst,
+            CliStacking::LightFirst => StackingStrategy::LightFirst,
+            CliStacking::AsMeasured => StackingStrategy::AsMeasured,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CliMixingModel {
+    Additive,
+    OrderAware,
+}
+
+impl From<CliMixingModel> for MixingModel {
+    fn from(model: CliMixingModel) -> Self {
+        match model {
+            CliMixingModel::Additive => MixingModel::Additive,
+            CliMixingModel::OrderAware => MixingModel::OrderAware,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CliDepthCompensation {
+    Texture,
+    Plate,
+}
+
+impl From<CliDepthCompensation> for DepthCompensation {
+    fn from(compensation: CliDepthCompensation) -> Self {
+        match compensation {
+            CliDepthCompensation::Texture => DepthCompensation::Texture,
+            CliDepthCompensation::Plate => DepthCompensation::Plate,
         }
     }
 }
@@ -47,6 +159,7 @@ impl From<CliColorDistance> for ColorDistanceMethod {
 pub enum CliPixelMethod {
     Additive,
     Full,
+    Height,
 }
 
 impl From<CliPixelMethod> for LithoPixelMethod {
@@ -54,6 +167,7 @@ impl From<CliPixelMethod> for LithoPixelMethod {
         match method {
             CliPixelMethod::Additive => LithoPixelMethod::Additive,
             CliPixelMethod::Full => LithoPixelMethod::Full,
+            CliPixelMethod::Height => LithoPixelMethod::Height,
         }
     }
 }
@@ -63,6 +177,7 @@ impl From<CliPixelMethod> for PalettePixelMethod {
         match method {
             CliPixelMethod::Additive => PalettePixelMethod::Additive,
             CliPixelMethod::Full => PalettePixelMethod::Full,
+            CliPixelMethod::Height => PalettePixelMethod::Height,
         }
     }
 }
@@ -151,22 +266,217 @@ pub struct Cli {
     #[arg(long)]
     pub no_texture: bool,
 
+    /// Alpha value (0-255) from which a pixel counts as opaque. 255 keeps only fully
+    /// opaque pixels; lower values keep anti-aliased edges.
+    #[arg(long, default_value = "255", value_name = "ALPHA")]
+    pub alpha_threshold: u8,
+
+    /// Edge handling of the color area in pixels: negative erodes (drops pixels next to
+    /// transparency), positive dilates, 0 keeps the edge
+    #[arg(
+        long,
+        default_value = "-1",
+        allow_hyphen_values = true,
+        value_name = "PIXELS"
+    )]
+    pub color_mask_radius: i32,
+
+    /// Edge handling of the texture area in pixels (like --color-mask-radius);
+    /// transparent texture pixels get no thickness
+    #[arg(
+        long,
+        default_value = "0",
+        allow_hyphen_values = true,
+        value_name = "PIXELS"
+    )]
+    pub texture_mask_radius: i32,
+
+    /// Generate the texture layer in modes that disable it by default (--pixel-art)
+    #[arg(long, conflicts_with = "no_texture")]
+    pub texture: bool,
+
     /// STL output format: ascii (human-readable) or binary (smaller, faster)
     #[arg(long, value_enum, default_value = "ascii")]
     pub format: CliStlFormat,
 
-    /// Color matching algorithm: cie-lab (perceptually uniform, recommended) or rgb (faster)
+    /// Color matching algorithm: cie-lab (perceptually uniform, recommended), rgb (faster)
+    /// or luminance (lightness only)
     #[arg(long, value_enum, default_value = "cie-lab")]
     pub color_distance: CliColorDistance,
 
-    /// Pixel color method: additive (stack layers for more colors) or full (one filament per pixel)
+    /// Gamut mapping strength before quantization (0 = off, 1 = full).
+    /// Compresses out-of-gamut image colors into the palette's achievable colors.
+    #[arg(long, default_value = "0", value_name = "STRENGTH")]
+    pub gamut_mapping: f64,
+
+    /// Gamut mapping method: lightness (keep lightness, reduce chroma) or chroma (keep chroma)
+    #[arg(long, value_enum, default_value = "lightness")]
+    pub gamut_method: CliGamutMethod,
+
+    /// Poster style: reduce the image to this many color clusters before palette
+    /// matching and use only the closest stack per cluster (0 = off)
+    #[arg(long, default_value = "0", value_name = "K")]
+    pub posterize: usize,
+
+    /// Clustering for --posterize: kmeans (median-cut seeded k-means) or median-cut
+    #[arg(long, value_enum, default_value = "kmeans")]
+    pub posterize_method: CliPosterizeMethod,
+
+    /// JSON file locking brand colors to a specific stack or to the nearest palette
+    /// color within a maximum Delta E (applied before the general color matching)
+    #[arg(long, value_name = "FILE")]
+    pub color_locks: Option<PathBuf>,
+
+    /// Delta E within which image pixels match a locked color (per lock: "tolerance")
+    #[arg(long, value_name = "DELTA_E", default_value_t = DEFAULT_LOCK_TOLERANCE)]
+    pub color_lock_tolerance: f64,
+
+    /// Region mask image (same aspect ratio as the input): white = color + texture,
+    /// grey = texture only, red = color only, black or transparent = excluded
+    #[arg(long, value_name = "FILE")]
+    pub region_mask: Option<PathBuf>,
+
+    /// Pixel color method: additive (stack layers for more colors), full (one filament per pixel)
+    /// or height (one relief with filament swaps at fixed heights, HueForge-style)
     #[arg(long, value_enum, default_value = "additive")]
     pub pixel_method: CliPixelMethod,
 
+    /// Grayscale lithophane: stack only the neutral filaments (white, greys, black),
+    /// match on lightness only and keep the front flat (no texture layer)
+    #[arg(long)]
+    pub monochrome: bool,
+
+    /// Image resampling filter: lanczos3 (sharpest), catmull-rom, triangle, gaussian (soft)
+    /// or nearest. Resampling is done in linear light with premultiplied alpha.
+    #[arg(long, value_enum, default_value = "lanczos3")]
+    pub resample_filter: CliResampleFilter,
+
+    /// Average the covered source area when shrinking the image by 2x or more
+    /// (less aliasing for large photos)
+    #[arg(long)]
+    pub area_downscale: bool,
+
+    /// Pixel art (sprites, logos): scale by whole pixels without interpolation, so each
+    /// source pixel becomes exactly NxN color pixels. Disables the texture layer unless
+    /// --texture is given.
+    #[arg(long)]
+    pub pixel_art: bool,
+
+    /// Map image colors that equal a palette color or a filament hex code directly,
+    /// without a Delta E search
+    #[arg(long)]
+    pub exact_colors: bool,
+
     /// Maximum number of filament colors per AMS group (0 = use all). Set to 4 for single AMS.
     #[arg(long, default_value = "0", value_name = "N")]
     pub color_number: usize,
 
+    /// Drop color combinations closer than this Delta E to an already generated one
+    /// (0 = keep all). Speeds up large palettes at a small loss of colors.
+    #[arg(long, default_value = "0", value_name = "DELTA_E")]
+    pub prune_delta_e: f64,
+
+    /// Hard limit for the number of generated color combinations (0 = no limit).
+    /// When it is reached, the first combinations in generation order are kept.
+    #[arg(long, default_value = "0", value_name = "N")]
+    pub max_combinations: usize,
+
+    /// Merge color combinations closer than this Delta E into the stack with the
+    /// fewest filaments and tool changes (0 = off)
+    #[arg(long, default_value = "0", value_name = "DELTA_E")]
+    pub merge_delta_e: f64,
+
+    /// Maximum number of distinct filaments in one color pixel stack (0 = no limit)
+    #[arg(long, default_value = "0", value_name = "N")]
+    pub max_filaments_per_stack: usize,
+
+    /// Minimum number of consecutive layers per filament in a color pixel stack (0 = no limit)
+    #[arg(long, default_value = "0", value_name = "N")]
+    pub min_layers_per_filament: u32,
+
+    /// Order of the filament layers inside each color stack, from plate to viewing side
+    #[arg(long, value_enum, default_value = "classic")]
+    pub stacking: CliStacking,
+
+    /// Color prediction of a stack: additive (order-independent) or order-aware
+    /// (layers covered by other layers lose saturation)
+    #[arg(long, value_enum, default_value = "additive")]
+    pub mixing_model: CliMixingModel,
+
+    /// Allow color stacks with fewer layers than --color-layers, down to this many
+    /// (0 = off). Saves base filament layers on bright images.
+    #[arg(long, default_value = "0", value_name = "N")]
+    pub min_stack_layers: u32,
+
+    /// Where the missing height of shorter stacks goes: texture (thicker texture
+    /// above the pixel) or plate (filled under the stack)
+    #[arg(long, value_enum, default_value = "texture")]
+    pub depth_compensation: CliDepthCompensation,
+
+    /// Reduce the texture thickness above each color stack by the stack's predicted
+    /// light attenuation, so dark stacks are not darkened further by the texture
+    #[arg(long)]
+    pub compensate_texture: bool,
+
+    /// Backlight intensity map image (bright = strong light), stretched over the
+    /// print; the texture is thinned where the light is weak
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["backlight_falloff", "led_spacing"])]
+    pub backlight_map: Option<PathBuf>,
+
+    /// Radial backlight falloff: share of the light missing in the corners (0-1)
+    #[arg(long, value_name = "FRACTION", conflicts_with = "led_spacing")]
+    pub backlight_falloff: Option<f64>,
+
+    /// LED grid spacing of the light box in mm (compensates the hot spots above the LEDs)
+    #[arg(long, value_name = "MM")]
+    pub led_spacing: Option<f64>,
+
+    /// Distance between the LEDs and the print in mm (with --led-spacing)
+    #[arg(long, default_value = "10", value_name = "MM")]
+    pub led_distance: f64,
+
+    /// Price per kg for the cost estimate of filaments without a "price_per_kg"
+    /// in the palette
+    #[arg(long, value_name = "PRICE")]
+    pub price_per_kg: Option<f64>,
+
+    /// Write a heatmap of the per-pixel color error (Delta E) to a PNG file
+    #[arg(long, value_name = "FILE.png")]
+    pub accuracy_heatmap: Option<PathBuf>,
+
+    /// Write a histogram of the color stack usage to a PNG file
+    #[arg(long, value_name = "FILE.png")]
+    pub usage_histogram: Option<PathBuf>,
+
+    /// Render the backlit appearance of the finished print to a PNG file
+    /// (predicted stack colors, texture and plate at the printed resolution)
+    #[arg(long, value_name = "FILE.png")]
+    pub simulate: Option<PathBuf>,
+
+    /// Reorder the layers of the used color stacks so each print layer needs fewer
+    /// filament changes (the colors stay the same)
+    #[arg(long)]
+    pub optimize_tool_changes: bool,
+
+    /// Replace each color pixel with the most frequent color within this radius
+    /// (removes single-pixel speckles, 0 = off)
+    #[arg(long, default_value = "0", value_name = "RADIUS")]
+    pub mode_filter: u32,
+
+    /// Merge areas of one color smaller than this many pixels into the closest
+    /// neighbouring color (0 = off)
+    #[arg(long, default_value = "0", value_name = "PIXELS")]
+    pub min_island_size: usize,
+
+    /// Stained-glass / comic look: dark outline of this many pixels between color
+    /// areas, printed over the full stack height (0 = off)
+    #[arg(long, default_value = "0", value_name = "PIXELS")]
+    pub outline: u32,
+
+    /// Filament hex code of the outline layer
+    #[arg(long, default_value = "#000000", value_name = "HEX")]
+    pub outline_color: String,
+
     /// Curve angle in degrees (0=flat, 90=quarter cylinder, 180=half, 360=full cylinder)
     #[arg(short = 'C', long, default_value = "0")]
     pub curve: f64,
@@ -175,12 +485,66 @@ pub struct Cli {
     #[arg(long)]
     pub calibrate: bool,
 
+    /// Cache computed color combinations and color matches on disk.
+    /// Repeated runs with the same palette and settings start instantly.
+    #[arg(long)]
+    pub cache: bool,
+
+    /// Directory for the palette cache (implies --cache). Default: user cache directory.
+    #[arg(long, value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+
     /// Print extra diagnostic output during generation
     #[arg(long)]
     pub debug: bool,
 }
 
 impl Cli {
+    pub fn to_palette_loader_config(&self) -> PaletteLoaderConfig {
+        PaletteLoaderConfig {
+            nb_layers: self.color_layers,
+            creation_method: self.pixel_method.into(),
+            color_number: self.color_number,
+            distance_method: self.distance_method(),
+            prune_delta_e: self.prune_delta_e,
+            max_combinations: self.max_combinations,
+            merge_delta_e: self.merge_delta_e,
+            max_filaments_per_stack: self.max_filaments_per_stack,
+            min_layers_per_filament: self.min_layers_per_filament,
+            stacking_strategy: self.stacking.into(),
+            mixing_model: self.mixing_model.into(),
+            min_stack_layers: self.min_stack_layers,
+            monochrome: self.monochrome,
+        }
+    }
+
+    /// Gets the color distance method (lightness only in monochrome mode)
+    fn distance_method(&self) -> ColorDistanceMethod {
+        if self.monochrome {
+            ColorDistanceMethod::Luminance
+        } else {
+            self.color_distance.into()
+        }
+    }
+
+    /// Gets the palette cache if enabled via --cache or --cache-dir
+    fn palette_cache(&self) -> Option<PaletteCache> {
+        match &self.cache_dir {
+            Some(dir) => Some(PaletteCache::new(dir)),
+            None if self.cache => Some(PaletteCache::new(PaletteCache::default_dir())),
+            None => None,
+        }
+    }
+
+    /// Loads the palette, through the cache if enabled
+    fn load_palette(&self) -> Result<Palette> {
+        let config = self.to_palette_loader_config();
+        match self.palette_cache() {
+            Some(cache) => PaletteLoader::load_cached(&self.palette, config, &cache),
+            None => PaletteLoader::load(&self.palette, config),
+        }
+    }
+
     pub fn to_lithophane_config(&self) -> LithophaneConfig {
         LithophaneConfig {
             dest_width_mm: self.width,
@@ -192,12 +556,44 @@ impl Cli {
             texture_pixel_width: self.texture_pixel_width,
             texture_min_thickness: self.texture_min,
             texture_max_thickness: self.texture_max,
-            texture_layer: !self.no_texture,
+            texture_layer: !self.no_texture
+                && !self.monochrome
+                && (!self.pixel_art || self.texture),
             texture_color: self.texture_color.clone(),
             plate_thickness: self.plate_thickness,
             pixel_creation_method: self.pixel_method.into(),
             color_number: self.color_number,
-            color_distance_method: self.color_distance.into(),
+            color_distance_method: self.distance_method(),
+            gamut_mapping_strength: self.gamut_mapping,
+            gamut_mapping_method: self.gamut_method.into(),
+            posterize_colors: self.posterize,
+            posterize_method: self.posterize_method.into(),
+            color_locks: ColorLocks::default(),
+            region_mask: None,
+            optimize_tool_changes: self.optimize_tool_changes,
+            depth_compensation: self.depth_compensation.into(),
+            texture_attenuation_compensation: self.compensate_texture,
+            backlight: self
+                .backlight_falloff
+                .map(|falloff| Backlight::Radial { falloff })
+                .or_else(|| {
+                    self.led_spacing.map(|spacing_mm| Backlight::LedGrid {
+                        spacing_mm,
+                        distance_mm: self.led_distance,
+                    })
+                }),
+            simulate: self.simulate.is_some(),
+            resample_filter: self.resample_filter.into(),
+            area_downscale: self.area_downscale,
+            mode_filter_radius: self.mode_filter,
+            min_island_size: self.min_island_size,
+            outline_width: self.outline,
+            outline_hex: self.outline_color.to_uppercase(),
+            alpha_threshold: self.alpha_threshold,
+            color_mask_radius: self.color_mask_radius,
+            texture_mask_radius: self.texture_mask_radius,
+            pixel_art: self.pixel_art,
+            exact_color_match: self.exact_colors,
             curve: self.curve,
             debug: self.debug,
             low_memory: false,
@@ -227,49 +623,238 @@ impl Cli {
         let raw_palette = PaletteLoader::load_raw(&self.palette)?;
         self.print_palette_warnings(&raw_palette);
 
-        let palette_config = PaletteLoaderConfig {
-            nb_layers: self.color_layers,
-            creation_method: self.pixel_method.into(),
-            color_number: self.color_number,
-            distance_method: self.color_distance.into(),
-        };
-        let palette = PaletteLoader::load(&self.palette, palette_config)?;
+        let palette = self.load_palette()?;
         println!("  Colors found: {}", palette.colors().len());
+        if let Some(report) = palette.combination_report() {
+            Self::print_combination_report(report);
+        }
         println!("  Color groups: {}\n", palette.hex_color_groups().len());
 
         // --- Load image and check resolution ---
         println!("Loading image: {}", input.display());
         let image = load_image(input)?;
         println!("  Image size: {}x{} pixels", image.width(), image.height());
-        self.print_resolution_warning(image.width(), image.height());
+        if self.pixel_art {
+            self.print_pixel_art_size(image.width(), image.height());
+        } else {
+            self.print_resolution_warning(image.width(), image.height());
+        }
         println!();
 
         // --- Generate lithophane ---
         println!("Generating lithophane layers...");
-        let config = self.to_lithophane_config();
+        let mut config = self.to_lithophane_config();
+        self.load_file_options(&mut config, image.width(), image.height())?;
         if config.curve > 0.0 {
             println!("  Curve: {:.0} degrees", config.curve);
         }
         let generator = crate::lithophane::LithophaneGenerator::new(config)?;
-        let layers = generator.generate(&image, &palette)?;
-        println!("  Generated {} layer(s)", layers.len());
-        for layer in &layers {
-            println!(
-                "    - {}: {} triangles",
-                layer.name,
-                layer.mesh.triangle_count()
-            );
-        }
+        let (layers, report) = generator.generate_with_report(&image, &palette)?;
+        self.update_palette_cache(&palette);
+        self.print_generation_report(&report)?;
+
+        let properties = self.filament_properties(&palette, &layers)?;
+        let mapping = FilamentMapping::from_layers_with_properties(&layers, &properties);
+        Self::print_filament_usage(&layers, &mapping);
         println!();
 
         // --- Export ---
         println!("Exporting to: {}", output.display());
-        self.export_layers(&layers, output)?;
+        self.export_layers(&layers, output, &properties)?;
 
         println!("Done!");
         Ok(())
     }
 
+    /// Prints the statistics of the combination generation
+    fn print_combination_report(report: &CombinationReport) {
+        if report.pruned > 0 || report.truncated {
+            println!("  Combinations: {report}");
+        }
+        if report.merged > 0 {
+            println!(
+                "  Merged {} near-identical combinations into cheaper stacks",
+                report.merged
+            );
+        }
+        if report.truncated {
+            eprintln!(
+                "  Warning: combination limit of {} reached, the palette is incomplete. \
+                Raise --max-combinations or use --prune-delta-e.",
+                report.cap
+            );
+        }
+    }
+
+    /// Prints the size of a pixel-art print
+    fn print_pixel_art_size(&self, image_width: u32, image_height: u32) {
+        let scale = pixel_art_scale(
+            image_width,
+            image_height,
+            self.width,
+            self.height,
+            self.color_pixel_width,
+        );
+        let pixel_mm = f64::from(scale) * self.color_pixel_width;
+        println!(
+            "  Pixel art: {scale}x{scale} color pixels per source pixel ({:.1} x {:.1} mm)",
+            f64::from(image_width) * pixel_mm,
+            f64::from(image_height) * pixel_mm
+        );
+    }
+
+    /// Loads the options given as files (color locks, backlight map, region mask)
+    fn load_file_options(
+        &self,
+        config: &mut LithophaneConfig,
+        image_width: u32,
+        image_height: u32,
+    ) -> Result<()> {
+        if let Some(path) = &self.color_locks {
+            if self.color_lock_tolerance < 0.0 {
+                return Err(crate::error::PixestlError::Config(format!(
+                    "--color-lock-tolerance must not be negative, got {}",
+                    self.color_lock_tolerance
+                )));
+            }
+            config.color_locks = ColorLocks::load(path)?.with_tolerance(self.color_lock_tolerance);
+        }
+        if let Some(path) = &self.backlight_map {
+            config.backlight = Some(Backlight::load_map(path)?);
+        }
+        if let Some(path) = &self.region_mask {
+            let mask = RegionMask::load(path)?;
+            let (mask_width, mask_height) = mask.dimensions();
+            if check_ratio(
+                image_width,
+                image_height,
+                f64::from(mask_width),
+                f64::from(mask_height),
+            )
+            .is_some()
+            {
+                println!(
+                    "  Warning: The region mask ratio ({:.2}) differs from the image ratio ({:.2}); the mask is stretched.",
+                    f64::from(mask_width) / f64::from(mask_height),
+                    f64::from(image_width) / f64::from(image_height)
+                );
+            }
+            config.region_mask = Some(mask);
+        }
+        Ok(())
+    }
+
+    /// Prints the generation statistics and saves the requested report images
+    fn print_generation_report(&self, report: &GenerationReport) -> Result<()> {
+        if let Some(tool_changes) = &report.tool_changes {
+            println!(
+                "  Tool changes (estimated): {} -> {} after stack reordering",
+                tool_changes.before, tool_changes.after
+            );
+        }
+        if !report.locked_colors.is_empty() {
+            println!("  Locked colors:");
+            for locked in &report.locked_colors {
+                println!(
+                    "    - {} -> {} (Delta E {:.1}): {}, {} pixel(s)",
+                    locked.source.to_hex(),
+                    locked.color.to_hex(),
+                    locked.delta_e,
+                    locked.stack,
+                    locked.pixels
+                );
+            }
+        }
+        if let Some(stacks) = report.posterized_stacks {
+            println!(
+                "  Posterize: {} cluster(s) -> {} stack(s)",
+                self.posterize, stacks
+            );
+        }
+        if self.mode_filter > 0 || self.min_island_size > 0 {
+            println!(
+                "  Cleanup: {} color pixel(s) changed",
+                report.cleaned_pixels
+            );
+        }
+        if let Some(accuracy) = &report.accuracy {
+            if let Some(summary) = accuracy.summary() {
+                println!(
+                    "  Color accuracy (Delta E): mean {:.1}, median {:.1}, 95th percentile {:.1}, max {:.1} ({} stack(s) used)",
+                    summary.mean,
+                    summary.median,
+                    summary.p95,
+                    summary.max,
+                    accuracy.stats.colors_used
+                );
+            }
+            if let Some(path) = &self.accuracy_heatmap {
+                accuracy.heatmap.save(path)?;
+                println!("  Accuracy heatmap: {}", path.display());
+            }
+            if let Some(path) = &self.usage_histogram {
+                accuracy.usage_histogram().save(path)?;
+                println!("  Usage histogram: {}", path.display());
+            }
+        } else if self.accuracy_heatmap.is_some() || self.usage_histogram.is_some() {
+            println!("  Color accuracy: not available without a color layer");
+        }
+        if let Some(path) = &self.simulate {
+            match &report.simulation {
+                Some(simulation) => {
+                    simulation.save(path)?;
+                    println!("  Simulation: {}", path.display());
+                }
+                None => println!("  Simulation: not supported by the height pixel method"),
+            }
+        }
+        if let Some(schedule) = &report.swap_schedule {
+            println!(
+                "  Filament swaps ({:.2} mm layers, mean Delta E {:.1}):",
+                schedule.layer_thickness, schedule.mean_delta_e
+            );
+            for swap in &schedule.swaps {
+                println!(
+                    "    - layer {:>3} (Z {:.2} mm): {} ({})",
+                    swap.start_layer + 1,
+                    swap.z_mm,
+                    swap.name,
+                    swap.hex_code
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the generated layers and the estimated filament usage
+    fn print_filament_usage(layers: &[NamedLayer], mapping: &FilamentMapping) {
+        println!("  Generated {} layer(s)", layers.len());
+        for (layer, usage) in layers.iter().zip(mapping.layer_usage()) {
+            println!(
+                "    - {}: {} triangles, {:.2} g",
+                layer.name,
+                layer.mesh.triangle_count(),
+                usage.grams
+            );
+        }
+        let filament_usage = mapping.filament_usage();
+        let plate_usage = mapping.plate_usage();
+        if !filament_usage.is_empty() || plate_usage.is_some() {
+            println!("  Filament usage (estimated):");
+            for (hex, usage) in filament_usage {
+                println!("    - {hex}: {}", format_usage(usage));
+            }
+            if let Some(usage) = &plate_usage {
+                println!("    - plate (extruder 1): {}", format_usage(usage));
+            }
+            let total = filament_usage
+                .iter()
+                .map(|(_, usage)| usage)
+                .chain(&plate_usage);
+            println!("    Total: {}", format_usage(&FilamentUsage::total(total)));
+        }
+    }
+
     /// Generates calibration test pattern and exports it.
     fn run_calibrate(&self) -> Result<()> {
         let output = self.output.as_ref().ok_or_else(|| {
@@ -324,7 +909,7 @@ impl Cli {
 
         // Export
         println!("Exportiere nach: {}", output.display());
-        self.export_layers(&layers, output)?;
+        self.export_layers(&layers, output, &HashMap::new())?;
 
         println!("Fertig!");
         println!();
@@ -357,7 +942,30 @@ impl Cli {
 
         println!("Datei:          {}", self.palette.display());
         println!("Farbschichten:  {}", self.color_layers);
-        println!("Methode:        {:?}\n", self.pixel_method);
+        println!("Methode:        {:?}", self.pixel_method);
+        if self.monochrome {
+            println!("Monochrom:      nur neutrale Filamente, Abgleich nach Helligkeit");
+        }
+        println!(
+            "Max. Filamente pro Stapel:    {}",
+            limit_text(self.max_filaments_per_stack)
+        );
+        println!(
+            "Min. Schichten pro Filament:  {}",
+            limit_text(self.min_layers_per_filament as usize)
+        );
+        if self.min_stack_layers > 0 {
+            println!(
+                "Variable Stapeltiefe:         {}-{} Schichten (Ausgleich: {:?})",
+                self.min_stack_layers, self.color_layers, self.depth_compensation
+            );
+        }
+        println!();
+        println!(
+            "Stapelstrategie:              {} ({})",
+            StackingStrategy::from(self.stacking).as_str(),
+            MixingModel::from(self.mixing_model).as_str()
+        );
 
         // Load raw palette data for display
         let raw_data = PaletteLoader::load_raw(&self.palette)?;
@@ -390,18 +998,33 @@ impl Cli {
         }
 
         // Try to load full palette for combination count and AMS info
-        let palette_config = PaletteLoaderConfig {
-            nb_layers: self.color_layers,
-            creation_method: self.pixel_method.into(),
-            color_number: self.color_number,
-            distance_method: self.color_distance.into(),
-        };
-
         println!();
-        match PaletteLoader::load(&self.palette, palette_config) {
+        match self.load_palette() {
             Ok(palette) => {
                 println!("Farbkombinationen: {}", palette.color_count());
+                if let Some(report) = palette.combination_report() {
+                    println!(
+                        "  erzeugt: {}, verworfen (nahezu gleich): {}",
+                        report.generated, report.pruned
+                    );
+                    if report.merged > 0 {
+                        println!("  zusammengefasst (guenstigerer Stapel): {}", report.merged);
+                    }
+                    if report.truncated {
+                        println!(
+                            "  Obergrenze von {} Kombinationen erreicht - Palette unvollstaendig!",
+                            report.cap
+                        );
+                    }
+                }
                 println!("AMS-Gruppen:       {}", palette.nb_groups());
+                println!(
+                    "Basis-Filament:    {} ({})",
+                    palette
+                        .get_color_name(palette.base_hex())
+                        .unwrap_or("nicht vorhanden"),
+                    palette.base_hex()
+                );
 
                 // Show AMS group assignment if more than 1 group
                 let groups = palette.hex_color_groups();
@@ -475,6 +1098,19 @@ impl Cli {
         );
     }
 
+    /// Writes color matches learned during generation back to the palette cache.
+    /// Failures only produce a warning; the generated output is unaffected.
+    fn update_palette_cache(&self, palette: &Palette) {
+        let Some(cache) = self.palette_cache() else {
+            return;
+        };
+        let result = PaletteCache::key_for_file(&self.palette, &self.to_palette_loader_config())
+            .and_then(|key| cache.store(&key, palette));
+        if let Err(e) = result {
+            eprintln!("Warning: could not update palette cache: {e}");
+        }
+    }
+
     /// Prints palette validation warnings to stderr.
     fn print_palette_warnings(&self, raw_data: &HashMap<String, PaletteColorEntry>) {
         let warnings = PaletteLoader::validate_completeness(raw_data, self.color_layers);
@@ -492,13 +1128,44 @@ impl Cli {
         eprintln!();
     }
 
+    /// Collects the material properties of the filaments used by the layers.
+    ///
+    /// Filaments missing from the palette get the defaults; `--price-per-kg`
+    /// fills in the price of filaments without one.
+    fn filament_properties(
+        &self,
+        palette: &Palette,
+        layers: &[NamedLayer],
+    ) -> Result<HashMap<String, FilamentProperties>> {
+        if let Some(price) = self.price_per_kg.filter(|price| *price < 0.0) {
+            return Err(crate::error::PixestlError::Config(format!(
+                "--price-per-kg must not be negative, got {price}"
+            )));
+        }
+        let mut properties = palette.filament_properties().clone();
+        for hex in layers.iter().filter_map(|layer| layer.hex_color.as_ref()) {
+            properties.entry(hex.clone()).or_default();
+        }
+        if let Some(price) = self.price_per_kg {
+            for filament in properties.values_mut() {
+                filament.price_per_kg.get_or_insert(price);
+            }
+        }
+        Ok(properties)
+    }
+
     /// Exports layers to the given output path.
     ///
     /// The output format is determined by the file extension:
     /// - `.zip`  → ZIP archive with one STL file per layer
     /// - `.3mf`  → 3MF file with embedded filament colors (recommended for Bambu Studio)
     /// - other   → directory with one STL file per layer
-    fn export_layers(&self, layers: &[NamedLayer], output: &std::path::Path) -> Result<()> {
+    fn export_layers(
+        &self,
+        layers: &[NamedLayer],
+        output: &std::path::Path,
+        properties: &HashMap<String, FilamentProperties>,
+    ) -> Result<()> {
         match output.extension().and_then(|e| e.to_str()) {
             Some("zip") => {
                 println!("  Format: ZIP ({:?})", self.format);
@@ -506,7 +1173,7 @@ impl Cli {
             }
             Some("3mf") => {
                 println!("  Format: 3MF (mit Farbmetadaten)");
-                export_to_3mf(layers, output, self.format.into())
+                export_to_3mf_with_properties(layers, output, self.format.into(), properties)
             }
             _ => {
                 println!("  Format: Verzeichnis ({:?})", self.format);
@@ -570,3 +1237,23 @@ impl Cli {
         }
     }
 }
+
+/// Formats an optional limit for display (0 = no limit)
+fn limit_text(limit: usize) -> String {
+    if limit == 0 {
+        "unbegrenzt".to_string()
+    } else {
+        limit.to_string()
+    }
+}
+
+/// Formats a filament usage for display (cost only with a known price)
+fn format_usage(usage: &FilamentUsage) -> String {
+    match usage.cost {
+        Some(cost) => format!(
+            "{:.2} g, {:.2} m, cost {:.2}",
+            usage.grams, usage.meters, cost
+        ),
+        None => format!("{:.2} g, {:.2} m", usage.grams, usage.meters),
+    }
+}
@@ -113,6 +113,24 @@ impl CieLab {
 
         (dl * dl + da * da + db * db).sqrt()
     }
+
+    /// Converts this CIELab color back to RGB
+    ///
+    /// Conversion pipeline: CIELab → XYZ (D65) → RGB. Colors outside the sRGB
+    /// gamut are clamped per channel.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pixestl::color::{CieLab, Rgb};
+    ///
+    /// let orange = Rgb::new(255, 128, 0);
+    /// assert_eq!(CieLab::from(orange).to_rgb(), orange);
+    /// ```
+    #[must_use]
+    pub fn to_rgb(&self) -> Rgb {
+        xyz_to_rgb(lab_to_xyz(*self))
+    }
 }
 
 impl From<Rgb> for CieLab {
@@ -216,6 +234,58 @@ fn pivot_xyz_to_lab(n: f64) -> f64 {
     }
 }
 
+/// Converts CIELab to XYZ color space (inverse of `xyz_to_lab`)
+#[allow(clippy::many_single_char_names)]
+fn lab_to_xyz(lab: CieLab) -> Xyz {
+    let fy = (lab.l + 16.0) / 116.0;
+    let fx = fy + lab.a / 500.0;
+    let fz = fy - lab.b / 200.0;
+
+    Xyz {
+        x: pivot_lab_to_xyz(fx) * D65_X,
+        y: pivot_lab_to_xyz(fy) * D65_Y,
+        z: pivot_lab_to_xyz(fz) * D65_Z,
+    }
+}
+
+/// Inverse Lab transformation function for CIELab → XYZ conversion
+fn pivot_lab_to_xyz(f: f64) -> f64 {
+    let cube = f * f * f;
+    if cube > LAB_EPSILON {
+        cube
+    } else {
+        (f - 4.0 / 29.0) / LAB_KAPPA
+    }
+}
+
+/// Converts XYZ (D65) to RGB (inverse of `rgb_to_xyz`)
+#[allow(clippy::many_single_char_names)]
+fn xyz_to_rgb(xyz: Xyz) -> Rgb {
+    let x = xyz.x / 100.0;
+    let y = xyz.y / 100.0;
+    let z = xyz.z / 100.0;
+
+    let r_linear = x * 3.240_454_2 + y * -1.537_138_5 + z * -0.498_531_4;
+    let g_linear = x * -0.969_266_0 + y * 1.876_010_8 + z * 0.041_556_0;
+    let b_linear = x * 0.055_643_4 + y * -0.204_025_9 + z * 1.057_225_2;
+
+    Rgb::from_f64(
+        pivot_xyz_to_rgb(r_linear),
+        pivot_xyz_to_rgb(g_linear),
+        pivot_xyz_to_rgb(b_linear),
+    )
+}
+
+/// Gamma companding for XYZ → RGB conversion (linear RGB → sRGB)
+fn pivot_xyz_to_rgb(n: f64) -> f64 {
+    let n = n.clamp(0.0, 1.0);
+    if n > SRGB_THRESHOLD / 12.92 {
+        1.055 * n.powf(1.0 / 2.4) - 0.055
+    } else {
+        n * 12.92
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -338,6 +408,27 @@ mod tests {
         assert!(distance > 99.0);
     }
 
+    #[test]
+    fn test_to_rgb_round_trip() {
+        for rgb in [
+            Rgb::new(0, 0, 0),
+            Rgb::new(255, 255, 255),
+            Rgb::new(255, 0, 0),
+            Rgb::new(0, 128, 255),
+            Rgb::new(17, 200, 93),
+            Rgb::new(128, 128, 128),
+        ] {
+            assert_eq!(CieLab::from(rgb).to_rgb(), rgb);
+        }
+    }
+
+    #[test]
+    fn test_to_rgb_clamps_out_of_gamut() {
+        // Extremely saturated Lab color outside sRGB must still produce a valid color
+        let rgb = CieLab::new(50.0, 150.0, -150.0).to_rgb();
+        assert!(rgb.r > rgb.g);
+    }
+
     #[test]
     fn test_display() {
         let lab = CieLab::new(53.24, 80.09, 67.20);
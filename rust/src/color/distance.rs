@@ -11,6 +11,8 @@ pub enum ColorDistanceMethod {
     /// CIELab Delta E distance (slower but perceptually uniform)
     #[default]
     CieLab,
+    /// CIELab lightness difference only (monochrome lithophanes)
+    Luminance,
 }
 
 impl std::str::FromStr for ColorDistanceMethod {
@@ -20,6 +22,7 @@ impl std::str::FromStr for ColorDistanceMethod {
         match s {
             "RGB" => Ok(Self::Rgb),
             "CIELab" => Ok(Self::CieLab),
+            "Luminance" => Ok(Self::Luminance),
             _ => Err(format!("Invalid color distance method: {s}")),
         }
     }
@@ -32,6 +35,7 @@ impl ColorDistanceMethod {
         match self {
             Self::Rgb => "RGB",
             Self::CieLab => "CIELab",
+            Self::Luminance => "Luminance",
         }
     }
 }
@@ -114,6 +118,10 @@ pub fn find_closest_color(
             let palette_labs: Vec<CieLab> = colors.iter().map(|c| CieLab::from(*c)).collect();
             Ok(find_closest_cielab(target, colors, &palette_labs))
         }
+        ColorDistanceMethod::Luminance => {
+            let palette_labs: Vec<CieLab> = colors.iter().map(|c| CieLab::from(*c)).collect();
+            Ok(find_closest_luminance(target, colors, &palette_labs))
+        }
     }
 }
 
@@ -136,6 +144,7 @@ pub fn find_closest_color_precomputed(
     match method {
         ColorDistanceMethod::Rgb => Ok(find_closest_rgb(target, colors)),
         ColorDistanceMethod::CieLab => Ok(find_closest_cielab(target, colors, palette_labs)),
+        ColorDistanceMethod::Luminance => Ok(find_closest_luminance(target, colors, palette_labs)),
     }
 }
 
@@ -170,6 +179,22 @@ fn find_closest_cielab(target: &Rgb, colors: &[Rgb], palette_labs: &[CieLab]) ->
     closest
 }
 
+fn find_closest_luminance(target: &Rgb, colors: &[Rgb], palette_labs: &[CieLab]) -> Rgb {
+    let target_l = CieLab::from(*target).l;
+    let mut min_distance = f64::MAX;
+    let mut closest = colors[0];
+
+    for (color, color_lab) in colors.iter().zip(palette_labs.iter()) {
+        let distance = (target_l - color_lab.l).abs();
+        if distance < min_distance {
+            min_distance = distance;
+            closest = *color;
+        }
+    }
+
+    closest
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -186,6 +211,10 @@ mod tests {
             ColorDistanceMethod::from_str("CIELab").unwrap(),
             ColorDistanceMethod::CieLab
         );
+        assert_eq!(
+            ColorDistanceMethod::from_str("Luminance").unwrap(),
+            ColorDistanceMethod::Luminance
+        );
         assert!(ColorDistanceMethod::from_str("Invalid").is_err());
     }
 
@@ -193,6 +222,7 @@ mod tests {
     fn test_color_distance_method_as_str() {
         assert_eq!(ColorDistanceMethod::Rgb.as_str(), "RGB");
         assert_eq!(ColorDistanceMethod::CieLab.as_str(), "CIELab");
+        assert_eq!(ColorDistanceMethod::Luminance.as_str(), "Luminance");
     }
 
     #[test]
@@ -274,6 +304,21 @@ mod tests {
         assert_eq!(closest, Rgb::new(255, 0, 0));
     }
 
+    #[test]
+    fn test_find_closest_color_luminance_ignores_hue() {
+        // A saturated red has the lightness of a dark grey
+        let target = Rgb::new(255, 0, 0);
+        let palette = vec![
+            Rgb::new(255, 150, 150),
+            Rgb::new(126, 126, 126),
+            Rgb::new(255, 255, 255),
+        ];
+
+        let closest =
+            find_closest_color(&target, &palette, ColorDistanceMethod::Luminance).unwrap();
+        assert_eq!(closest, Rgb::new(126, 126, 126));
+    }
+
     #[test]
     fn test_find_closest_color_exact_match() {
         let target = Rgb::new(128, 64, 200);
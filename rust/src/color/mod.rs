@@ -18,6 +18,8 @@ pub use distance::{
 pub use hsl::Hsl;
 pub use rgb::Rgb;
 
+use serde::{Deserialize, Serialize};
+
 /// CMYK-Farbdarstellung für Druckfarben (Werte im Bereich 0.0–1.0)
 ///
 /// CMYK steht für Cyan, Magenta, Yellow (Gelb) und Key (Schwarz).
@@ -26,7 +28,7 @@ pub use rgb::Rgb;
 ///
 /// Jeder Kanal liegt im Bereich `[0.0, 1.0]`, wobei `0.0` keinen und `1.0` maximalen
 /// Farbanteil bedeutet.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Cmyk {
     /// Cyan-Anteil (0.0 = kein Cyan, 1.0 = volles Cyan)
     pub c: f64,
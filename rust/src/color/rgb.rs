@@ -91,6 +91,19 @@ impl Rgb {
         )
     }
 
+    /// Converts RGB to linear light values (0.0-1.0, sRGB transfer removed)
+    #[must_use]
+    pub fn to_linear(&self) -> (f64, f64, f64) {
+        let (r, g, b) = self.to_f64();
+        (srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b))
+    }
+
+    /// Creates RGB from linear light values (0.0-1.0, clamped)
+    #[must_use]
+    pub fn from_linear(r: f64, g: f64, b: f64) -> Self {
+        Self::from_f64(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b))
+    }
+
     /// Converts RGB to CMYK color space
     ///
     /// Based on Java ColorUtil.colorToCMYK implementation
@@ -127,6 +140,25 @@ impl Rgb {
     }
 }
 
+/// Removes the sRGB transfer function from a normalized channel value
+fn srgb_to_linear(n: f64) -> f64 {
+    if n > 0.04045 {
+        ((n + 0.055) / 1.055).powf(2.4)
+    } else {
+        n / 12.92
+    }
+}
+
+/// Applies the sRGB transfer function to a linear channel value
+fn linear_to_srgb(n: f64) -> f64 {
+    let n = n.clamp(0.0, 1.0);
+    if n > 0.003_130_8 {
+        1.055 * n.powf(1.0 / 2.4) - 0.055
+    } else {
+        n * 12.92
+    }
+}
+
 impl fmt::Display for Rgb {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "RGB({}, {}, {})", self.r, self.g, self.b)
@@ -191,6 +223,19 @@ mod tests {
         assert_eq!(color.to_hex(), "#ABCDEF");
     }
 
+    #[test]
+    fn test_linear_roundtrip() {
+        let (r, g, b) = Rgb::new(128, 128, 128).to_linear();
+        assert_relative_eq!(r, 0.2158, epsilon = 1e-3);
+        assert_eq!(r, g);
+        assert_eq!(g, b);
+
+        for color in [Rgb::new(0, 0, 0), Rgb::new(12, 200, 255), Rgb::new(3, 4, 5)] {
+            let (r, g, b) = color.to_linear();
+            assert_eq!(Rgb::from_linear(r, g, b), color);
+        }
+    }
+
     #[test]
     fn test_hex_roundtrip() {
         let original = Rgb::new(123, 45, 67);
@@ -53,6 +53,10 @@ pub enum PixestlError {
     #[error("Required color {0} missing from palette")]
     MissingColor(String),
 
+    /// Locked color mapping cannot be met with the palette
+    #[error("Color lock cannot be met: {0}")]
+    ColorLock(String),
+
     /// Invalid palette configuration
     #[error("Invalid palette: {0}")]
     InvalidPalette(String),
@@ -6,19 +6,37 @@
 //!
 //! - `Metadata/model_settings.config` — Objekt-zu-Extruder-Zuordnung
 //! - `Metadata/project_settings.config` — Projekt-Filamente (Farben, Typen)
+//!
+//! Zusätzlich wird der Filamentverbrauch pro Layer, pro Filament und pro Slot
+//! geschätzt (siehe [`usage`]). Layer einer AMS-Gruppe teilen ihr Volumen über
+//! [`NamedLayer::filament_volumes`] auf die Filamente der Gruppe auf; Layer ohne
+//! Farbe (Grundplatte) werden getrennt ausgewiesen.
+
+pub mod usage;
+
+pub use usage::{FilamentProperties, FilamentUsage, DEFAULT_DENSITY, DEFAULT_DIAMETER};
 
 use crate::lithophane::layer::NamedLayer;
+use std::collections::HashMap;
 
 /// Mapping von Layern zu Filament-Slots für die 3MF-Ausgabe.
 ///
 /// Wird aus den NamedLayern erstellt und speichert:
 /// - Die geordnete Liste einzigartiger Farben (Filament-Slots)
 /// - Die Zuordnung jedes Layers zu seinem Extruder-Index (1-basiert)
+/// - Den geschätzten Filamentverbrauch jedes Layers und jedes Filaments
 pub struct FilamentMapping {
     /// Unique hex colors in order of first appearance (these become filament slots)
     colors: Vec<String>,
     /// For each layer: the 1-based extruder index (defaults to 1 for layers without color)
     extruder_indices: Vec<u32>,
+    /// For each layer: the estimated filament usage of its mesh
+    layer_usage: Vec<FilamentUsage>,
+    /// Usage per filament (including AMS group members without a slot),
+    /// in order of first appearance
+    filament_usage: Vec<(String, FilamentUsage)>,
+    /// Usage of the layers without color (None if there are none)
+    plate_usage: Option<FilamentUsage>,
 }
 
 impl FilamentMapping {
@@ -27,7 +45,21 @@ impl FilamentMapping {
     /// Sammelt alle einzigartigen Hex-Farben (Reihenfolge des ersten Auftretens)
     /// und ordnet jedem Layer den passenden Extruder-Slot zu.
     /// Layer ohne Farbe (z.B. Grundplatte) erhalten Extruder 1 als Fallback.
+    /// Der Verbrauch wird mit den Standardwerten für PLA geschätzt.
     pub fn from_layers(layers: &[NamedLayer]) -> Self {
+        Self::from_layers_with_properties(layers, &HashMap::new())
+    }
+
+    /// Erstellt eine Filament-Zuordnung mit Materialeigenschaften pro Hex-Code.
+    ///
+    /// Der Verbrauch wird pro Filament berechnet: Layer einer AMS-Gruppe
+    /// werden über ihre `filament_volumes` aufgeteilt, Layer ohne Farbe mit den
+    /// Eigenschaften des Filaments in Slot 1 (das sie druckt) gerechnet.
+    /// Fehlende Einträge verwenden die Standardwerte.
+    pub fn from_layers_with_properties(
+        layers: &[NamedLayer],
+        properties: &HashMap<String, FilamentProperties>,
+    ) -> Self {
         let mut colors: Vec<String> = Vec::new();
         let mut extruder_indices: Vec<u32> = Vec::new();
 
@@ -49,9 +81,59 @@ impl FilamentMapping {
             extruder_indices.push(extruder);
         }
 
+        let properties_of = |hex: Option<&String>| {
+            hex.and_then(|hex| properties.get(hex))
+                .copied()
+                .unwrap_or_default()
+        };
+
+        let mut filament_usage: Vec<(String, FilamentUsage)> = Vec::new();
+        let mut plate_usage: Option<FilamentUsage> = None;
+        let mut layer_usage = Vec::with_capacity(layers.len());
+        for layer in layers {
+            // Volume per filament; None = layer without color
+            let parts: Vec<(Option<&String>, f64)> = if !layer.filament_volumes.is_empty() {
+                layer
+                    .filament_volumes
+                    .iter()
+                    .map(|(hex, volume)| (Some(hex), *volume))
+                    .collect()
+            } else {
+                vec![(layer.hex_color.as_ref(), layer.mesh.volume())]
+            };
+
+            let mut usages = Vec::with_capacity(parts.len());
+            for (hex, volume) in parts {
+                let usage = match hex {
+                    Some(hex) => {
+                        let usage = FilamentUsage::from_volume(volume, &properties_of(Some(hex)));
+                        match filament_usage.iter_mut().find(|(h, _)| h == hex) {
+                            Some((_, total)) => *total = FilamentUsage::total([&*total, &usage]),
+                            None => filament_usage.push((hex.clone(), usage)),
+                        }
+                        usage
+                    }
+                    None => {
+                        let usage =
+                            FilamentUsage::from_volume(volume, &properties_of(colors.first()));
+                        plate_usage = Some(match plate_usage {
+                            Some(total) => FilamentUsage::total([&total, &usage]),
+                            None => usage,
+                        });
+                        usage
+                    }
+                };
+                usages.push(usage);
+            }
+            layer_usage.push(FilamentUsage::total(&usages));
+        }
+
         Self {
             colors,
             extruder_indices,
+            layer_usage,
+            filament_usage,
+            plate_usage,
         }
     }
 
@@ -65,6 +147,44 @@ impl FilamentMapping {
         &self.colors
     }
 
+    /// Geschätzter Verbrauch jedes Layers (Reihenfolge der Layer).
+    pub fn layer_usage(&self) -> &[FilamentUsage] {
+        &self.layer_usage
+    }
+
+    /// Geschätzter Verbrauch pro Filament (Hex-Code, Reihenfolge des ersten Auftretens).
+    ///
+    /// Enthält auch Filamente einer AMS-Gruppe ohne eigenen Slot; Layer ohne
+    /// Farbe zählen nicht dazu (siehe [`plate_usage`](Self::plate_usage)).
+    pub fn filament_usage(&self) -> &[(String, FilamentUsage)] {
+        &self.filament_usage
+    }
+
+    /// Geschätzter Verbrauch der Layer ohne Farbe (Grundplatte), falls vorhanden.
+    pub fn plate_usage(&self) -> Option<FilamentUsage> {
+        self.plate_usage
+    }
+
+    /// Geschätzter Verbrauch pro Filament-Slot (Reihenfolge von [`colors`](Self::colors)).
+    ///
+    /// Jeder Slot zählt den Verbrauch seines Filaments; Slot 1 zusätzlich die
+    /// Layer ohne Farbe, da sie in der 3MF-Datei Extruder 1 zugeordnet sind.
+    pub fn slot_usage(&self) -> Vec<FilamentUsage> {
+        self.colors
+            .iter()
+            .enumerate()
+            .map(|(i, hex)| {
+                let filament = self
+                    .filament_usage
+                    .iter()
+                    .filter(|(h, _)| h == hex)
+                    .map(|(_, usage)| usage);
+                let plate = self.plate_usage.iter().filter(|_| i == 0);
+                FilamentUsage::total(filament.chain(plate))
+            })
+            .collect()
+    }
+
     /// Hex-Farbe als RGBA für Bambu Studio (z.B. `"#FF0000"` → `"#FF0000FF"`).
     fn hex_to_rgba(hex: &str) -> String {
         if hex.len() == 7 && hex.starts_with('#') {
@@ -121,13 +241,15 @@ impl FilamentMapping {
              \x20   <metadata key=\"filament_map\" value=\"{filament_map}\"/>\n",
         ));
 
-        // Filament-Einträge mit RGBA-Farben
-        for (i, color) in self.colors.iter().enumerate() {
+        // Filament-Einträge mit RGBA-Farben und geschätztem Verbrauch
+        for (i, (color, usage)) in self.colors.iter().zip(self.slot_usage()).enumerate() {
             let rgba = Self::hex_to_rgba(color);
             xml.push_str(&format!(
-                "    <filament id=\"{}\" type=\"PLA\" color=\"{}\" used_m=\"0\" used_g=\"0\"/>\n",
+                "    <filament id=\"{}\" type=\"PLA\" color=\"{}\" used_m=\"{:.2}\" used_g=\"{:.2}\"/>\n",
                 i + 1,
-                rgba
+                rgba,
+                usage.meters,
+                usage.grams
             ));
         }
 
@@ -289,6 +411,115 @@ mod tests {
         assert!(xml.contains("identify_id"));
     }
 
+    #[test]
+    fn test_usage_per_slot() {
+        use crate::lithophane::geometry::Vector3;
+
+        // 10 x 10 x 10 mm = 1 cm³ per cube
+        let cube = Mesh::cube(10.0, 10.0, 10.0, Vector3::new(5.0, 5.0, 5.0));
+        let layers = vec![
+            NamedLayer::new(
+                "layer-Red".to_string(),
+                cube.clone(),
+                Some("#FF0000".to_string()),
+            ),
+            NamedLayer::new("layer-plate".to_string(), cube.clone(), None),
+            NamedLayer::new("layer-Green".to_string(), cube, Some("#00FF00".to_string())),
+        ];
+        let properties = HashMap::from([(
+            "#FF0000".to_string(),
+            FilamentProperties {
+                density: 2.0,
+                price_per_kg: Some(30.0),
+                ..FilamentProperties::default()
+            },
+        )]);
+
+        let mapping = FilamentMapping::from_layers_with_properties(&layers, &properties);
+        let usage = mapping.slot_usage();
+
+        // The plate is printed with slot 1 and counts towards it
+        assert!((usage[0].grams - 4.0).abs() < 1e-9);
+        assert!((usage[0].cost.unwrap() - 0.12).abs() < 1e-9);
+        assert!((usage[1].grams - DEFAULT_DENSITY).abs() < 1e-9);
+        assert!(usage[1].cost.is_none());
+        assert!((mapping.layer_usage()[1].volume_mm3 - 1000.0).abs() < 1e-9);
+
+        let xml = mapping.generate_model_settings_config(&layers);
+        assert!(
+            xml.contains(r#"used_g="4.00""#),
+            "slot 1 usage missing; config: {xml}"
+        );
+        assert!(xml.contains(&format!(r#"used_m="{:.2}""#, usage[1].meters)));
+
+        // Per filament, the plate has its own entry
+        let filaments = mapping.filament_usage();
+        assert_eq!(filaments.len(), 2);
+        assert_eq!(filaments[0].0, "#FF0000");
+        assert!((filaments[0].1.grams - 2.0).abs() < 1e-9);
+        assert!((mapping.plate_usage().unwrap().grams - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_usage_per_filament_of_group_layer() {
+        use crate::lithophane::geometry::Vector3;
+
+        // One AMS group layer of 3 cm³, printed with three filaments
+        let mesh = Mesh::cube(30.0, 10.0, 10.0, Vector3::new(15.0, 5.0, 5.0));
+        let layers = vec![
+            NamedLayer::new(
+                "layer-Black+Matte Sakura Pink+Yellow".to_string(),
+                mesh,
+                Some("#000000".to_string()),
+            )
+            .with_filament_volumes(vec![
+                ("#000000".to_string(), 500.0),
+                ("#F4A6B8".to_string(), 1000.0),
+                ("#FFFF00".to_string(), 1500.0),
+            ]),
+            NamedLayer::new(
+                "layer-Yellow".to_string(),
+                Mesh::cube(10.0, 10.0, 10.0, Vector3::new(5.0, 5.0, 5.0)),
+                Some("#FFFF00".to_string()),
+            ),
+        ];
+        let properties = HashMap::from([(
+            "#F4A6B8".to_string(),
+            FilamentProperties {
+                density: 1.3,
+                ..FilamentProperties::default()
+            },
+        )]);
+
+        let mapping = FilamentMapping::from_layers_with_properties(&layers, &properties);
+
+        let grams: Vec<(&str, f64)> = mapping
+            .filament_usage()
+            .iter()
+            .map(|(hex, usage)| (hex.as_str(), usage.grams))
+            .collect();
+        assert_eq!(grams.len(), 3);
+        assert_eq!(grams[0].0, "#000000");
+        assert!((grams[0].1 - 0.5 * DEFAULT_DENSITY).abs() < 1e-9);
+        assert_eq!(grams[1].0, "#F4A6B8");
+        assert!((grams[1].1 - 1.3).abs() < 1e-9);
+        // Yellow of the group plus its own layer
+        assert_eq!(grams[2].0, "#FFFF00");
+        assert!((grams[2].1 - 2.5 * DEFAULT_DENSITY).abs() < 1e-9);
+        assert!(mapping.plate_usage().is_none());
+
+        // The group layer weighs its filaments with their own densities
+        let group = mapping.layer_usage()[0].grams;
+        assert!((group - (2.0 * DEFAULT_DENSITY + 1.3)).abs() < 1e-9);
+
+        // Slot 1 (the group's first filament) only gets its own share
+        let xml = mapping.generate_model_settings_config(&layers);
+        assert!(
+            xml.contains(&format!(r#"used_g="{:.2}""#, 0.5 * DEFAULT_DENSITY)),
+            "slot 1 usage missing; config: {xml}"
+        );
+    }
+
     #[test]
     fn test_project_settings_config_output() {
         let mesh = Mesh::new();
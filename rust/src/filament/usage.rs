@@ -0,0 +1,142 @@
+//! Filamentverbrauch, Gewicht und Kosten
+//!
+//! Das Volumen jedes Layer-Meshes wird über die Dichte in Gramm und über den
+//! Filamentdurchmesser in Meter umgerechnet. Mit einem Preis pro Kilogramm
+//! ergeben sich zusätzlich die Kosten. Fehlen Angaben in der Palette, gelten
+//! die Werte für PLA mit 1,75 mm Durchmesser.
+
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Dichte von PLA in g/cm³
+pub const DEFAULT_DENSITY: f64 = 1.24;
+/// Filamentdurchmesser in mm
+pub const DEFAULT_DIAMETER: f64 = 1.75;
+
+/// Materialeigenschaften eines Filaments
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FilamentProperties {
+    /// Dichte in g/cm³
+    pub density: f64,
+    /// Durchmesser in mm
+    pub diameter: f64,
+    /// Preis pro Kilogramm (None = unbekannt, keine Kosten)
+    pub price_per_kg: Option<f64>,
+}
+
+impl Default for FilamentProperties {
+    fn default() -> Self {
+        Self {
+            density: DEFAULT_DENSITY,
+            diameter: DEFAULT_DIAMETER,
+            price_per_kg: None,
+        }
+    }
+}
+
+/// Geschätzter Verbrauch eines Layers oder Filaments
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FilamentUsage {
+    /// Volumen in mm³
+    pub volume_mm3: f64,
+    /// Gewicht in g
+    pub grams: f64,
+    /// Filamentlänge in m
+    pub meters: f64,
+    /// Kosten (nur mit bekanntem Preis pro kg)
+    pub cost: Option<f64>,
+}
+
+impl FilamentUsage {
+    /// Rechnet ein Volumen in mm³ mit den Materialeigenschaften um
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pixestl::filament::{FilamentProperties, FilamentUsage};
+    ///
+    /// // 1 cm³ PLA
+    /// let usage = FilamentUsage::from_volume(1000.0, &FilamentProperties::default());
+    /// assert!((usage.grams - 1.24).abs() < 1e-9);
+    /// assert!(usage.cost.is_none());
+    /// ```
+    #[must_use]
+    pub fn from_volume(volume_mm3: f64, properties: &FilamentProperties) -> Self {
+        // Also turns -0.0 of empty meshes into 0.0
+        let volume_mm3 = if volume_mm3 > 0.0 { volume_mm3 } else { 0.0 };
+        let grams = volume_mm3 / 1000.0 * properties.density;
+        let cross_section = PI * (properties.diameter / 2.0).powi(2);
+        Self {
+            volume_mm3,
+            grams,
+            meters: volume_mm3 / cross_section / 1000.0,
+            cost: properties.price_per_kg.map(|price| grams / 1000.0 * price),
+        }
+    }
+
+    /// Summiert mehrere Verbräuche; Kosten nur, wenn alle Preise bekannt sind
+    #[must_use]
+    pub fn total<'a>(usages: impl IntoIterator<Item = &'a FilamentUsage>) -> Self {
+        let mut total = FilamentUsage {
+            cost: Some(0.0),
+            ..FilamentUsage::default()
+        };
+        let mut empty = true;
+        for usage in usages {
+            empty = false;
+            total.volume_mm3 += usage.volume_mm3;
+            total.grams += usage.grams;
+            total.meters += usage.meters;
+            total.cost = total.cost.zip(usage.cost).map(|(a, b)| a + b);
+        }
+        if empty {
+            total.cost = None;
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_volume() {
+        let properties = FilamentProperties {
+            density: 1.0,
+            diameter: 2.0,
+            price_per_kg: Some(20.0),
+        };
+        // π mm³ per mm of filament: 1000π mm³ are 1 m and 3.14 g
+        let usage = FilamentUsage::from_volume(1000.0 * PI, &properties);
+        assert!((usage.meters - 1.0).abs() < 1e-9);
+        assert!((usage.grams - PI).abs() < 1e-9);
+        assert!((usage.cost.unwrap() - PI * 0.02).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_total_needs_all_prices() {
+        let priced = FilamentProperties {
+            price_per_kg: Some(25.0),
+            ..FilamentProperties::default()
+        };
+        let a = FilamentUsage::from_volume(1000.0, &priced);
+        let b = FilamentUsage::from_volume(2000.0, &FilamentProperties::default());
+
+        let both = FilamentUsage::total([&a, &a]);
+        assert!((both.grams - 2.0 * a.grams).abs() < 1e-9);
+        assert!((both.cost.unwrap() - 2.0 * a.cost.unwrap()).abs() < 1e-9);
+        assert!(FilamentUsage::total([&a, &b]).cost.is_none());
+        assert!(FilamentUsage::total([]).cost.is_none());
+    }
+
+    #[test]
+    fn test_empty_volume_is_not_negative() {
+        let usage = FilamentUsage::from_volume(-0.0, &FilamentProperties::default());
+        assert_eq!(format!("{:.2}", usage.grams), "0.00");
+        assert_eq!(
+            FilamentUsage::from_volume(-1.0, &FilamentProperties::default()).meters,
+            0.0
+        );
+    }
+}
@@ -2,14 +2,17 @@
 //!
 //! This module provides functionality for:
 //! - Loading and decoding images
-//! - Resizing based on physical dimensions (mm)
+//! - Resizing based on physical dimensions (mm), in linear light with
+//!   premultiplied alpha
+//! - Integer scaling of pixel art (nearest neighbour)
 //! - Converting to grayscale
-//! - Handling transparency
+//! - Handling transparency (alpha threshold, eroding or dilating the mask)
 //! - Flipping images for 3D printing
 
 use crate::color::Rgb;
 use crate::error::{PixestlError, Result};
-use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageBuffer, Rgba, Rgba32FImage, RgbaImage};
 use std::path::Path;
 
 /// Loads an image from a file path
@@ -72,10 +75,51 @@ pub fn check_ratio(
     }
 }
 
+/// Resampling filter used when resizing images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResampleFilter {
+    /// Nearest neighbour (hard edges, no new colors)
+    Nearest,
+    /// Linear (bilinear) filter
+    Triangle,
+    /// Cubic filter (sharper than linear)
+    CatmullRom,
+    /// Gaussian filter (soft)
+    Gaussian,
+    /// Lanczos with window 3 (sharpest)
+    #[default]
+    Lanczos3,
+}
+
+impl From<ResampleFilter> for FilterType {
+    fn from(filter: ResampleFilter) -> Self {
+        match filter {
+            ResampleFilter::Nearest => FilterType::Nearest,
+            ResampleFilter::Triangle => FilterType::Triangle,
+            ResampleFilter::CatmullRom => FilterType::CatmullRom,
+            ResampleFilter::Gaussian => FilterType::Gaussian,
+            ResampleFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Options for resizing images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResampleOptions {
+    /// Filter for the resampling
+    pub filter: ResampleFilter,
+    /// Average the covered source area instead of filtering when shrinking
+    /// by a factor of 2 or more (less aliasing on large downscales)
+    pub area_downscale: bool,
+}
+
 /// Resizes an image based on physical dimensions
 ///
 /// Based on Java ImageUtil.resizeImage
 ///
+/// Resamples with Lanczos3 in linear light and with premultiplied alpha, so
+/// fine detail keeps its brightness and transparent edges get no dark fringes.
+///
 /// # Arguments
 ///
 /// * `image` - Input image
@@ -96,17 +140,37 @@ pub fn check_ratio(
 /// let img = load_image(Path::new("input.png")).unwrap();
 /// let resized = resize_image(&img, 100.0, 0.0, 0.8).unwrap();
 /// ```
+pub fn resize_image(
+    image: &DynamicImage,
+    width_mm: f64,
+    height_mm: f64,
+    pixel_mm: f64,
+) -> Result<RgbaImage> {
+    resize_image_with(
+        image,
+        width_mm,
+        height_mm,
+        pixel_mm,
+        ResampleOptions::default(),
+    )
+}
+
+/// Resizes an image based on physical dimensions with the given options
+///
+/// Same as [`resize_image`]; `ResampleFilter::Nearest` keeps hard edges and
+/// creates no new colors.
 #[allow(
     clippy::cast_possible_truncation,
     clippy::cast_sign_loss,
     clippy::cast_precision_loss
 )]
 #[allow(clippy::float_cmp)]
-pub fn resize_image(
+pub fn resize_image_with(
     image: &DynamicImage,
     width_mm: f64,
     height_mm: f64,
     pixel_mm: f64,
+    options: ResampleOptions,
 ) -> Result<RgbaImage> {
     let (src_width, src_height) = (image.width(), image.height());
 
@@ -136,14 +200,177 @@ pub fn resize_image(
         )));
     }
 
-    // Use Lanczos3 for high-quality resizing
-    let resized = image.resize_exact(
+    Ok(resample(
+        &image.to_rgba8(),
         nb_pixel_width,
         nb_pixel_height,
-        image::imageops::FilterType::Lanczos3,
-    );
+        options,
+    ))
+}
+
+/// Resamples an image to the given pixel size
+///
+/// Blending filters work on linear light with premultiplied alpha; nearest
+/// neighbour copies the source pixels unchanged.
+#[must_use]
+pub fn resample(image: &RgbaImage, width: u32, height: u32, options: ResampleOptions) -> RgbaImage {
+    if options.filter == ResampleFilter::Nearest {
+        return image::imageops::resize(image, width, height, FilterType::Nearest);
+    }
+
+    let linear = to_linear_premultiplied(image);
+    let (src_width, src_height) = image.dimensions();
+    let shrinks = src_width >= width && src_height >= height;
+    let large = src_width >= 2 * width || src_height >= 2 * height;
+    let resized = if options.area_downscale && shrinks && large {
+        area_resize(&linear, width, height)
+    } else {
+        image::imageops::resize(&linear, width, height, options.filter.into())
+    };
+    from_linear_premultiplied(&resized)
+}
+
+/// Converts to linear light with premultiplied alpha
+fn to_linear_premultiplied(image: &RgbaImage) -> Rgba32FImage {
+    let lut: Vec<f32> = (0..=255u8)
+        .map(|v| Rgb::new(v, v, v).to_linear().0 as f32)
+        .collect();
+    let (width, height) = image.dimensions();
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let alpha = f32::from(a) / 255.0;
+        Rgba([
+            lut[r as usize] * alpha,
+            lut[g as usize] * alpha,
+            lut[b as usize] * alpha,
+            alpha,
+        ])
+    })
+}
+
+/// Converts back from linear light with premultiplied alpha to sRGB
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn from_linear_premultiplied(image: &Rgba32FImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let alpha = a.clamp(0.0, 1.0);
+        if alpha <= f32::EPSILON {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let color = Rgb::from_linear(
+            f64::from(r / alpha),
+            f64::from(g / alpha),
+            f64::from(b / alpha),
+        );
+        Rgba([color.r, color.g, color.b, (alpha * 255.0).round() as u8])
+    })
+}
+
+/// Shrinks an image by averaging the source area covered by each pixel
+fn area_resize(image: &Rgba32FImage, width: u32, height: u32) -> Rgba32FImage {
+    let (src_width, src_height) = image.dimensions();
+    let columns = area_weights(src_width, width);
+    let rows = area_weights(src_height, height);
+
+    // Horizontal pass
+    let horizontal: Rgba32FImage = ImageBuffer::from_fn(width, src_height, |x, y| {
+        let mut sum = [0.0f32; 4];
+        for &(sx, weight) in &columns[x as usize] {
+            let pixel = image.get_pixel(sx, y).0;
+            for c in 0..4 {
+                sum[c] += pixel[c] * weight;
+            }
+        }
+        Rgba(sum)
+    });
+
+    // Vertical pass
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let mut sum = [0.0f32; 4];
+        for &(sy, weight) in &rows[y as usize] {
+            let pixel = horizontal.get_pixel(x, sy).0;
+            for c in 0..4 {
+                sum[c] += pixel[c] * weight;
+            }
+        }
+        Rgba(sum)
+    })
+}
+
+/// Computes the covered source pixels and their weights for each target pixel
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn area_weights(src: u32, dst: u32) -> Vec<Vec<(u32, f32)>> {
+    let scale = f64::from(src) / f64::from(dst);
+    (0..dst)
+        .map(|i| {
+            let start = f64::from(i) * scale;
+            let end = start + scale;
+            let first = start.floor() as u32;
+            let last = (end.ceil() as u32).min(src);
+            (first..last)
+                .map(|s| {
+                    let covered = end.min(f64::from(s + 1)) - start.max(f64::from(s));
+                    (s, (covered / scale) as f32)
+                })
+                .filter(|&(_, weight)| weight > 0.0)
+                .collect()
+        })
+        .collect()
+}
+
+/// Computes the integer scale factor for pixel art
+///
+/// Each source pixel becomes `scale` × `scale` color pixels. The factor gets
+/// the result closest to the requested size (without exceeding it when both
+/// width and height are given) and is at least 1. Without a size, one source
+/// pixel maps to one color pixel.
+///
+/// # Example
+///
+/// ```
+/// use pixestl::image::pixel_art_scale;
+///
+/// // 16 px sprite, 40 mm wide at 0.8 mm per color pixel = 50 pixels -> 3x
+/// assert_eq!(pixel_art_scale(16, 16, 40.0, 0.0, 0.8), 3);
+/// ```
+#[must_use]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn pixel_art_scale(
+    image_width: u32,
+    image_height: u32,
+    width_mm: f64,
+    height_mm: f64,
+    pixel_mm: f64,
+) -> u32 {
+    let scale_x = width_mm / pixel_mm / f64::from(image_width);
+    let scale_y = height_mm / pixel_mm / f64::from(image_height);
+    let scale = match (width_mm > 0.0, height_mm > 0.0) {
+        (true, true) => scale_x.min(scale_y).floor(),
+        (true, false) => scale_x.round(),
+        (false, true) => scale_y.round(),
+        (false, false) => 1.0,
+    };
+    (scale as u32).max(1)
+}
 
-    Ok(resized.to_rgba8())
+/// Scales pixel art by an integer factor (nearest neighbour)
+///
+/// Every source pixel becomes exactly `scale` × `scale` pixels of the same
+/// color, so edges stay sharp and no colors are invented.
+#[must_use]
+pub fn scale_pixel_art(image: &DynamicImage, scale: u32) -> RgbaImage {
+    image
+        .resize_exact(
+            image.width() * scale,
+            image.height() * scale,
+            FilterType::Nearest,
+        )
+        .to_rgba8()
 }
 
 /// Checks if an image has any transparent pixels
@@ -163,6 +390,71 @@ pub fn is_pixel_transparent(pixel: &Rgba<u8>) -> bool {
     pixel[3] < 255
 }
 
+/// Makes every pixel fully opaque or fully transparent
+///
+/// Pixels with an alpha of at least `threshold` become opaque (alpha 255) and
+/// keep their color; all others become transparent. With 255 only fully opaque
+/// pixels are kept, lower values keep anti-aliased edges.
+#[must_use]
+pub fn apply_alpha_threshold(image: &RgbaImage, threshold: u8) -> RgbaImage {
+    let mut result = image.clone();
+    for pixel in result.pixels_mut() {
+        *pixel = if pixel[3] >= threshold {
+            Rgba([pixel[0], pixel[1], pixel[2], 255])
+        } else {
+            Rgba([0, 0, 0, 0])
+        };
+    }
+    result
+}
+
+/// Erodes or dilates the opaque area of an image
+///
+/// A negative `radius` removes opaque pixels that have a transparent pixel
+/// within that many pixels (including diagonals); a positive `radius` fills
+/// transparent pixels within that distance of the opaque area with the color
+/// of the nearest opaque pixel. The image border does not count as transparent.
+#[must_use]
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+pub fn adjust_mask(image: &RgbaImage, radius: i32) -> RgbaImage {
+    if radius == 0 || !has_transparent_pixel(image) {
+        return image.clone();
+    }
+
+    let (width, height) = image.dimensions();
+    let r = radius.abs();
+    // Pixels within the radius, nearest first
+    let mut offsets: Vec<(i32, i32)> = (-r..=r)
+        .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+        .filter(|&offset| offset != (0, 0))
+        .collect();
+    offsets.sort_by_key(|&(dx, dy)| dx * dx + dy * dy);
+
+    let neighbors = |x: u32, y: u32| {
+        offsets.iter().filter_map(move |&(dx, dy)| {
+            let nx = x as i32 + dx;
+            let ny = y as i32 + dy;
+            (nx >= 0 && ny >= 0 && nx < width as i32 && ny < height as i32)
+                .then(|| image.get_pixel(nx as u32, ny as u32))
+        })
+    };
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let pixel = *image.get_pixel(x, y);
+        let transparent = is_pixel_transparent(&pixel);
+        if radius < 0 && !transparent && neighbors(x, y).any(is_pixel_transparent) {
+            Rgba([0, 0, 0, 0])
+        } else if radius > 0 && transparent {
+            neighbors(x, y)
+                .find(|p| !is_pixel_transparent(p))
+                .copied()
+                .unwrap_or(pixel)
+        } else {
+            pixel
+        }
+    })
+}
+
 /// Converts an image to grayscale (for texture layers)
 ///
 /// Based on Java ImageUtil.convertToBlackAndWhite
@@ -318,6 +610,150 @@ mod tests {
         assert_eq!(resized.height(), 40);
     }
 
+    #[test]
+    fn test_resample_in_linear_light() {
+        // Averaging black and white gives 50 % light, not sRGB 128
+        let img = ImageBuffer::from_fn(2, 1, |x, _| {
+            let v = if x == 0 { 0 } else { 255 };
+            Rgba([v, v, v, 255])
+        });
+        let options = ResampleOptions {
+            filter: ResampleFilter::Triangle,
+            ..ResampleOptions::default()
+        };
+        let resized = resample(&img, 1, 1, options);
+        let pixel = resized.get_pixel(0, 0);
+        assert!((186..=190).contains(&pixel[0]), "got {}", pixel[0]);
+        assert_eq!(pixel[3], 255);
+    }
+
+    #[test]
+    fn test_resample_premultiplied_alpha_has_no_fringe() {
+        // Transparent black next to red must not darken the red
+        let img = ImageBuffer::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        });
+        let options = ResampleOptions {
+            filter: ResampleFilter::Triangle,
+            ..ResampleOptions::default()
+        };
+        let pixel = *resample(&img, 1, 1, options).get_pixel(0, 0);
+        assert_eq!([pixel[0], pixel[1], pixel[2]], [255, 0, 0]);
+        assert!((127..=128).contains(&pixel[3]));
+    }
+
+    #[test]
+    fn test_area_downscale_averages() {
+        let img = ImageBuffer::from_fn(6, 3, |x, y| {
+            let v = if (x + y) % 2 == 0 { 0 } else { 255 };
+            Rgba([v, v, v, 255])
+        });
+        let options = ResampleOptions {
+            area_downscale: true,
+            ..ResampleOptions::default()
+        };
+        let resized = resample(&img, 2, 1, options);
+        // Each target pixel covers 3x3 pixels: 4 or 5 of 9 white
+        let left = resized.get_pixel(0, 0)[0];
+        let right = resized.get_pixel(1, 0)[0];
+        assert!((170..=180).contains(&left), "got {left}");
+        assert!((194..=200).contains(&right), "got {right}");
+    }
+
+    #[test]
+    fn test_area_weights_sum_to_one() {
+        for (src, dst) in [(10, 3), (7, 2), (100, 33)] {
+            for weights in area_weights(src, dst) {
+                let sum: f32 = weights.iter().map(|(_, w)| w).sum();
+                assert!((sum - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_alpha_threshold() {
+        let img = ImageBuffer::from_fn(3, 1, |x, _| {
+            Rgba([200, 100, 50, [255, 160, 40][x as usize]])
+        });
+
+        let strict = apply_alpha_threshold(&img, 255);
+        assert_eq!(strict.get_pixel(0, 0).0, [200, 100, 50, 255]);
+        assert_eq!(strict.get_pixel(1, 0).0, [0, 0, 0, 0]);
+
+        let lenient = apply_alpha_threshold(&img, 128);
+        assert_eq!(lenient.get_pixel(1, 0).0, [200, 100, 50, 255]);
+        assert_eq!(lenient.get_pixel(2, 0)[3], 0);
+    }
+
+    #[test]
+    fn test_adjust_mask_erodes_next_to_transparency() {
+        // Transparent center pixel in a 5x5 opaque image
+        let img = ImageBuffer::from_fn(5, 5, |x, y| {
+            if x == 2 && y == 2 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                Rgba([255, 0, 0, 255])
+            }
+        });
+
+        let eroded = adjust_mask(&img, -1);
+        assert!(is_pixel_transparent(eroded.get_pixel(1, 1)));
+        assert!(is_pixel_transparent(eroded.get_pixel(3, 2)));
+        // The image border does not erode
+        assert!(!is_pixel_transparent(eroded.get_pixel(0, 0)));
+        assert!(!is_pixel_transparent(eroded.get_pixel(4, 2)));
+
+        let eroded = adjust_mask(&img, -2);
+        assert!(is_pixel_transparent(eroded.get_pixel(0, 0)));
+
+        assert_eq!(adjust_mask(&img, 0), img);
+    }
+
+    #[test]
+    fn test_adjust_mask_dilates_with_nearest_color() {
+        let img = ImageBuffer::from_fn(5, 1, |x, _| match x {
+            0 => Rgba([255, 0, 0, 255]),
+            4 => Rgba([0, 0, 255, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        });
+
+        let dilated = adjust_mask(&img, 1);
+        assert_eq!(dilated.get_pixel(1, 0).0, [255, 0, 0, 255]);
+        assert!(is_pixel_transparent(dilated.get_pixel(2, 0)));
+        assert_eq!(dilated.get_pixel(3, 0).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_pixel_art_scale() {
+        assert_eq!(pixel_art_scale(16, 8, 0.0, 0.0, 0.8), 1);
+        assert_eq!(pixel_art_scale(16, 8, 40.0, 0.0, 0.8), 3);
+        assert_eq!(pixel_art_scale(16, 8, 0.0, 20.0, 0.5), 5);
+        // Both given: the largest factor that fits
+        assert_eq!(pixel_art_scale(16, 8, 40.0, 10.0, 0.8), 1);
+        // Never below one pixel per source pixel
+        assert_eq!(pixel_art_scale(100, 100, 10.0, 0.0, 0.8), 1);
+    }
+
+    #[test]
+    fn test_scale_pixel_art_keeps_colors() {
+        let img = DynamicImage::ImageRgba8(create_test_image(4, 2));
+        let scaled = scale_pixel_art(&img, 3);
+
+        assert_eq!(scaled.dimensions(), (12, 6));
+        for (x, _, pixel) in scaled.enumerate_pixels() {
+            let expected = if x < 6 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 255, 0, 255]
+            };
+            assert_eq!(pixel.0, expected);
+        }
+    }
+
     #[test]
     fn test_has_transparent_pixel_opaque() {
         let img = create_test_image(10, 10);
@@ -15,4 +15,7 @@ pub mod stl;
 pub use error::{PixestlError, Result};
 pub use lithophane::{LithophaneConfig, LithophaneGenerator, Mesh, NamedLayer, Triangle, Vector3};
 pub use palette::{Palette, PaletteLoader, PaletteLoaderConfig, PixelCreationMethod};
-pub use stl::{export_to_3mf, export_to_dir, export_to_zip, write_stl, StlFormat};
+pub use stl::{
+    export_to_3mf, export_to_3mf_with_properties, export_to_dir, export_to_zip, write_stl,
+    StlFormat,
+};
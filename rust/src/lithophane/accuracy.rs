@@ -0,0 +1,163 @@
+//! Color accuracy of the quantized color map
+//!
+//! Compares each opaque source pixel with the color of the stack it was
+//! assigned (ΔE in CIELab) to tell whether a palette is good enough for an
+//! image. Besides the [`DeltaESummary`] the report renders two images:
+//!
+//! - a heatmap of the per-pixel error (green = exact, yellow, red from
+//!   [`HEATMAP_MAX_DELTA_E`] on; pixels without a stack stay transparent),
+//! - a histogram of the stack usage, one bar per stack in its color, sorted
+//!   by pixel count.
+
+use crate::color::Rgb;
+use crate::image::is_pixel_transparent;
+use crate::palette::{DeltaESummary, QuantizationStats};
+use image::{Rgba, RgbaImage};
+
+/// ΔE shown as full red in the heatmap
+pub const HEATMAP_MAX_DELTA_E: f64 = 20.0;
+
+/// Bar width of the usage histogram in pixels (plus one pixel gap)
+const BAR_WIDTH: u32 = 8;
+/// Height of the usage histogram in pixels
+const HISTOGRAM_HEIGHT: u32 = 200;
+const HISTOGRAM_BACKGROUND: Rgba<u8> = Rgba([48, 48, 48, 255]);
+const BAR_FRAME: Rgba<u8> = Rgba([160, 160, 160, 255]);
+
+/// Color accuracy of one generation
+#[derive(Debug, Clone)]
+pub struct AccuracyReport {
+    /// Usage and per-pixel ΔE of the quantized pixels
+    pub stats: QuantizationStats,
+    /// Per-pixel ΔE heatmap in image orientation
+    pub heatmap: RgbaImage,
+}
+
+impl AccuracyReport {
+    /// Compares a source image with its quantized color map (same size)
+    ///
+    /// Only pixels that are opaque in both images are counted.
+    #[must_use]
+    pub fn new(source: &RgbaImage, quantized: &RgbaImage) -> Self {
+        let compared: Vec<(u32, u32)> = quantized
+            .enumerate_pixels()
+            .filter(|&(x, y, pixel)| {
+                !is_pixel_transparent(pixel) && !is_pixel_transparent(source.get_pixel(x, y))
+            })
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        let rgb_at = |image: &RgbaImage, (x, y): (u32, u32)| {
+            let pixel = image.get_pixel(x, y);
+            Rgb::new(pixel[0], pixel[1], pixel[2])
+        };
+        let source_pixels: Vec<Rgb> = compared.iter().map(|&p| rgb_at(source, p)).collect();
+        let quantized_pixels: Vec<Rgb> = compared.iter().map(|&p| rgb_at(quantized, p)).collect();
+        let stats = QuantizationStats::new(&source_pixels, &quantized_pixels);
+
+        let mut heatmap = RgbaImage::new(quantized.width(), quantized.height());
+        for (&(x, y), &delta_e) in compared.iter().zip(&stats.delta_e) {
+            heatmap.put_pixel(x, y, heatmap_color(delta_e));
+        }
+
+        Self { stats, heatmap }
+    }
+
+    /// Mean, median, 95th percentile and maximum ΔE (None without pixels)
+    #[must_use]
+    pub fn summary(&self) -> Option<DeltaESummary> {
+        self.stats.delta_e_summary()
+    }
+
+    /// Renders the stack usage as a bar chart, most used stack first
+    #[must_use]
+    pub fn usage_histogram(&self) -> RgbaImage {
+        let mut usage: Vec<(Rgb, usize)> = self
+            .stats
+            .color_usage
+            .iter()
+            .map(|(&color, &count)| (color, count))
+            .collect();
+        usage.sort_by_key(|&(color, count)| (std::cmp::Reverse(count), color.r, color.g, color.b));
+        let max = usage.first().map_or(1, |&(_, count)| count);
+
+        let width = (usage.len() as u32 * (BAR_WIDTH + 1) + 1).max(1);
+        let mut image = RgbaImage::from_pixel(width, HISTOGRAM_HEIGHT, HISTOGRAM_BACKGROUND);
+        for (i, (color, count)) in usage.iter().enumerate() {
+            let bar_height = ((*count as f64 / max as f64) * f64::from(HISTOGRAM_HEIGHT - 1))
+                .round()
+                .max(2.0) as u32;
+            let left = i as u32 * (BAR_WIDTH + 1) + 1;
+            let top = HISTOGRAM_HEIGHT - bar_height;
+            for y in top..HISTOGRAM_HEIGHT {
+                for x in left..left + BAR_WIDTH {
+                    // Framed bars stay visible against the background in any color
+                    let frame = x == left || x == left + BAR_WIDTH - 1 || y == top;
+                    let pixel = if frame {
+                        BAR_FRAME
+                    } else {
+                        Rgba([color.r, color.g, color.b, 255])
+                    };
+                    image.put_pixel(x, y, pixel);
+                }
+            }
+        }
+        image
+    }
+}
+
+/// Heatmap color of a ΔE: green (0) over yellow to red ([`HEATMAP_MAX_DELTA_E`])
+fn heatmap_color(delta_e: f64) -> Rgba<u8> {
+    let t = (delta_e / HEATMAP_MAX_DELTA_E).clamp(0.0, 1.0);
+    let (r, g) = if t < 0.5 {
+        (t * 2.0, 1.0)
+    } else {
+        (1.0, (1.0 - t) * 2.0)
+    };
+    Rgba([(r * 255.0).round() as u8, (g * 255.0).round() as u8, 0, 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageBuffer;
+
+    #[test]
+    fn test_heatmap_marks_errors() {
+        let source = ImageBuffer::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([255, 0, 0, 255]),
+            1 => Rgba([0, 0, 0, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        });
+        let quantized = ImageBuffer::from_pixel(3, 1, Rgba([255, 0, 0, 255]));
+        let report = AccuracyReport::new(&source, &quantized);
+
+        assert_eq!(report.stats.total_pixels, 2);
+        assert_eq!(*report.heatmap.get_pixel(0, 0), Rgba([0, 255, 0, 255]));
+        assert_eq!(*report.heatmap.get_pixel(1, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(report.heatmap.get_pixel(2, 0)[3], 0);
+        assert!(report.summary().unwrap().max > HEATMAP_MAX_DELTA_E);
+    }
+
+    #[test]
+    fn test_usage_histogram_sorted_by_count() {
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        let quantized = ImageBuffer::from_fn(4, 1, |x, _| if x == 0 { red } else { blue });
+        let report = AccuracyReport::new(&quantized, &quantized);
+        let histogram = report.usage_histogram();
+
+        assert_eq!(
+            histogram.dimensions(),
+            (2 * (BAR_WIDTH + 1) + 1, HISTOGRAM_HEIGHT)
+        );
+        // Blue (3 pixels) first at full height, red (1 pixel) at a third of it
+        let bottom = HISTOGRAM_HEIGHT - 1;
+        assert_eq!(*histogram.get_pixel(2, bottom), blue);
+        assert_eq!(*histogram.get_pixel(2, 2), blue);
+        assert_eq!(*histogram.get_pixel(BAR_WIDTH + 3, bottom), red);
+        assert_eq!(
+            *histogram.get_pixel(BAR_WIDTH + 3, HISTOGRAM_HEIGHT / 2),
+            HISTOGRAM_BACKGROUND
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Backlight non-uniformity compensation
+//!
+//! Light boxes are brighter in the centre (or right above each LED) than in the
+//! corners. With a model of the backlight intensity the texture relief is made
+//! thinner where the light is weak and thicker where it is strong, so the lit
+//! print looks uniform.
+//!
+//! The texture brightness is scaled in linear light by `mean / intensity`
+//! (intensities normalised to a maximum of 1). Where the texture is already at
+//! its minimum thickness the light cannot be compensated any further.
+
+use crate::color::Rgb;
+use crate::error::Result;
+use crate::image::{is_pixel_transparent, load_image};
+use image::{imageops::FilterType, GrayImage, Rgba, RgbaImage};
+use std::path::Path;
+
+/// Lowest normalised intensity used for compensation (avoids division by zero)
+const MIN_INTENSITY: f64 = 0.05;
+
+/// Model of the backlight intensity over the print
+#[derive(Debug, Clone)]
+pub enum Backlight {
+    /// Measured intensity map (bright = strong light), stretched over the print
+    Map(GrayImage),
+    /// Radial falloff from the centre: the corners get `1 - falloff` of the light
+    Radial { falloff: f64 },
+    /// Square grid of LEDs centred on the print, `distance_mm` behind it
+    LedGrid { spacing_mm: f64, distance_mm: f64 },
+}
+
+impl Backlight {
+    /// Loads an intensity map from an image file
+    pub fn load_map(path: &Path) -> Result<Self> {
+        Ok(Backlight::Map(load_image(path)?.to_luma8()))
+    }
+
+    /// Computes the normalised intensity (max 1) per pixel of a print with
+    /// `width` × `height` pixels of `pixel_mm` (row-major, image orientation)
+    #[must_use]
+    pub fn intensity_map(&self, width: u32, height: u32, pixel_mm: f64) -> Vec<f64> {
+        let centre = |p: u32, size: u32| (f64::from(p) + 0.5 - f64::from(size) / 2.0) * pixel_mm;
+        let raw: Vec<f64> = match self {
+            Backlight::Map(map) => {
+                let resized = image::imageops::resize(map, width, height, FilterType::Triangle);
+                resized.pixels().map(|p| f64::from(p[0]) / 255.0).collect()
+            }
+            Backlight::Radial { falloff } => {
+                let corner = (f64::from(width).hypot(f64::from(height)) / 2.0) * pixel_mm;
+                (0..height)
+                    .flat_map(|y| (0..width).map(move |x| (x, y)))
+                    .map(|(x, y)| {
+                        let r = centre(x, width).hypot(centre(y, height)) / corner;
+                        1.0 - falloff * r * r
+                    })
+                    .collect()
+            }
+            Backlight::LedGrid {
+                spacing_mm,
+                distance_mm,
+            } => {
+                // Lambertian point sources: intensity ~ d / (r² + d²)^(3/2)
+                let half_w = f64::from(width) * pixel_mm / 2.0;
+                let half_h = f64::from(height) * pixel_mm / 2.0;
+                let nx = (half_w / spacing_mm).ceil() as i32 + 1;
+                let ny = (half_h / spacing_mm).ceil() as i32 + 1;
+                let leds: Vec<(f64, f64)> = (-ny..=ny)
+                    .flat_map(|j| (-nx..=nx).map(move |i| (i, j)))
+                    .map(|(i, j)| (f64::from(i) * spacing_mm, f64::from(j) * spacing_mm))
+                    .collect();
+                let d = *distance_mm;
+                (0..height)
+                    .flat_map(|y| (0..width).map(move |x| (x, y)))
+                    .map(|(x, y)| {
+                        let (px, py) = (centre(x, width), centre(y, height));
+                        leds.iter()
+                            .map(|(lx, ly)| {
+                                let r2 = (px - lx).powi(2) + (py - ly).powi(2);
+                                d / (r2 + d * d).powf(1.5)
+                            })
+                            .sum()
+                    })
+                    .collect()
+            }
+        };
+
+        let max = raw.iter().copied().fold(0.0, f64::max);
+        if max <= 0.0 {
+            return vec![1.0; raw.len()];
+        }
+        raw.iter()
+            .map(|i| (i / max).clamp(MIN_INTENSITY, 1.0))
+            .collect()
+    }
+}
+
+/// Adjusts a grayscale texture (image orientation) to the backlight
+///
+/// Each pixel's linear brightness is multiplied by `mean / intensity`, capped
+/// at white. Transparent pixels are left unchanged.
+#[must_use]
+pub fn compensate_backlight(
+    texture: &RgbaImage,
+    backlight: &Backlight,
+    pixel_mm: f64,
+) -> RgbaImage {
+    let (width, height) = texture.dimensions();
+    let intensity = backlight.intensity_map(width, height, pixel_mm);
+    let mean = intensity.iter().sum::<f64>() / intensity.len().max(1) as f64;
+
+    let mut result = texture.clone();
+    for (pixel, light) in result.pixels_mut().zip(&intensity) {
+        if is_pixel_transparent(pixel) {
+            continue;
+        }
+        let (linear, _, _) = Rgb::new(pixel[0], pixel[0], pixel[0]).to_linear();
+        let adjusted = (linear * mean / light).min(1.0);
+        let gray = Rgb::from_linear(adjusted, adjusted, adjusted).r;
+        *pixel = Rgba([gray, gray, gray, 255]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma};
+
+    #[test]
+    fn test_radial_falloff() {
+        let map = Backlight::Radial { falloff: 0.5 }.intensity_map(11, 11, 1.0);
+        let centre = map[5 * 11 + 5];
+        let corner = map[0];
+        assert!((centre - 1.0).abs() < 1e-9);
+        assert!(corner < centre);
+        // Corner pixel centre lies slightly inside the corner
+        assert!(corner > 0.5 && corner < 0.6);
+    }
+
+    #[test]
+    fn test_led_grid_is_brightest_above_leds() {
+        // LEDs at -10, 0, 10 mm; pixel centres every 1 mm
+        let map = Backlight::LedGrid {
+            spacing_mm: 10.0,
+            distance_mm: 3.0,
+        }
+        .intensity_map(20, 1, 1.0);
+        // Pixel 10 is centred at 0.5 mm (next to an LED), pixel 15 between LEDs
+        assert!(map[10] > map[15]);
+        assert!(map.iter().all(|&i| i > 0.0 && i <= 1.0));
+    }
+
+    #[test]
+    fn test_map_is_normalised() {
+        let map = ImageBuffer::from_fn(2, 1, |x, _| Luma([if x == 0 { 100 } else { 200 }]));
+        let intensity = Backlight::Map(map).intensity_map(2, 1, 1.0);
+        assert!((intensity[1] - 1.0).abs() < 0.05);
+        assert!(intensity[0] < intensity[1]);
+    }
+
+    #[test]
+    fn test_compensation_brightens_dim_areas() {
+        let map = ImageBuffer::from_fn(2, 1, |x, _| Luma([if x == 0 { 128 } else { 255 }]));
+        let texture = ImageBuffer::from_pixel(2, 1, Rgba([100, 100, 100, 255]));
+        let result = compensate_backlight(&texture, &Backlight::Map(map), 1.0);
+
+        // Weak light: brighter texture (thinner), strong light: darker (thicker)
+        assert!(result.get_pixel(0, 0)[0] > 100);
+        assert!(result.get_pixel(1, 0)[0] < 100);
+    }
+}
@@ -57,7 +57,7 @@ pub fn generate_calibration_pattern(
 
     // Grid dimensions
     let grid_width =
-        num_columns as f64 * SQUARE_SIZE + (num_columns - 1).max(0) as f64 * COLUMN_GAP;
+        num_columns as f64 * SQUARE_SIZE + (num_columns.saturating_sub(1)) as f64 * COLUMN_GAP;
     let grid_depth =
         num_filaments as f64 * SQUARE_SIZE + (num_filaments.saturating_sub(1)) as f64 * ROW_GAP;
 
@@ -160,6 +160,11 @@ mod tests {
                 name: "Red".to_string(),
                 active: true,
                 layers: Some(red_layers),
+                base: false,
+                td: None,
+                density: None,
+                diameter: None,
+                price_per_kg: None,
             },
         );
 
@@ -179,6 +184,11 @@ mod tests {
                 name: "White".to_string(),
                 active: true,
                 layers: Some(white_layers),
+                base: false,
+                td: None,
+                density: None,
+                diameter: None,
+                price_per_kg: None,
             },
         );
 
@@ -198,6 +208,11 @@ mod tests {
                 name: "Blue".to_string(),
                 active: false,
                 layers: Some(blue_layers),
+                base: false,
+                td: None,
+                density: None,
+                diameter: None,
+                price_per_kg: None,
             },
         );
 
@@ -304,6 +319,11 @@ mod tests {
                 name: "Red".to_string(),
                 active: true,
                 layers: Some(layers),
+                base: false,
+                td: None,
+                density: None,
+                diameter: None,
+                price_per_kg: None,
             },
         );
 
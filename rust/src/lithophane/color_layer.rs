@@ -11,36 +11,13 @@ use crate::lithophane::geometry::{Mesh, Vector3};
 use crate::palette::Palette;
 use image::RgbaImage;
 use rayon::prelude::*;
-
-/// Checks if a pixel has any transparent neighbors
-fn has_transparent_neighbor(image: &RgbaImage, x: u32, y: u32) -> bool {
-    let (width, height) = image.dimensions();
-
-    for dy in -1..=1_i32 {
-        for dx in -1..=1_i32 {
-            if dx == 0 && dy == 0 {
-                continue;
-            }
-
-            let nx = x as i32 + dx;
-            let ny = y as i32 + dy;
-
-            if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
-                continue;
-            }
-
-            let pixel = image.get_pixel(nx as u32, ny as u32);
-            if is_pixel_transparent(pixel) {
-                return true;
-            }
-        }
-    }
-
-    false
-}
+use std::collections::HashMap;
 
 /// Generates mesh for a single color layer
 ///
+/// With `raise_to` set, stacks lower than that many layers are raised so all
+/// stacks end at the same height (the gap below is filled by the plate).
+///
 /// Based on Java CSGThreadColorRow.run()
 pub fn generate_color_layer(
     image: &RgbaImage,
@@ -49,9 +26,9 @@ pub fn generate_color_layer(
     config: &LithophaneConfig,
     layer_offset: i32,
     layer_max: i32,
+    raise_to: Option<u32>,
 ) -> Result<Mesh> {
     let (width, height) = image.dimensions();
-    let has_transparency = crate::image::has_transparent_pixel(image);
 
     // Process rows in parallel
     let row_meshes: Vec<Mesh> = (0..height)
@@ -64,9 +41,9 @@ pub fn generate_color_layer(
                 config,
                 y,
                 width,
-                has_transparency,
                 layer_offset,
                 layer_max,
+                raise_to,
             )
         })
         .collect();
@@ -81,6 +58,44 @@ pub fn generate_color_layer(
     Ok(final_mesh)
 }
 
+/// Computes the volume (mm³) each filament of a color layer prints
+///
+/// Sums the filament runs of the stacks like [`generate_color_layer`] without
+/// building the mesh, so the volumes add up to the volume of its mesh.
+pub fn filament_volumes(
+    image: &RgbaImage,
+    palette: &Palette,
+    hex_codes: &[String],
+    config: &LithophaneConfig,
+) -> Vec<(String, f64)> {
+    let mut pixel_counts: HashMap<Rgb, u64> = HashMap::new();
+    for pixel in image.pixels().filter(|p| !is_pixel_transparent(p)) {
+        *pixel_counts
+            .entry(Rgb::new(pixel[0], pixel[1], pixel[2]))
+            .or_default() += 1;
+    }
+
+    let pixel_volume = config.color_pixel_width.powi(2) * config.color_pixel_layer_thickness;
+    hex_codes
+        .iter()
+        .map(|hex_code| {
+            let layers: u64 = pixel_counts
+                .iter()
+                .filter_map(|(color, &count)| {
+                    let combi = palette.get_combi(color)?;
+                    let runs: u64 = combi
+                        .layers_with_hex(hex_code)
+                        .iter()
+                        .map(|layer| u64::from(layer.layer()))
+                        .sum();
+                    Some(runs * count)
+                })
+                .sum();
+            (hex_code.clone(), layers as f64 * pixel_volume)
+        })
+        .collect()
+}
+
 /// Processes a single row of pixels to generate cube meshes for color layers.
 ///
 /// For each hex code in the palette, scans the row left-to-right using run-length
@@ -88,8 +103,8 @@ pub fn generate_color_layer(
 /// RLE run, looks up the `ColorCombi` from the palette and generates a cube for each
 /// layer of that hex code's contribution.
 ///
-/// Transparent pixels and pixels adjacent to transparent neighbors are skipped
-/// to avoid artifacts at transparency boundaries.
+/// Transparent pixels are skipped; the edges of the opaque area are eroded or
+/// dilated beforehand (see `LithophaneConfig::color_mask_radius`).
 #[allow(clippy::too_many_arguments)]
 fn process_row(
     image: &RgbaImage,
@@ -98,9 +113,9 @@ fn process_row(
     config: &LithophaneConfig,
     y: u32,
     width: u32,
-    has_transparency: bool,
     layer_offset: i32,
     layer_max: i32,
+    raise_to: Option<u32>,
 ) -> Mesh {
     let mut mesh = Mesh::new();
 
@@ -114,11 +129,6 @@ fn process_row(
                 continue;
             }
 
-            if has_transparency && has_transparent_neighbor(image, x, y) {
-                x += 1;
-                continue;
-            }
-
             let pixel_rgb = Rgb::new(pixel[0], pixel[1], pixel[2]);
 
             // Run-length encoding
@@ -127,9 +137,7 @@ fn process_row(
                 let next_pixel = image.get_pixel(x + k, y);
                 let next_rgb = Rgb::new(next_pixel[0], next_pixel[1], next_pixel[2]);
 
-                if next_rgb != pixel_rgb
-                    || (has_transparency && has_transparent_neighbor(image, x + k, y))
-                {
+                if next_rgb != pixel_rgb || is_pixel_transparent(next_pixel) {
                     break;
                 }
 
@@ -138,6 +146,9 @@ fn process_row(
 
             if let Some(color_combi) = palette.get_combi(&pixel_rgb) {
                 let layers = color_combi.layers_with_hex(hex_code);
+                let raise = raise_to.map_or(0, |top| {
+                    top.saturating_sub(color_combi.total_layers()) as usize
+                });
 
                 for (layer_index, layer) in layers.iter().enumerate() {
                     let layer_height = layer.layer();
@@ -147,7 +158,8 @@ fn process_row(
 
                     let layer_before = color_combi
                         .layer_position(hex_code, layer_index)
-                        .unwrap_or(0);
+                        .unwrap_or(0)
+                        + raise;
 
                     let (adjusted_height, adjusted_before) =
                         if layer_offset != -1 && layer_max != -1 {
@@ -240,6 +252,7 @@ fn apply_layer_offset(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::image::adjust_mask;
     use image::{ImageBuffer, Rgba};
 
     fn create_opaque_image(width: u32, height: u32, color: [u8; 3]) -> RgbaImage {
@@ -319,32 +332,37 @@ mod tests {
         assert_eq!(b, 0);
     }
 
-    // --- has_transparent_neighbor tests ---
+    // --- edge erosion (default color mask radius) ---
+
+    fn is_eroded(image: &RgbaImage, x: u32, y: u32) -> bool {
+        let radius = LithophaneConfig::default().color_mask_radius;
+        is_pixel_transparent(adjust_mask(image, radius).get_pixel(x, y))
+    }
 
     #[test]
-    fn test_has_transparent_neighbor_all_opaque() {
+    fn test_erosion_all_opaque() {
         let image = create_opaque_image(3, 3, [255, 0, 0]);
-        assert!(!has_transparent_neighbor(&image, 1, 1));
+        assert!(!is_eroded(&image, 1, 1));
     }
 
     #[test]
-    fn test_has_transparent_neighbor_with_transparent() {
+    fn test_erosion_next_to_transparent() {
         let image = create_image_with_transparent_center(3, 3);
         // Pixel at (0, 0) has neighbor at (1, 1) which is transparent
-        assert!(has_transparent_neighbor(&image, 0, 0));
+        assert!(is_eroded(&image, 0, 0));
     }
 
     #[test]
-    fn test_has_transparent_neighbor_corner_pixel() {
+    fn test_erosion_corner_pixel() {
         let image = create_opaque_image(3, 3, [255, 0, 0]);
         // Corner pixel (0, 0) in all-opaque image
-        assert!(!has_transparent_neighbor(&image, 0, 0));
+        assert!(!is_eroded(&image, 0, 0));
     }
 
     #[test]
-    fn test_has_transparent_neighbor_edge_pixel() {
+    fn test_erosion_edge_pixel() {
         let image = create_opaque_image(3, 3, [255, 0, 0]);
         // Edge pixel (1, 0) in all-opaque image
-        assert!(!has_transparent_neighbor(&image, 1, 0));
+        assert!(!is_eroded(&image, 1, 0));
     }
 }
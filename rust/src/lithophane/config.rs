@@ -10,18 +10,44 @@
 //! - **Stützplatte** (`plate`): Eine flache Basis, die alle Farbschichten trägt.
 
 use crate::color::ColorDistanceMethod;
+use crate::image::ResampleFilter;
+use crate::lithophane::backlight::Backlight;
+use crate::lithophane::region::RegionMask;
+use crate::palette::{ColorLocks, GamutMappingMethod, PosterizeMethod};
 
 /// Methode zur Pixel-Erstellung beim Drucken der Farbschichten
 ///
 /// Steuert, wie die einzelnen Farbpixel als 3D-Geometrie erzeugt werden:
 /// - `Additive`: Nur die tatsächlich benötigten Schichten werden hinzugefügt.
 /// - `Full`: Jeder Pixel wird vollständig mit allen Schichten befüllt.
+/// - `Height`: Ein einziges Höhenrelief, die Farbe entsteht durch Filamentwechsel
+///   auf festen Z-Höhen (wie HueForge).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PixelCreationMethod {
     /// Nur die benötigten Schichten werden additiv aufgebaut (spart Material).
     Additive,
     /// Jeder Pixel wird mit der vollständigen Schichtzahl befüllt.
     Full,
+    /// Höhenrelief mit Filamentwechseln auf festen Z-Höhen. Die Reliefhöhe liegt
+    /// zwischen `texture_min_thickness` und `texture_max_thickness`, die Auflösung ist
+    /// `texture_pixel_width`, höchstens `color_number` Filamente (0 = alle aktiven).
+    Height,
+}
+
+/// Ausgleich der fehlenden Höhe bei Farbstapeln mit variabler Tiefe
+///
+/// Ist ein Farbstapel niedriger als die volle Stapelhöhe, wird die Differenz
+/// aufgefüllt, damit die optische Gesamtdicke gleich bleibt:
+/// - `Texture`: Die Texturschicht wird über dem Pixel entsprechend dicker.
+/// - `Plate`: Die Stützplatte wird unter dem Pixel aufgefüllt, der Stapel sitzt darauf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DepthCompensation {
+    /// Fehlende Höhe der Texturschicht zuschlagen (ohne Textur über dem Pixel,
+    /// z. B. in Löchern oder reinen Farbbereichen: wie `Plate`).
+    #[default]
+    Texture,
+    /// Fehlende Höhe unter dem Stapel mit dem Plattenfilament auffüllen.
+    Plate,
 }
 
 /// Vollständige Konfiguration für die Lithophan-Generierung
@@ -60,6 +86,65 @@ pub struct LithophaneConfig {
     pub color_number: usize,
     /// Methode zur Farbabstandsberechnung (RGB oder CIELab)
     pub color_distance_method: ColorDistanceMethod,
+    /// Stärke des Gamut-Mappings vor der Quantisierung (0.0 = aus, 1.0 = volle Kompression)
+    pub gamut_mapping_strength: f64,
+    /// Methode des Gamut-Mappings (Helligkeit oder Buntheit erhalten)
+    pub gamut_mapping_method: GamutMappingMethod,
+    /// Poster-Modus: Bildfarben vor der Quantisierung auf so viele Cluster reduzieren;
+    /// nur die zu den Clusterzentren nächsten Stapel werden verwendet (0 = aus)
+    pub posterize_colors: usize,
+    /// Clusterverfahren des Poster-Modus (k-Means oder Median-Cut)
+    pub posterize_method: PosterizeMethod,
+    /// Feste Zuordnungen für Marken- und Sonderfarben; werden vor der
+    /// allgemeinen Nächste-Farbe-Suche angewendet
+    pub color_locks: ColorLocks,
+    /// Regionen-Maske: legt pro Bereich fest, ob Farbe, Textur, beides oder
+    /// nichts erzeugt wird (None = überall Farbe und Textur)
+    pub region_mask: Option<RegionMask>,
+    /// Schichtreihenfolge der verwendeten Farbstapel für weniger Werkzeugwechsel optimieren
+    pub optimize_tool_changes: bool,
+    /// Ausgleich für Farbstapel, die niedriger als die volle Stapelhöhe sind
+    pub depth_compensation: DepthCompensation,
+    /// Texturdicke über jedem Farbstapel um dessen vorhergesagte Lichtabschwächung
+    /// verringern, damit die durchscheinende Helligkeit dem Bild entspricht
+    pub texture_attenuation_compensation: bool,
+    /// Modell der Hintergrundbeleuchtung; die Textur wird bei schwachem Licht
+    /// dünner und bei starkem Licht dicker (None = gleichmäßiges Licht)
+    pub backlight: Option<Backlight>,
+    /// Durchlicht-Ansicht des fertigen Drucks berechnen (Vorhersage der
+    /// Stapelfarben, Textur- und Plattendicke in physikalischer Auflösung)
+    pub simulate: bool,
+    /// Filter beim Skalieren des Bildes (gerechnet in linearem Licht mit
+    /// vormultipliziertem Alpha)
+    pub resample_filter: ResampleFilter,
+    /// Bei starker Verkleinerung (ab Faktor 2) die abgedeckte Fläche mitteln statt filtern
+    pub area_downscale: bool,
+    /// Modusfilter auf der quantisierten Farbkarte: Radius des Fensters in Pixeln,
+    /// in dem die häufigste Farbe einen Pixel ersetzt (0 = aus)
+    pub mode_filter_radius: u32,
+    /// Zusammenhängende Flächen einer Farbe mit weniger Pixeln werden der
+    /// ähnlichsten Nachbarfarbe zugeschlagen (0 = aus)
+    pub min_island_size: usize,
+    /// Breite der dunklen Umrisslinie zwischen Farbflächen in Pixeln
+    /// (Bleiglas-/Comic-Look, 0 = aus)
+    pub outline_width: u32,
+    /// Hex-Code des Filaments für die Umrisslinie
+    pub outline_hex: String,
+    /// Alpha-Schwelle: Pixel mit Alpha ab diesem Wert gelten als deckend, alle
+    /// anderen als transparent (255 = nur voll deckende Pixel)
+    pub alpha_threshold: u8,
+    /// Randbehandlung der Farbmaske in Pixeln: negativ = Erosion (Pixel neben
+    /// transparenten Pixeln entfallen), positiv = Dilatation, 0 = unverändert
+    pub color_mask_radius: i32,
+    /// Randbehandlung der Texturmaske in Pixeln (wie `color_mask_radius`);
+    /// transparente Texturpixel erhalten keine Dicke
+    pub texture_mask_radius: i32,
+    /// Pixel-Art-Modus: ganzzahlige Skalierung ohne Interpolation, jeder Quellpixel
+    /// wird zu genau N×N Farbpixeln (die Zielgröße wird auf ein Vielfaches gerundet)
+    pub pixel_art: bool,
+    /// Bildfarben, die exakt einer Palette-Farbe oder einem Filament-Hex-Code
+    /// entsprechen, ohne ΔE-Suche zuordnen
+    pub exact_color_match: bool,
     /// Krümmungswinkel in Grad (0 = flach, 90 = Viertelzylinder, 360 = voller Zylinder)
     pub curve: f64,
     /// Debug-Ausgaben aktivieren
@@ -90,6 +175,28 @@ impl Default for LithophaneConfig {
             pixel_creation_method: PixelCreationMethod::Additive,
             color_number: 0,
             color_distance_method: ColorDistanceMethod::CieLab,
+            gamut_mapping_strength: 0.0,
+            gamut_mapping_method: GamutMappingMethod::Lightness,
+            posterize_colors: 0,
+            posterize_method: PosterizeMethod::KMeans,
+            color_locks: ColorLocks::default(),
+            region_mask: None,
+            optimize_tool_changes: false,
+            depth_compensation: DepthCompensation::default(),
+            texture_attenuation_compensation: false,
+            backlight: None,
+            simulate: false,
+            resample_filter: ResampleFilter::default(),
+            area_downscale: false,
+            mode_filter_radius: 0,
+            min_island_size: 0,
+            outline_width: 0,
+            outline_hex: "#000000".to_string(),
+            alpha_threshold: 255,
+            color_mask_radius: -1,
+            texture_mask_radius: 0,
+            pixel_art: false,
+            exact_color_match: false,
             curve: 0.0,
             debug: false,
             low_memory: false,
@@ -111,7 +218,9 @@ impl LithophaneConfig {
     /// - `texture_max_thickness` nicht größer als `texture_min_thickness` ist
     /// - `plate_thickness` negativ ist
     /// - weder `color_layer` noch `texture_layer` aktiviert ist
+    /// - im Modus `Height` keine Farbschicht aktiviert ist
     /// - `curve` außerhalb des Bereichs [0, 360] liegt
+    /// - `gamut_mapping_strength` außerhalb des Bereichs [0, 1] liegt
     pub fn validate(&self) -> crate::error::Result<()> {
         if self.color_pixel_width <= 0.0 {
             return Err(crate::error::PixestlError::Config(
@@ -153,11 +262,43 @@ impl LithophaneConfig {
                 "At least one of color_layer or texture_layer must be enabled".to_string(),
             ));
         }
+        if self.pixel_creation_method == PixelCreationMethod::Height && !self.color_layer {
+            return Err(crate::error::PixestlError::Config(
+                "The height pixel method requires the color layer".to_string(),
+            ));
+        }
         if self.curve < 0.0 || self.curve > 360.0 {
             return Err(crate::error::PixestlError::Config(
                 "curve must be between 0 and 360 degrees".to_string(),
             ));
         }
+        if !(0.0..=1.0).contains(&self.gamut_mapping_strength) {
+            return Err(crate::error::PixestlError::Config(
+                "gamut_mapping_strength must be between 0 and 1".to_string(),
+            ));
+        }
+        match self.backlight {
+            Some(Backlight::Radial { falloff }) if !(0.0..1.0).contains(&falloff) => {
+                return Err(crate::error::PixestlError::Config(
+                    "backlight falloff must be at least 0 and below 1".to_string(),
+                ));
+            }
+            Some(Backlight::LedGrid {
+                spacing_mm,
+                distance_mm,
+            }) if spacing_mm <= 0.0 || distance_mm <= 0.0 => {
+                return Err(crate::error::PixestlError::Config(
+                    "LED spacing and distance must be positive".to_string(),
+                ));
+            }
+            _ => {}
+        }
+        if self.outline_width > 0 && crate::color::Rgb::from_hex(&self.outline_hex).is_err() {
+            return Err(crate::error::PixestlError::Config(format!(
+                "outline_hex must be a hex color code like #000000, got {}",
+                self.outline_hex
+            )));
+        }
         Ok(())
     }
 
@@ -300,4 +441,50 @@ mod tests {
         };
         assert!(config.validate().is_err());
     }
+
+    #[test]
+    fn test_invalid_gamut_mapping_strength() {
+        let config = LithophaneConfig {
+            gamut_mapping_strength: 1.5,
+            ..LithophaneConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_height_method_requires_color_layer() {
+        let config = LithophaneConfig {
+            pixel_creation_method: PixelCreationMethod::Height,
+            color_layer: false,
+            ..LithophaneConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_invalid_outline_hex() {
+        let config = LithophaneConfig {
+            outline_width: 2,
+            outline_hex: "black".to_string(),
+            ..LithophaneConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_invalid_backlight() {
+        let config = LithophaneConfig {
+            backlight: Some(Backlight::Radial { falloff: 1.0 }),
+            ..LithophaneConfig::default()
+        };
+        assert!(config.validate().is_err());
+        let config = LithophaneConfig {
+            backlight: Some(Backlight::LedGrid {
+                spacing_mm: 0.0,
+                distance_mm: 10.0,
+            }),
+            ..LithophaneConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
 }
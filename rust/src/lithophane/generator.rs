@@ -3,18 +3,73 @@
 use crate::color::Rgb;
 use crate::error::{PixestlError, Result};
 use crate::image::{
-    convert_to_grayscale, extract_pixels, flip_vertical, has_transparent_pixel, resize_image,
+    adjust_mask, apply_alpha_threshold, convert_to_grayscale, extract_pixels, flip_vertical,
+    has_transparent_pixel, is_pixel_transparent, pixel_art_scale, resize_image_with,
+    scale_pixel_art, ResampleFilter, ResampleOptions,
 };
-use crate::lithophane::config::LithophaneConfig;
+use crate::lithophane::accuracy::AccuracyReport;
+use crate::lithophane::backlight::compensate_backlight;
+use crate::lithophane::cleanup::{clean_color_map_with, CleanupOptions};
+use crate::lithophane::config::{DepthCompensation, LithophaneConfig, PixelCreationMethod};
+use crate::lithophane::height_layer::{self, SwapSchedule};
 use crate::lithophane::layer::NamedLayer;
+use crate::lithophane::outline::{clear_outline, generate_outline_layer, outline_mask};
+use crate::lithophane::region::{Region, RegionMask};
+use crate::lithophane::simulate::{simulate_backlit, SimulationInput};
 use crate::lithophane::{color_layer, support_plate, texture_layer};
-use crate::palette::{quantize_image, Palette};
+use crate::palette::{
+    posterize_palette_colors, quantize_image, quantize_image_cached, GamutMapper,
+    LockedColorReport, Palette, ToolChangeReport,
+};
 use image::{DynamicImage, RgbaImage};
+use std::collections::{HashMap, HashSet};
 
 pub struct LithophaneGenerator {
     config: LithophaneConfig,
 }
 
+/// Statistics collected while generating a lithophane
+#[derive(Debug, Clone, Default)]
+pub struct GenerationReport {
+    /// Estimated tool changes before and after stack reordering (if enabled)
+    pub tool_changes: Option<ToolChangeReport>,
+    /// Filament swap heights (height pixel method only)
+    pub swap_schedule: Option<SwapSchedule>,
+    /// Pixels changed by the color map cleanup (mode filter and island removal)
+    pub cleaned_pixels: usize,
+    /// Stacks left for quantization in poster mode
+    pub posterized_stacks: Option<usize>,
+    /// How closely each locked color is achieved with the palette
+    pub locked_colors: Vec<LockedColorReport>,
+    /// ΔE between the source pixels and their assigned stacks (color layer only)
+    pub accuracy: Option<AccuracyReport>,
+    /// Rendered backlit appearance (if simulation is enabled)
+    pub simulation: Option<RgbaImage>,
+}
+
+/// Color map of the color layers with the data derived while building it
+struct ColorMap {
+    /// Stack colors in print orientation (outline pixels cleared)
+    image: RgbaImage,
+    /// Outline pixels (row-major, print orientation), if the outline is on
+    outline: Option<Vec<bool>>,
+    /// Whether the color area has transparent holes
+    has_holes: bool,
+}
+
+/// How stacks lower than the full height are filled up (see `DepthCompensation`)
+#[derive(Default)]
+struct DepthFill {
+    /// Missing layers per color pixel (row-major); None if all stacks are complete
+    missing_layers: Option<Vec<u32>>,
+    /// Color pixels whose missing layers the texture adds
+    texture_fill: Option<Vec<bool>>,
+    /// Height the stacks on the plate fill are raised to
+    raise_to: Option<u32>,
+    /// Color map split into the stacks on the plate fill and the ones below the texture
+    split: Option<(RgbaImage, RgbaImage)>,
+}
+
 impl LithophaneGenerator {
     pub fn new(config: LithophaneConfig) -> Result<Self> {
         config.validate()?;
@@ -22,81 +77,597 @@ impl LithophaneGenerator {
     }
 
     pub fn generate(&self, image: &DynamicImage, palette: &Palette) -> Result<Vec<NamedLayer>> {
-        let mut layers = Vec::new();
+        self.generate_with_report(image, palette)
+            .map(|(layers, _)| layers)
+    }
+
+    /// Generates the layers and reports statistics of the run
+    pub fn generate_with_report(
+        &self,
+        image: &DynamicImage,
+        palette: &Palette,
+    ) -> Result<(Vec<NamedLayer>, GenerationReport)> {
+        if self.config.pixel_creation_method == PixelCreationMethod::Height {
+            return self.generate_height(image, palette);
+        }
+
+        let mut report = GenerationReport::default();
 
         // When neither --width nor --height is specified (both are 0), derive the physical
         // dimensions from the source image using color_pixel_width as the scale factor.
         // This ensures color and texture layers cover the same physical area.
         let (eff_width_mm, eff_height_mm) = self.effective_dimensions(image);
 
-        let color_image = if self.config.color_layer {
-            let resized = resize_image(
+        let color_map = if self.config.color_layer {
+            Some(self.build_color_map(image, palette, eff_width_mm, eff_height_mm, &mut report)?)
+        } else {
+            None
+        };
+        let color_image = color_map.as_ref().map(|map| &map.image);
+
+        let texture_image = if self.config.texture_layer {
+            let texture = self.build_texture_map(image, eff_width_mm, eff_height_mm)?;
+            // Thinner texture above dark stacks so the transmitted light matches
+            Some(match color_image {
+                Some(color_img) if self.config.texture_attenuation_compensation => {
+                    texture_layer::compensate_stack_attenuation(
+                        &texture,
+                        color_img,
+                        palette,
+                        self.config.texture_pixel_width / self.config.color_pixel_width,
+                    )
+                }
+                _ => texture,
+            })
+        } else {
+            None
+        };
+
+        let fill = match color_image {
+            Some(color_img) => self.plan_depth_fill(color_img, texture_image.as_ref(), palette),
+            None => DepthFill::default(),
+        };
+
+        let mut layers = Vec::new();
+        let plate_printed = color_map
+            .as_ref()
+            .is_some_and(|map| self.prints_support_plate(map));
+        if let Some(map) = &color_map {
+            layers.extend(self.plate_layer(map, plate_printed, &fill, palette)?);
+            let (stack_layers, tool_changes) = self.stack_layers(map, &fill, palette)?;
+            report.tool_changes = tool_changes;
+            layers.extend(stack_layers);
+        }
+        if let Some(texture_img) = &texture_image {
+            layers.push(self.texture_relief_layer(texture_img, color_image, &fill)?);
+        }
+
+        if self.config.simulate {
+            let input = SimulationInput {
+                color_map: color_image,
+                outline: color_map.as_ref().and_then(|map| map.outline.as_deref()),
+                missing_layers: fill.missing_layers.as_deref(),
+                texture_fill: fill.texture_fill.as_deref(),
+                texture_map: texture_image.as_ref(),
+                plate: plate_printed,
+            };
+            report.simulation = Some(simulate_backlit(&input, palette, &self.config)?);
+        }
+
+        self.apply_curve(image, &mut layers);
+
+        Ok((layers, report))
+    }
+
+    /// Builds the color map (print orientation) from the source image
+    ///
+    /// Runs the color stages in order: locks, posterize, exact map, gamut
+    /// mapping, quantization, cleanup, regions and outline. The accuracy is
+    /// measured on the result.
+    fn build_color_map(
+        &self,
+        image: &DynamicImage,
+        palette: &Palette,
+        width_mm: f64,
+        height_mm: f64,
+        report: &mut GenerationReport,
+    ) -> Result<ColorMap> {
+        let resized = if self.config.pixel_art {
+            scale_pixel_art(image, self.pixel_art_scale(image))
+        } else {
+            resize_image_with(
                 image,
-                eff_width_mm,
-                eff_height_mm,
+                width_mm,
+                height_mm,
                 self.config.color_pixel_width,
-            )?;
+                self.resample_options(),
+            )?
+        };
+        let resized = apply_alpha_threshold(&resized, self.config.alpha_threshold);
+        let pixels = extract_pixels(&resized);
+        let palette_colors = palette.colors();
 
-            let pixels_with_option = extract_pixels(&resized);
-            let pixels: Vec<Vec<Rgb>> = pixels_with_option
-                .iter()
-                .map(|row| row.iter().filter_map(|&p| p).collect())
-                .collect();
+        let (locked, locked_colors) = self.lock_colors(&pixels, palette)?;
+        report.locked_colors = locked_colors;
+        // Locked pixels (row-major) are kept by the cleanup and the outline
+        let fixed = locked_pixel_mask(&pixels, &locked);
+
+        let posterized = self.posterize(&resized, palette, &locked);
+        report.posterized_stacks = posterized.as_ref().map(Vec::len);
+
+        let exact = self.exact_color_map(palette, posterized.as_deref(), locked);
+        let searched = self.map_gamut(unmatched_pixels(&pixels, exact.as_ref()), &palette_colors);
+        let quantized =
+            self.quantize(&searched, palette, &palette_colors, posterized.as_deref())?;
+
+        let mut quantized = pixels_to_image(restore_pixels(&pixels, exact.as_ref(), quantized));
+        report.cleaned_pixels = self.clean_up(&mut quantized, fixed.as_deref());
+        let (color_map, has_holes) = self.mask_color_map(&quantized);
+        let (color_map, outline) = self.apply_outline(color_map, fixed.as_deref());
+
+        // Outline pixels print no stack and are left out of the comparison
+        report.accuracy = Some(AccuracyReport::new(&resized, &flip_vertical(&color_map)));
+
+        Ok(ColorMap {
+            image: color_map,
+            outline,
+            has_holes,
+        })
+    }
+
+    /// Maps the pixel colors matching a color lock to the lock's stack
+    ///
+    /// Also returns how closely each locked color is achieved.
+    fn lock_colors(
+        &self,
+        pixels: &[Vec<Option<Rgb>>],
+        palette: &Palette,
+    ) -> Result<(HashMap<Rgb, Rgb>, Vec<LockedColorReport>)> {
+        let locks = &self.config.color_locks;
+        if locks.is_empty() {
+            return Ok((HashMap::new(), Vec::new()));
+        }
+        let (targets, mut reports) = locks.resolve(palette, self.config.color_distance_method)?;
+        let locked = locks.lock_pixels(pixels.iter().flatten().flatten(), &targets, &mut reports);
+        Ok((locked, reports))
+    }
+
+    /// Picks the stacks used in poster mode (None if it is off)
+    ///
+    /// Only the stacks closest to the cluster centres are used; pixels outside
+    /// the color regions and locked pixels do not take part in the clustering.
+    fn posterize(
+        &self,
+        resized: &RgbaImage,
+        palette: &Palette,
+        locked: &HashMap<Rgb, Rgb>,
+    ) -> Option<Vec<Rgb>> {
+        if self.config.posterize_colors == 0 {
+            return None;
+        }
+        let opaque: Vec<Rgb> = extract_pixels(&self.apply_regions(resized, Region::has_color))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|p| !locked.contains_key(p))
+            .collect();
+        Some(posterize_palette_colors(
+            palette,
+            &opaque,
+            self.config.posterize_colors,
+            self.config.posterize_method,
+            self.config.color_distance_method,
+        ))
+    }
+
+    /// Gets the colors that are mapped without a search (None if there are none)
+    ///
+    /// Pixel art drawn with palette colors needs no search for those; locked
+    /// colors take precedence over everything else.
+    fn exact_color_map(
+        &self,
+        palette: &Palette,
+        posterized: Option<&[Rgb]>,
+        locked: HashMap<Rgb, Rgb>,
+    ) -> Option<HashMap<Rgb, Rgb>> {
+        let mut exact = self.config.exact_color_match.then(|| {
+            let mut map = palette.exact_color_map();
+            if let Some(targets) = posterized {
+                map.retain(|_, color| targets.contains(color));
+            }
+            map
+        });
+        if !locked.is_empty() {
+            exact.get_or_insert_with(HashMap::new).extend(locked);
+        }
+        exact
+    }
+
+    /// Compresses out-of-gamut colors into the palette gamut (if enabled)
+    fn map_gamut(&self, pixels: Vec<Vec<Rgb>>, palette_colors: &[Rgb]) -> Vec<Vec<Rgb>> {
+        if self.config.gamut_mapping_strength <= 0.0 {
+            return pixels;
+        }
+        match GamutMapper::new(
+            palette_colors,
+            self.config.gamut_mapping_method,
+            self.config.gamut_mapping_strength,
+        ) {
+            Some(mapper) => mapper.map_image(&pixels),
+            None => pixels,
+        }
+    }
+
+    /// Quantizes the pixels to the palette stacks (or to the poster stacks)
+    fn quantize(
+        &self,
+        pixels: &[Vec<Rgb>],
+        palette: &Palette,
+        palette_colors: &[Rgb],
+        posterized: Option<&[Rgb]>,
+    ) -> Result<Vec<Vec<Rgb>>> {
+        let method = self.config.color_distance_method;
+        match (posterized, palette.color_lookup()) {
+            // The persistent lookup covers the full palette only
+            (Some(targets), _) => quantize_image(pixels, targets, method),
+            (None, Some(lookup)) => {
+                let mut lookup = lookup.lock().map_err(|_| {
+                    PixestlError::Other("Palette color lookup is poisoned".to_string())
+                })?;
+                if lookup.method() == method {
+                    quantize_image_cached(pixels, palette_colors, method, &mut lookup)
+                } else {
+                    quantize_image(pixels, palette_colors, method)
+                }
+            }
+            (None, None) => quantize_image(pixels, palette_colors, method),
+        }
+    }
+
+    /// Cleans up the color map and returns the number of changed pixels
+    fn clean_up(&self, color_map: &mut RgbaImage, fixed: Option<&[bool]>) -> usize {
+        clean_color_map_with(
+            color_map,
+            CleanupOptions {
+                mode_filter_radius: self.config.mode_filter_radius,
+                min_island_size: self.config.min_island_size,
+            },
+            fixed,
+        )
+    }
+
+    /// Applies the color mask radius and the color regions
+    ///
+    /// Returns the color map in print orientation and whether the color area
+    /// has holes; holes only in texture regions do not count.
+    fn mask_color_map(&self, quantized: &RgbaImage) -> (RgbaImage, bool) {
+        let masked = adjust_mask(quantized, self.config.color_mask_radius);
+        let has_holes = has_transparent_pixel(&masked);
+        let color_map = flip_vertical(&self.apply_regions(&masked, Region::has_color));
+        (color_map, has_holes)
+    }
+
+    /// Clears the outline pixels, which get a dark column instead of their stack
+    ///
+    /// Returns the cleared color map and the outline mask (None if the outline
+    /// is off). Locked pixels (`fixed`, image orientation) are never outlined.
+    fn apply_outline(
+        &self,
+        color_map: RgbaImage,
+        fixed: Option<&[bool]>,
+    ) -> (RgbaImage, Option<Vec<bool>>) {
+        if self.config.outline_width == 0 {
+            return (color_map, None);
+        }
+        let mut mask = outline_mask(&color_map, self.config.outline_width);
+        if let Some(fixed) = fixed {
+            // The mask is in print orientation, the locked pixels are not
+            let width = color_map.width() as usize;
+            for (mask_row, fixed_row) in mask.chunks_mut(width).zip(fixed.chunks(width).rev()) {
+                for (outline, &locked) in mask_row.iter_mut().zip(fixed_row) {
+                    *outline &= !locked;
+                }
+            }
+        }
+        (clear_outline(&color_map, &mask), Some(mask))
+    }
 
-            let palette_colors = palette.colors();
-            let quantized_pixels =
-                quantize_image(&pixels, &palette_colors, self.config.color_distance_method)?;
+    /// Builds the texture height map (print orientation)
+    fn build_texture_map(
+        &self,
+        image: &DynamicImage,
+        width_mm: f64,
+        height_mm: f64,
+    ) -> Result<RgbaImage> {
+        let resized = resize_image_with(
+            image,
+            width_mm,
+            height_mm,
+            self.config.texture_pixel_width,
+            self.resample_options(),
+        )?;
+
+        let masked = adjust_mask(
+            &apply_alpha_threshold(&resized, self.config.alpha_threshold),
+            self.config.texture_mask_radius,
+        );
+        let texture = self.apply_regions(&masked, Region::has_texture);
+        let grayscale = convert_to_grayscale(&texture);
+        // Thinner relief where the backlight is weak, thicker where it is strong
+        let grayscale = match &self.config.backlight {
+            Some(backlight) => {
+                compensate_backlight(&grayscale, backlight, self.config.texture_pixel_width)
+            }
+            None => grayscale,
+        };
+        Ok(flip_vertical(&grayscale))
+    }
 
-            let quantized = pixels_to_image(quantized_pixels);
-            Some(flip_vertical(&quantized))
+    /// Plans how stacks lower than the full height are filled up
+    fn plan_depth_fill(
+        &self,
+        color_img: &RgbaImage,
+        texture_img: Option<&RgbaImage>,
+        palette: &Palette,
+    ) -> DepthFill {
+        let stack_height = palette.stack_height();
+        let missing_layers = Some(missing_layers(color_img, palette, stack_height))
+            .filter(|missing| missing.iter().any(|&m| m > 0));
+        // Color pixels whose missing layers the texture adds; pixels without
+        // texture above them (holes, color-only regions) are filled by the plate
+        let texture_fill = match (texture_img, &missing_layers) {
+            (Some(texture_img), Some(_))
+                if self.config.depth_compensation == DepthCompensation::Texture =>
+            {
+                Some(self.texture_fill_mask(color_img, texture_img))
+            }
+            _ => None,
+        };
+        let raise_to = missing_layers
+            .as_ref()
+            .is_some_and(|missing| {
+                missing
+                    .iter()
+                    .enumerate()
+                    .any(|(i, &m)| m > 0 && !texture_fill.as_ref().is_some_and(|mask| mask[i]))
+            })
+            .then_some(stack_height);
+        // Stacks on the plate fill are raised, the ones below the texture are not
+        let split = match &texture_fill {
+            Some(mask) if raise_to.is_some() => {
+                let on_plate: Vec<bool> = mask.iter().map(|&t| !t).collect();
+                Some((
+                    clear_outline(color_img, mask),
+                    clear_outline(color_img, &on_plate),
+                ))
+            }
+            _ => None,
+        };
+
+        DepthFill {
+            missing_layers,
+            texture_fill,
+            raise_to,
+            split,
+        }
+    }
+
+    /// Whether the support plate is printed
+    ///
+    /// Holes in the color area itself (not texture-only regions) and excluded
+    /// regions drop the plate.
+    fn prints_support_plate(&self, color_map: &ColorMap) -> bool {
+        let excluded = self
+            .config
+            .region_mask
+            .as_ref()
+            .is_some_and(RegionMask::has_exclusions);
+        !color_map.has_holes && !excluded
+    }
+
+    /// Generates the plate layer: the support plate and the plate fill
+    ///
+    /// Without a support plate only the fill is printed (None if there is no
+    /// fill either).
+    fn plate_layer(
+        &self,
+        color_map: &ColorMap,
+        plate: bool,
+        fill: &DepthFill,
+        palette: &Palette,
+    ) -> Result<Option<NamedLayer>> {
+        let fill_mesh = fill.raise_to.map(|top| {
+            let filled = fill
+                .split
+                .as_ref()
+                .map_or(&color_map.image, |(on_plate, _)| on_plate);
+            support_plate::generate_plate_fill(filled, palette, top, &self.config)
+        });
+        let mesh = if plate {
+            let mut mesh = support_plate::generate_support_plate(&color_map.image, &self.config)?;
+            if let Some(fill_mesh) = fill_mesh {
+                mesh.merge_owned(fill_mesh);
+            }
+            Some(mesh)
         } else {
-            None
+            fill_mesh
         };
+        Ok(mesh.map(|mesh| NamedLayer::without_color("layer-plate".to_string(), mesh)))
+    }
 
-        let texture_image = if self.config.texture_layer {
-            let resized = resize_image(
-                image,
-                eff_width_mm,
-                eff_height_mm,
-                self.config.texture_pixel_width,
-            )?;
+    /// Generates the color layers and the outline layer
+    ///
+    /// Also returns the estimated tool changes if the stacks are reordered.
+    fn stack_layers(
+        &self,
+        color_map: &ColorMap,
+        fill: &DepthFill,
+        palette: &Palette,
+    ) -> Result<(Vec<NamedLayer>, Option<ToolChangeReport>)> {
+        let color_img = &color_map.image;
 
-            let grayscale = convert_to_grayscale(&resized);
-            Some(flip_vertical(&grayscale))
+        // Reorder the used stacks on a copy; the caller's palette stays untouched
+        let ordered_palette;
+        let mut tool_changes = None;
+        let palette = if self.config.optimize_tool_changes {
+            let used_colors: HashSet<Rgb> = color_img
+                .pixels()
+                .map(|p| Rgb::new(p[0], p[1], p[2]))
+                .collect();
+            let mut ordered = palette.clone();
+            tool_changes = Some(ordered.optimize_stack_order(&used_colors));
+            ordered_palette = ordered;
+            &ordered_palette
         } else {
-            None
+            palette
         };
 
-        if let Some(ref color_img) = color_image {
-            if !has_transparent_pixel(color_img) {
-                let plate = support_plate::generate_support_plate(color_img, &self.config)?;
-                layers.push(NamedLayer::without_color("layer-plate".to_string(), plate));
+        let mut layers = match &fill.split {
+            Some((on_plate, below_texture)) => {
+                let mut layers = self.generate_color_layers(on_plate, palette, fill.raise_to)?;
+                let unraised = self.generate_color_layers(below_texture, palette, None)?;
+                for (layer, other) in layers.iter_mut().zip(unraised) {
+                    layer.mesh.merge_owned(other.mesh);
+                    for ((_, volume), (_, other_volume)) in layer
+                        .filament_volumes
+                        .iter_mut()
+                        .zip(other.filament_volumes)
+                    {
+                        *volume += other_volume;
+                    }
+                }
+                layers
             }
+            None => self.generate_color_layers(color_img, palette, fill.raise_to)?,
+        };
+
+        if let Some(mask) = &color_map.outline {
+            let (width, height) = color_img.dimensions();
+            let mesh =
+                generate_outline_layer(mask, width, height, palette.stack_height(), &self.config);
+            layers.push(NamedLayer::new(
+                "layer-outline".to_string(),
+                mesh,
+                Some(self.config.outline_hex.clone()),
+            ));
         }
 
-        if let Some(ref color_img) = color_image {
-            let color_layers = self.generate_color_layers(color_img, palette)?;
-            layers.extend(color_layers);
+        Ok((layers, tool_changes))
+    }
+
+    /// Generates the texture layer, thickened above the stacks it fills up
+    fn texture_relief_layer(
+        &self,
+        texture_img: &RgbaImage,
+        color_img: Option<&RgbaImage>,
+        fill: &DepthFill,
+    ) -> Result<NamedLayer> {
+        let mesh = match (color_img, &fill.missing_layers, &fill.texture_fill) {
+            (Some(color_img), Some(missing), Some(mask)) => {
+                // Thicken the texture above stacks lower than the full height
+                let layer = self.config.color_pixel_layer_thickness;
+                texture_layer::generate_texture_layer_with_offset(
+                    texture_img,
+                    &self.config,
+                    |x, y| {
+                        let index = self.color_pixel_index(color_img, x, y);
+                        if mask[index] {
+                            f64::from(missing[index]) * layer
+                        } else {
+                            0.0
+                        }
+                    },
+                )?
+            }
+            _ => texture_layer::generate_texture_layer(texture_img, &self.config)?,
+        };
+        Ok(NamedLayer::new(
+            "layer-texture".to_string(),
+            mesh,
+            Some(self.config.texture_color.clone()),
+        ))
+    }
+
+    /// Generates one heightfield with filament bands (height pixel method)
+    ///
+    /// The relief replaces the color, texture and plate layers; its minimum
+    /// thickness acts as the base.
+    fn generate_height(
+        &self,
+        image: &DynamicImage,
+        palette: &Palette,
+    ) -> Result<(Vec<NamedLayer>, GenerationReport)> {
+        let (eff_width_mm, eff_height_mm) = self.effective_dimensions(image);
+        let resized = resize_image_with(
+            image,
+            eff_width_mm,
+            eff_height_mm,
+            self.config.texture_pixel_width,
+            self.resample_options(),
+        )?;
+        let masked = adjust_mask(
+            &apply_alpha_threshold(&resized, self.config.alpha_threshold),
+            self.config.texture_mask_radius,
+        );
+
+        let masked = self.apply_regions(&masked, |region| region != Region::Exclude);
+
+        let (mut layers, schedule) =
+            height_layer::generate_height_layers(&flip_vertical(&masked), palette, &self.config)?;
+        self.apply_curve(image, &mut layers);
+
+        let report = GenerationReport {
+            swap_schedule: Some(schedule),
+            ..GenerationReport::default()
+        };
+        Ok((layers, report))
+    }
+
+    /// Gets the row-major index of the color pixel under a texture pixel
+    fn color_pixel_index(&self, color_image: &RgbaImage, x: u32, y: u32) -> usize {
+        let (width, height) = color_image.dimensions();
+        let scale = self.config.texture_pixel_width / self.config.color_pixel_width;
+        let cx = ((f64::from(x) * scale) as u32).min(width - 1);
+        let cy = ((f64::from(y) * scale) as u32).min(height - 1);
+        (cy * width + cx) as usize
+    }
+
+    /// Marks the color pixels (row-major) whose texture pixels all have a relief
+    fn texture_fill_mask(&self, color_image: &RgbaImage, texture_image: &RgbaImage) -> Vec<bool> {
+        let size = (color_image.width() * color_image.height()) as usize;
+        let mut covered = vec![false; size];
+        let mut holes = vec![false; size];
+        for (x, y, _) in texture_image.enumerate_pixels() {
+            let index = self.color_pixel_index(color_image, x, y);
+            covered[index] = true;
+            let thickness = texture_layer::texture_thickness(
+                texture_image,
+                x,
+                y,
+                self.config.texture_min_thickness,
+                self.config.texture_max_thickness,
+            );
+            holes[index] |= thickness == 0.0;
         }
+        covered.iter().zip(&holes).map(|(&c, &h)| c && !h).collect()
+    }
 
-        if let Some(ref texture_img) = texture_image {
-            let texture_mesh = texture_layer::generate_texture_layer(texture_img, &self.config)?;
-            layers.push(NamedLayer::new(
-                "layer-texture".to_string(),
-                texture_mesh,
-                Some(self.config.texture_color.clone()),
-            ));
+    /// Clears the pixels of regions that are not kept (see [`RegionMask`])
+    fn apply_regions(&self, image: &RgbaImage, keep: impl Fn(Region) -> bool) -> RgbaImage {
+        match &self.config.region_mask {
+            Some(mask) => mask.apply(image, keep),
+            None => image.clone(),
         }
+    }
 
-        // Apply curve transformation if configured
+    /// Applies the curve transformation if configured
+    fn apply_curve(&self, image: &DynamicImage, layers: &mut [NamedLayer]) {
         if self.config.curve > 0.0 {
             let total_width = self.compute_total_width(image);
-            for layer in &mut layers {
+            for layer in layers {
                 layer.mesh.apply_curve(self.config.curve, total_width);
             }
         }
-
-        Ok(layers)
     }
 
     /// Returns the effective physical dimensions (width_mm, height_mm) to use for resizing.
@@ -107,7 +678,14 @@ impl LithophaneGenerator {
     fn effective_dimensions(&self, image: &DynamicImage) -> (f64, f64) {
         let w = self.config.dest_width_mm;
         let h = self.config.dest_height_mm;
-        if w > 0.0 || h > 0.0 {
+        if self.config.pixel_art {
+            // Snap the size to whole source pixels
+            let size = f64::from(self.pixel_art_scale(image)) * self.config.color_pixel_width;
+            (
+                f64::from(image.width()) * size,
+                f64::from(image.height()) * size,
+            )
+        } else if w > 0.0 || h > 0.0 {
             (w, h)
         } else {
             // No explicit size: map 1 source pixel → 1 color pixel
@@ -118,6 +696,29 @@ impl LithophaneGenerator {
         }
     }
 
+    /// Gets the resampling options (nearest neighbour for pixel art)
+    fn resample_options(&self) -> ResampleOptions {
+        ResampleOptions {
+            filter: if self.config.pixel_art {
+                ResampleFilter::Nearest
+            } else {
+                self.config.resample_filter
+            },
+            area_downscale: self.config.area_downscale,
+        }
+    }
+
+    /// Gets the number of color pixels per source pixel in pixel-art mode
+    fn pixel_art_scale(&self, image: &DynamicImage) -> u32 {
+        pixel_art_scale(
+            image.width(),
+            image.height(),
+            self.config.dest_width_mm,
+            self.config.dest_height_mm,
+            self.config.color_pixel_width,
+        )
+    }
+
     /// Computes the total width of the lithophane in mm for curve transformation.
     fn compute_total_width(&self, image: &DynamicImage) -> f64 {
         let (w, _) = self.effective_dimensions(image);
@@ -128,6 +729,7 @@ impl LithophaneGenerator {
         &self,
         image: &RgbaImage,
         palette: &Palette,
+        raise_to: Option<u32>,
     ) -> Result<Vec<NamedLayer>> {
         let mut layers = Vec::new();
         let hex_color_groups = palette.hex_color_groups();
@@ -156,17 +758,106 @@ impl LithophaneGenerator {
             // For single-filament groups (the common case) this is exact.
             let representative_color = hex_codes.first().cloned();
 
-            let mesh =
-                color_layer::generate_color_layer(image, palette, hex_codes, &self.config, -1, -1)?;
+            let mesh = color_layer::generate_color_layer(
+                image,
+                palette,
+                hex_codes,
+                &self.config,
+                -1,
+                -1,
+                raise_to,
+            )?;
 
-            layers.push(NamedLayer::new(layer_name, mesh, representative_color));
+            let mut layer = NamedLayer::new(layer_name, mesh, representative_color);
+            // The filaments of a group share one mesh; their usage is split by run
+            if hex_codes.len() > 1 {
+                layer = layer.with_filament_volumes(color_layer::filament_volumes(
+                    image,
+                    palette,
+                    hex_codes,
+                    &self.config,
+                ));
+            }
+            layers.push(layer);
         }
 
         Ok(layers)
     }
 }
 
-fn pixels_to_image(pixels: Vec<Vec<Rgb>>) -> RgbaImage {
+/// Gets the layers each color pixel lacks to the full stack height (row-major)
+///
+/// Transparent pixels and colors without a stack count as complete.
+fn missing_layers(image: &RgbaImage, palette: &Palette, stack_height: u32) -> Vec<u32> {
+    image
+        .pixels()
+        .map(|p| {
+            if is_pixel_transparent(p) {
+                return 0;
+            }
+            palette
+                .get_combi(&Rgb::new(p[0], p[1], p[2]))
+                .map_or(0, |combi| stack_height.saturating_sub(combi.total_layers()))
+        })
+        .collect()
+}
+
+/// Marks the locked pixels (row-major); None if no pixel is locked
+fn locked_pixel_mask(pixels: &[Vec<Option<Rgb>>], locked: &HashMap<Rgb, Rgb>) -> Option<Vec<bool>> {
+    (!locked.is_empty()).then(|| {
+        pixels
+            .iter()
+            .flatten()
+            .map(|p| p.is_some_and(|p| locked.contains_key(&p)))
+            .collect()
+    })
+}
+
+/// Gets the opaque pixels without an exact match, row by row
+fn unmatched_pixels(
+    pixels: &[Vec<Option<Rgb>>],
+    exact: Option<&HashMap<Rgb, Rgb>>,
+) -> Vec<Vec<Rgb>> {
+    pixels
+        .iter()
+        .map(|row| {
+            row.iter()
+                .flatten()
+                .filter(|p| exact.is_none_or(|map| !map.contains_key(p)))
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+/// Puts the quantized colors back at the positions of the opaque pixels
+///
+/// `searched` holds the quantized colors of the opaque pixels without an exact
+/// match, row by row in image order; transparent pixels stay `None`.
+fn restore_pixels(
+    pixels: &[Vec<Option<Rgb>>],
+    exact: Option<&HashMap<Rgb, Rgb>>,
+    searched: Vec<Vec<Rgb>>,
+) -> Vec<Vec<Option<Rgb>>> {
+    pixels
+        .iter()
+        .zip(searched)
+        .map(|(row, searched)| {
+            let mut searched = searched.into_iter();
+            row.iter()
+                .map(|p| {
+                    p.and_then(|p| {
+                        exact
+                            .and_then(|map| map.get(&p).copied())
+                            .or_else(|| searched.next())
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn pixels_to_image(pixels: Vec<Vec<Option<Rgb>>>) -> RgbaImage {
     use image::{ImageBuffer, Rgba};
 
     let height = pixels.len() as u32;
@@ -177,11 +868,9 @@ fn pixels_to_image(pixels: Vec<Vec<Rgb>>) -> RgbaImage {
     };
 
     ImageBuffer::from_fn(width, height, |x, y| {
-        if y as usize >= pixels.len() || x as usize >= pixels[y as usize].len() {
-            Rgba([0, 0, 0, 0])
-        } else {
-            let rgb = pixels[y as usize][x as usize];
-            Rgba([rgb.r, rgb.g, rgb.b, 255])
+        match pixels.get(y as usize).and_then(|row| row.get(x as usize)) {
+            Some(Some(rgb)) => Rgba([rgb.r, rgb.g, rgb.b, 255]),
+            _ => Rgba([0, 0, 0, 0]),
         }
     })
 }
@@ -2,6 +2,7 @@
 //!
 //! Provides Vector3, Triangle, and Mesh structures for building STL models
 
+use rayon::prelude::*;
 use std::ops::{Add, Mul, Sub};
 
 /// 3D vector
@@ -157,6 +158,19 @@ impl Mesh {
         self.triangles.extend(other.triangles);
     }
 
+    /// Computes the enclosed volume in mm³
+    ///
+    /// Sums the signed tetrahedron volumes of all triangles (divergence
+    /// theorem), so the mesh must be closed with outward-facing triangles.
+    /// Separate closed parts add up.
+    pub fn volume(&self) -> f64 {
+        self.triangles
+            .par_iter()
+            .map(|t| t.v0.dot(&t.v1.cross(&t.v2)))
+            .sum::<f64>()
+            / 6.0
+    }
+
     /// Creates a cube mesh
     ///
     /// # Arguments
@@ -183,28 +197,28 @@ impl Mesh {
         let v111 = center + Vector3::new(hw, hd, hh);
 
         // Front face (+Y)
-        mesh.add_triangle(Triangle::new(v010, v110, v111));
-        mesh.add_triangle(Triangle::new(v010, v111, v011));
+        mesh.add_triangle(Triangle::new(v010, v111, v110));
+        mesh.add_triangle(Triangle::new(v010, v011, v111));
 
         // Back face (-Y)
-        mesh.add_triangle(Triangle::new(v000, v101, v100));
-        mesh.add_triangle(Triangle::new(v000, v001, v101));
+        mesh.add_triangle(Triangle::new(v000, v100, v101));
+        mesh.add_triangle(Triangle::new(v000, v101, v001));
 
         // Right face (+X)
         mesh.add_triangle(Triangle::new(v100, v110, v101));
         mesh.add_triangle(Triangle::new(v110, v111, v101));
 
         // Left face (-X)
-        mesh.add_triangle(Triangle::new(v000, v011, v001));
-        mesh.add_triangle(Triangle::new(v000, v010, v011));
+        mesh.add_triangle(Triangle::new(v000, v001, v011));
+        mesh.add_triangle(Triangle::new(v000, v011, v010));
 
         // Top face (+Z)
         mesh.add_triangle(Triangle::new(v001, v101, v111));
         mesh.add_triangle(Triangle::new(v001, v111, v011));
 
         // Bottom face (-Z)
-        mesh.add_triangle(Triangle::new(v000, v110, v010));
-        mesh.add_triangle(Triangle::new(v000, v100, v110));
+        mesh.add_triangle(Triangle::new(v000, v010, v110));
+        mesh.add_triangle(Triangle::new(v000, v110, v100));
 
         mesh
     }
@@ -372,6 +386,22 @@ mod tests {
         assert_eq!(mesh.triangle_count(), 12); // 6 faces * 2 triangles per face
     }
 
+    #[test]
+    fn test_mesh_volume() {
+        let mut mesh = Mesh::cube(1.0, 2.0, 3.0, Vector3::new(5.0, -4.0, 1.5));
+        assert!((mesh.volume() - 6.0).abs() < 1e-9);
+
+        // Every face points outwards
+        for triangle in &mesh.triangles {
+            let centre = (triangle.v0 + triangle.v1 + triangle.v2) * (1.0 / 3.0);
+            let outwards = centre - Vector3::new(5.0, -4.0, 1.5);
+            assert!(triangle.normal().dot(&outwards) > 0.0);
+        }
+
+        mesh.merge_owned(Mesh::cube(1.0, 1.0, 1.0, Vector3::new(10.0, 0.0, 0.0)));
+        assert!((mesh.volume() - 7.0).abs() < 1e-9);
+    }
+
     #[test]
     fn test_mesh_translate() {
         let mesh = Mesh::cube(2.0, 2.0, 2.0, Vector3::zero());
@@ -0,0 +1,520 @@
+//! Height-based single-stack mode (HueForge-style)
+//!
+//! The whole lithophane is one heightfield. Each filament fills a band of
+//! print layers and the printer swaps the filament at the start of every band
+//! (see [`SwapSchedule`]). The color of a pixel results from the backlight
+//! passing through all layers below its height:
+//!
+//! - every layer attenuates each linear RGB channel following Beer–Lambert,
+//!   `T = exp(-μ · layer)` with `μ = ln(1 / 0.05) / TD · (2 − f)` for the
+//!   filament color channel `f`: at its transmission distance (TD) a white
+//!   filament lets 5% of the light through, colored filaments absorb the
+//!   other channels faster,
+//! - the predicted colors are normalized to the brightest (thinnest) column.
+//!
+//! The schedule is planned for the image: the base filament of the palette
+//! (or the lightest one) fills the bottom, then the swap (filament, start
+//! layer) that lowers the mean ΔE the most is added
+//! greedily until no swap helps or the filament limit is reached. Afterwards
+//! the swap heights are refined. Every pixel gets the height whose predicted
+//! color is closest to it, and one mesh is generated per filament band.
+
+use crate::color::{CieLab, Rgb};
+use crate::error::{PixestlError, Result};
+use crate::image::is_pixel_transparent;
+use crate::lithophane::config::LithophaneConfig;
+use crate::lithophane::geometry::{Mesh, Vector3};
+use crate::lithophane::layer::NamedLayer;
+use crate::palette::Palette;
+use image::RgbaImage;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Fraction of white light a filament lets through at its transmission distance
+const TD_TRANSMISSION: f64 = 0.05;
+
+/// Minimum decrease of the mean ΔE for an additional filament swap
+const MIN_IMPROVEMENT: f64 = 0.05;
+
+/// Passes over the swap heights after the greedy search
+const REFINE_PASSES: usize = 4;
+
+/// Bits per channel of the color histogram used for planning
+const HISTOGRAM_BITS: u32 = 5;
+
+/// Filament change at a fixed height
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FilamentSwap {
+    /// Hex code of the filament
+    pub hex_code: String,
+    /// Name of the filament in the palette
+    pub name: String,
+    /// First relief layer printed with this filament (0 = bottom)
+    pub start_layer: u32,
+    /// Height of the first layer above the bottom of the relief in mm
+    pub z_mm: f64,
+}
+
+/// Filament bands of a height-mode lithophane, bottom to top
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SwapSchedule {
+    /// Thickness of one print layer in mm
+    pub layer_thickness: f64,
+    /// Layers of the thickest possible pixel
+    pub max_layers: u32,
+    /// Filament per band; the first entry starts at layer 0
+    pub swaps: Vec<FilamentSwap>,
+    /// Mean ΔE (CIE76) between the image and the predicted colors
+    pub mean_delta_e: f64,
+}
+
+/// Filament with its transmittance per print layer (linear RGB)
+struct Filament {
+    hex_code: String,
+    transmittance: [f64; 3],
+}
+
+impl Filament {
+    fn new(hex_code: &str, td: f64, layer_thickness: f64) -> Result<Self> {
+        Ok(Self {
+            hex_code: hex_code.to_string(),
+            transmittance: filament_transmittance(Rgb::from_hex(hex_code)?, td, layer_thickness),
+        })
+    }
+}
+
+/// Transmittance (linear RGB) of `thickness` mm of a filament with the given
+/// transmission distance (Beer–Lambert, see the module documentation)
+pub(crate) fn filament_transmittance(color: Rgb, td: f64, thickness: f64) -> [f64; 3] {
+    let (r, g, b) = color.to_linear();
+    let absorption = -TD_TRANSMISSION.ln() / td;
+    [r, g, b].map(|f| (-absorption * (2.0 - f) * thickness).exp())
+}
+
+/// Filament band: index into the filament list and first layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Band {
+    filament: usize,
+    start: u32,
+}
+
+/// Schedule search over the color histogram of an image
+struct Planner<'a> {
+    filaments: &'a [Filament],
+    /// (color, pixel count)
+    histogram: &'a [(CieLab, usize)],
+    total: usize,
+    min_layers: u32,
+    max_layers: u32,
+}
+
+impl Planner<'_> {
+    /// Predicts the color of every column height `0..=max_layers`
+    fn column_colors(&self, bands: &[Band]) -> Vec<CieLab> {
+        let mut light = [1.0; 3];
+        let mut linear = Vec::with_capacity(self.max_layers as usize + 1);
+        linear.push(light);
+
+        let mut band = 0;
+        for layer in 0..self.max_layers {
+            while band + 1 < bands.len() && bands[band + 1].start <= layer {
+                band += 1;
+            }
+            let transmittance = self.filaments[bands[band].filament].transmittance;
+            for (channel, t) in light.iter_mut().zip(transmittance) {
+                *channel *= t;
+            }
+            linear.push(light);
+        }
+
+        // The thinnest printable column shows the brightest color
+        let scale = linear[self.min_layers as usize]
+            .iter()
+            .copied()
+            .fold(f64::MIN_POSITIVE, f64::max);
+        linear
+            .iter()
+            .map(|l| CieLab::from(Rgb::from_linear(l[0] / scale, l[1] / scale, l[2] / scale)))
+            .collect()
+    }
+
+    /// Gets the height with the closest predicted color and its ΔE
+    fn best_height(&self, columns: &[CieLab], color: &CieLab) -> (u32, f64) {
+        (self.min_layers..=self.max_layers)
+            .map(|n| (n, color.delta_e(&columns[n as usize])))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((self.min_layers, f64::MAX))
+    }
+
+    /// Mean ΔE of the image for a schedule
+    fn error(&self, bands: &[Band]) -> f64 {
+        let columns = self.column_colors(bands);
+        let sum: f64 = self
+            .histogram
+            .iter()
+            .map(|(color, count)| self.best_height(&columns, color).1 * *count as f64)
+            .sum();
+        sum / self.total as f64
+    }
+
+    /// Picks the bands on top of the base filament (greedy, then refined)
+    ///
+    /// Returns the bands with their error.
+    fn plan(&self, base: usize, max_filaments: usize) -> (Vec<Band>, f64) {
+        let mut bands = vec![Band {
+            filament: base,
+            start: 0,
+        }];
+        let mut error = self.error(&bands);
+
+        while bands.len() < max_filaments {
+            let last_start = bands.last().map_or(0, |b| b.start);
+            let candidates: Vec<Band> = (0..self.filaments.len())
+                .filter(|f| bands.iter().all(|b| b.filament != *f))
+                .flat_map(|filament| {
+                    (last_start + 1..self.max_layers).map(move |start| Band { filament, start })
+                })
+                .collect();
+
+            let best = candidates
+                .par_iter()
+                .map(|&band| {
+                    let mut trial = bands.clone();
+                    trial.push(band);
+                    (band, self.error(&trial))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+
+            match best {
+                Some((band, trial_error)) if trial_error < error - MIN_IMPROVEMENT => {
+                    bands.push(band);
+                    error = trial_error;
+                }
+                _ => break,
+            }
+        }
+
+        // Move every swap up or down by one layer while it helps
+        for _ in 0..REFINE_PASSES {
+            let mut improved = false;
+            for i in 1..bands.len() {
+                let low = bands[i - 1].start + 1;
+                let high = bands.get(i + 1).map_or(self.max_layers, |b| b.start) - 1;
+                for start in [bands[i].start.saturating_sub(1), bands[i].start + 1] {
+                    if start < low || start > high || start == bands[i].start {
+                        continue;
+                    }
+                    let mut trial = bands.clone();
+                    trial[i].start = start;
+                    let trial_error = self.error(&trial);
+                    if trial_error < error {
+                        bands = trial;
+                        error = trial_error;
+                        improved = true;
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+
+        (bands, error)
+    }
+}
+
+/// Generates the filament band meshes of a height-mode lithophane
+///
+/// # Arguments
+///
+/// * `image` - Color image at texture resolution (flipped like the other layers)
+/// * `palette` - Palette with the transmission distances of the active filaments
+/// * `config` - Relief height range, resolution, layer thickness and filament limit
+///
+/// # Returns
+///
+/// One layer per filament band (bottom to top) and the swap schedule
+pub fn generate_height_layers(
+    image: &RgbaImage,
+    palette: &Palette,
+    config: &LithophaneConfig,
+) -> Result<(Vec<NamedLayer>, SwapSchedule)> {
+    let layer_thickness = config.color_pixel_layer_thickness;
+    let min_layers = ((config.texture_min_thickness / layer_thickness).round() as u32).max(1);
+    let max_layers =
+        ((config.texture_max_thickness / layer_thickness).round() as u32).max(min_layers + 1);
+
+    let mut hex_codes: Vec<(&String, f64)> = palette
+        .transmission_distances()
+        .iter()
+        .map(|(hex, td)| (hex, *td))
+        .collect();
+    hex_codes.sort_by(|a, b| a.0.cmp(b.0));
+    let filaments = hex_codes
+        .into_iter()
+        .map(|(hex, td)| Filament::new(hex, td, layer_thickness))
+        .collect::<Result<Vec<_>>>()?;
+    if filaments.is_empty() {
+        return Err(PixestlError::InvalidPalette(
+            "The height pixel method needs at least one active filament".to_string(),
+        ));
+    }
+
+    let histogram = color_histogram(image);
+    let total: usize = histogram.iter().map(|(_, count)| count).sum();
+    if total == 0 {
+        return Err(PixestlError::ImageProcess(
+            "The image has no opaque pixels".to_string(),
+        ));
+    }
+
+    let planner = Planner {
+        filaments: &filaments,
+        histogram: &histogram,
+        total,
+        min_layers,
+        max_layers,
+    };
+    let max_filaments = match config.color_number {
+        0 => filaments.len(),
+        n => n.min(filaments.len()),
+    };
+    // The base filament fills the bottom; without it the lightest filament does
+    let base = filaments
+        .iter()
+        .position(|f| f.hex_code == palette.base_hex())
+        .unwrap_or_else(|| {
+            (0..filaments.len())
+                .max_by(|&a, &b| {
+                    let sum = |f: usize| filaments[f].transmittance.iter().sum::<f64>();
+                    sum(a).total_cmp(&sum(b))
+                })
+                .unwrap_or(0)
+        });
+    let (bands, mean_delta_e) = planner.plan(base, max_filaments);
+
+    // Height of every pixel (0 = transparent)
+    let columns = planner.column_colors(&bands);
+    let (width, height) = image.dimensions();
+    let mut cache: HashMap<Rgb, u32> = HashMap::new();
+    let heights: Vec<u32> = image
+        .pixels()
+        .map(|p| {
+            if is_pixel_transparent(p) {
+                return 0;
+            }
+            let rgb = Rgb::new(p[0], p[1], p[2]);
+            *cache
+                .entry(rgb)
+                .or_insert_with(|| planner.best_height(&columns, &CieLab::from(rgb)).0)
+        })
+        .collect();
+
+    let mut layers = Vec::with_capacity(bands.len());
+    let mut swaps = Vec::with_capacity(bands.len());
+    for (i, band) in bands.iter().enumerate() {
+        let end = bands.get(i + 1).map_or(max_layers, |b| b.start);
+        let hex_code = &filaments[band.filament].hex_code;
+        let name = palette.get_color_name(hex_code).unwrap_or(hex_code);
+
+        let mesh = band_mesh(&heights, width, height, band.start, end, config);
+        layers.push(NamedLayer::new(
+            format!("layer-{name}"),
+            mesh,
+            Some(hex_code.clone()),
+        ));
+        swaps.push(FilamentSwap {
+            hex_code: hex_code.clone(),
+            name: name.to_string(),
+            start_layer: band.start,
+            z_mm: f64::from(band.start) * layer_thickness,
+        });
+    }
+
+    let schedule = SwapSchedule {
+        layer_thickness,
+        max_layers,
+        swaps,
+        mean_delta_e,
+    };
+    Ok((layers, schedule))
+}
+
+/// Builds the color histogram of the opaque pixels (reduced precision)
+///
+/// Each bucket holds the mean color of its pixels; buckets are sorted for a
+/// deterministic planning result.
+fn color_histogram(image: &RgbaImage) -> Vec<(CieLab, usize)> {
+    let shift = 8 - HISTOGRAM_BITS;
+    let mut buckets: HashMap<(u8, u8, u8), ([u64; 3], usize)> = HashMap::new();
+    for p in image.pixels() {
+        if is_pixel_transparent(p) {
+            continue;
+        }
+        let bucket = buckets
+            .entry((p[0] >> shift, p[1] >> shift, p[2] >> shift))
+            .or_insert(([0; 3], 0));
+        for (sum, value) in bucket.0.iter_mut().zip([p[0], p[1], p[2]]) {
+            *sum += u64::from(value);
+        }
+        bucket.1 += 1;
+    }
+
+    let mut buckets: Vec<_> = buckets.into_iter().collect();
+    buckets.sort_by_key(|(key, _)| *key);
+    buckets
+        .into_iter()
+        .map(|(_, (sums, count))| {
+            let mean = |sum: u64| (sum / count as u64) as u8;
+            let color = Rgb::new(mean(sums[0]), mean(sums[1]), mean(sums[2]));
+            (CieLab::from(color), count)
+        })
+        .collect()
+}
+
+/// Generates the mesh of one filament band `[start, end)` of the heightfield
+fn band_mesh(
+    heights: &[u32],
+    width: u32,
+    height: u32,
+    start: u32,
+    end: u32,
+    config: &LithophaneConfig,
+) -> Mesh {
+    let pixel_width = config.texture_pixel_width;
+    let layer_thickness = config.color_pixel_layer_thickness;
+    let band_layers = |x: u32, y: u32| {
+        let h = heights[(y * width + x) as usize];
+        h.min(end).saturating_sub(start)
+    };
+
+    let row_meshes: Vec<Mesh> = (0..height)
+        .into_par_iter()
+        .map(|y| {
+            let mut mesh = Mesh::new();
+            let mut x = 0;
+            while x < width {
+                let layers = band_layers(x, y);
+                let mut k = 1;
+                while x + k < width && band_layers(x + k, y) == layers {
+                    k += 1;
+                }
+
+                if layers > 0 {
+                    let cube_width = pixel_width * f64::from(k);
+                    let cube_height = layer_thickness * f64::from(layers);
+                    let center = Vector3::new(
+                        f64::from(x) * pixel_width + cube_width / 2.0,
+                        (f64::from(y) + 0.5) * pixel_width,
+                        f64::from(start) * layer_thickness + cube_height / 2.0,
+                    );
+                    mesh.merge_owned(Mesh::cube(cube_width, pixel_width, cube_height, center));
+                }
+                x += k;
+            }
+            mesh
+        })
+        .collect();
+
+    let mut band = Mesh::new();
+    for row_mesh in row_meshes {
+        band.merge_owned(row_mesh);
+    }
+    band
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    fn planner<'a>(filaments: &'a [Filament], histogram: &'a [(CieLab, usize)]) -> Planner<'a> {
+        Planner {
+            filaments,
+            histogram,
+            total: histogram.iter().map(|(_, n)| n).sum(),
+            min_layers: 2,
+            max_layers: 12,
+        }
+    }
+
+    #[test]
+    fn test_thicker_columns_are_darker() {
+        let filaments = [Filament::new("#FFFFFF", 3.0, 0.1).unwrap()];
+        let planner = planner(&filaments, &[]);
+        let columns = planner.column_colors(&[Band {
+            filament: 0,
+            start: 0,
+        }]);
+
+        assert!((columns[2].l - 100.0).abs() < 0.5);
+        for n in 2..12 {
+            assert!(columns[n + 1].l < columns[n].l);
+        }
+    }
+
+    #[test]
+    fn test_plan_adds_colored_band() {
+        let filaments = [
+            Filament::new("#FF0000", 1.5, 0.1).unwrap(),
+            Filament::new("#FFFFFF", 3.0, 0.1).unwrap(),
+        ];
+        // Light grays and saturated dark reds
+        let histogram = [
+            (CieLab::from(Rgb::new(230, 230, 230)), 50),
+            (CieLab::from(Rgb::new(160, 20, 20)), 50),
+        ];
+        let planner = planner(&filaments, &histogram);
+
+        let (bands, error) = planner.plan(1, 2);
+        assert_eq!(bands.len(), 2);
+        assert_eq!(
+            bands[0],
+            Band {
+                filament: 1,
+                start: 0
+            }
+        );
+        assert_eq!(bands[1].filament, 0);
+        assert!(error < planner.error(&bands[..1]));
+
+        let (single, _) = planner.plan(1, 1);
+        assert_eq!(single.len(), 1);
+    }
+
+    #[test]
+    fn test_band_mesh_clips_heights() {
+        // Heights 0 (transparent), 3 and 8 layers in one row
+        let heights = [0, 3, 8];
+        let config = LithophaneConfig::default();
+        let z_max = |mesh: &Mesh| {
+            mesh.triangles
+                .iter()
+                .flat_map(|t| [t.v0.z, t.v1.z, t.v2.z])
+                .fold(f64::MIN, f64::max)
+        };
+
+        let lower = band_mesh(&heights, 3, 1, 0, 5, &config);
+        let upper = band_mesh(&heights, 3, 1, 5, 10, &config);
+
+        // Two cubes below the swap, one above
+        assert_eq!(lower.triangle_count(), 24);
+        assert_eq!(upper.triangle_count(), 12);
+        assert!((z_max(&lower) - 0.5).abs() < 1e-9);
+        assert!((z_max(&upper) - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_color_histogram_skips_transparent() {
+        let image: RgbaImage = ImageBuffer::from_fn(4, 1, |x, _| match x {
+            0 => Rgba([0, 0, 0, 0]),
+            1 | 2 => Rgba([200, 10, 10, 255]),
+            _ => Rgba([201, 11, 11, 255]),
+        });
+
+        let histogram = color_histogram(&image);
+        assert_eq!(histogram.len(), 1);
+        assert_eq!(histogram[0].1, 3);
+    }
+}
@@ -15,6 +15,9 @@ pub struct NamedLayer {
     pub mesh: Mesh,
     /// Palette hex color, e.g. `"#FF4400"`. `None` for texture/plate layers.
     pub hex_color: Option<String>,
+    /// Volume in mm³ per filament for layers printed with several filaments (AMS
+    /// groups). Empty if the whole mesh is printed with `hex_color`.
+    pub filament_volumes: Vec<(String, f64)>,
 }
 
 impl NamedLayer {
@@ -23,6 +26,7 @@ impl NamedLayer {
             name,
             mesh,
             hex_color,
+            filament_volumes: Vec::new(),
         }
     }
 
@@ -31,6 +35,14 @@ impl NamedLayer {
             name,
             mesh,
             hex_color: None,
+            filament_volumes: Vec::new(),
         }
     }
+
+    /// Sets the volume each filament of the layer prints (see `filament_volumes`)
+    #[must_use]
+    pub fn with_filament_volumes(mut self, volumes: Vec<(String, f64)>) -> Self {
+        self.filament_volumes = volumes;
+        self
+    }
 }
@@ -3,21 +3,38 @@
 //! This module provides functionality for:
 //! - Converting quantized images to 3D lithophane meshes
 //! - Color layer generation (stacked cubes)
+//! - Speckle and island cleanup of the quantized color map
+//! - Color accuracy report (ΔE statistics, heatmap, stack usage)
 //! - Texture layer generation (brightness-based depth)
 //! - Support plate generation
+//! - Backlit appearance simulation
+//! - Height-based single-stack mode with filament swaps (HueForge-style)
 //! - Parallel mesh generation using Rayon
 
+pub mod accuracy;
+pub mod backlight;
 pub mod calibration;
+pub mod cleanup;
 pub mod color_layer;
 pub mod config;
 pub mod generator;
 pub mod geometry;
+pub mod height_layer;
 pub mod layer;
+pub mod outline;
+pub mod region;
+pub mod simulate;
 pub mod support_plate;
 pub mod texture_layer;
 
+pub use accuracy::AccuracyReport;
+pub use backlight::Backlight;
 pub use calibration::generate_calibration_pattern;
-pub use config::{LithophaneConfig, PixelCreationMethod};
-pub use generator::LithophaneGenerator;
+pub use cleanup::{clean_color_map, clean_color_map_with, CleanupOptions};
+pub use config::{DepthCompensation, LithophaneConfig, PixelCreationMethod};
+pub use generator::{GenerationReport, LithophaneGenerator};
 pub use geometry::{Mesh, Triangle, Vector3};
+pub use height_layer::{FilamentSwap, SwapSchedule};
 pub use layer::NamedLayer;
+pub use region::{Region, RegionMask};
+pub use simulate::{simulate_backlit, SimulationInput};
@@ -0,0 +1,177 @@
+//! Outline ("leading") layer for a stained-glass or comic look
+//!
+//! Boundaries between differently colored areas of the quantized color map are
+//! replaced by a dark line printed in one filament over the full stack height.
+//! The line hides imprecise color transitions like the lead cames of a
+//! stained-glass window.
+//!
+//! A line of `width` pixels is centred on the boundary: for odd widths the
+//! extra pixel lies on the right / lower side. Edges towards transparent
+//! pixels get no line.
+
+use crate::image::is_pixel_transparent;
+use crate::lithophane::config::LithophaneConfig;
+use crate::lithophane::geometry::{Mesh, Vector3};
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Finds the outline pixels of a color map (row-major)
+///
+/// A pixel belongs to the outline if an opaque pixel of another color lies in
+/// the window `[-(width + 1) / 2, width / 2]` around it (both axes), which
+/// gives lines of `width` pixels across straight boundaries.
+#[must_use]
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+pub fn outline_mask(image: &RgbaImage, width: u32) -> Vec<bool> {
+    let (image_width, image_height) = image.dimensions();
+    if width == 0 {
+        return vec![false; (image_width * image_height) as usize];
+    }
+
+    let before = width.div_ceil(2) as i32;
+    let after = (width / 2) as i32;
+    let differs = |x: u32, y: u32| -> bool {
+        let pixel = image.get_pixel(x, y);
+        if is_pixel_transparent(pixel) {
+            return false;
+        }
+        for dy in -before..=after {
+            for dx in -before..=after {
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+                if nx < 0 || ny < 0 || nx >= image_width as i32 || ny >= image_height as i32 {
+                    continue;
+                }
+                let neighbor = image.get_pixel(nx as u32, ny as u32);
+                if !is_pixel_transparent(neighbor) && neighbor != pixel {
+                    return true;
+                }
+            }
+        }
+        false
+    };
+
+    (0..image_height)
+        .into_par_iter()
+        .flat_map_iter(|y| (0..image_width).map(move |x| differs(x, y)))
+        .collect()
+}
+
+/// Makes the outline pixels of a color map transparent
+///
+/// The color stacks there are replaced by the outline layer.
+#[must_use]
+pub fn clear_outline(image: &RgbaImage, mask: &[bool]) -> RgbaImage {
+    let mut result = image.clone();
+    for (pixel, &outline) in result.pixels_mut().zip(mask) {
+        if outline {
+            *pixel = Rgba([0, 0, 0, 0]);
+        }
+    }
+    result
+}
+
+/// Generates the outline mesh: a column of `stack_height` layers per outline pixel
+///
+/// Neighbouring pixels of a row are merged into one cube (run-length encoding
+/// as in the color layers).
+#[must_use]
+pub fn generate_outline_layer(
+    mask: &[bool],
+    width: u32,
+    height: u32,
+    stack_height: u32,
+    config: &LithophaneConfig,
+) -> Mesh {
+    let pixel_width = config.color_pixel_width;
+    let cube_height = config.color_pixel_layer_thickness * f64::from(stack_height);
+
+    let row_meshes: Vec<Mesh> = (0..height)
+        .into_par_iter()
+        .map(|y| {
+            let row = &mask[(y * width) as usize..((y + 1) * width) as usize];
+            let mut mesh = Mesh::new();
+            let mut x = 0;
+            while x < width as usize {
+                if !row[x] {
+                    x += 1;
+                    continue;
+                }
+                let mut k = 1;
+                while x + k < row.len() && row[x + k] {
+                    k += 1;
+                }
+
+                let cube_width = pixel_width * k as f64;
+                let center = Vector3::new(
+                    x as f64 * pixel_width + cube_width / 2.0,
+                    (f64::from(y) + 0.5) * pixel_width,
+                    cube_height / 2.0,
+                );
+                mesh.merge_owned(Mesh::cube(cube_width, pixel_width, cube_height, center));
+                x += k;
+            }
+            mesh
+        })
+        .collect();
+
+    let mut mesh = Mesh::new();
+    for row_mesh in row_meshes {
+        mesh.merge_owned(row_mesh);
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageBuffer;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const CLEAR: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    /// 6x2 map: three red columns, three blue columns
+    fn halves() -> RgbaImage {
+        ImageBuffer::from_fn(6, 2, |x, _| if x < 3 { RED } else { BLUE })
+    }
+
+    fn marked_columns(mask: &[bool], width: usize) -> Vec<usize> {
+        (0..width).filter(|&x| mask[x]).collect()
+    }
+
+    #[test]
+    fn test_outline_width_across_boundary() {
+        let image = halves();
+        assert_eq!(marked_columns(&outline_mask(&image, 1), 6), vec![3]);
+        assert_eq!(marked_columns(&outline_mask(&image, 2), 6), vec![2, 3]);
+        assert_eq!(marked_columns(&outline_mask(&image, 3), 6), vec![2, 3, 4]);
+        assert!(outline_mask(&image, 0).iter().all(|&m| !m));
+    }
+
+    #[test]
+    fn test_no_outline_towards_transparency() {
+        let image = ImageBuffer::from_fn(4, 1, |x, _| if x < 2 { CLEAR } else { RED });
+        assert!(outline_mask(&image, 2).iter().all(|&m| !m));
+    }
+
+    #[test]
+    fn test_clear_outline_and_mesh() {
+        let image = halves();
+        let mask = outline_mask(&image, 2);
+        let cleared = clear_outline(&image, &mask);
+        assert!(is_pixel_transparent(cleared.get_pixel(2, 0)));
+        assert_eq!(*cleared.get_pixel(1, 0), RED);
+
+        // One merged cube per row, spanning the full stack height
+        let config = LithophaneConfig::default();
+        let mesh = generate_outline_layer(&mask, 6, 2, 5, &config);
+        assert_eq!(mesh.triangle_count(), 24);
+        let top = mesh
+            .triangles
+            .iter()
+            .flat_map(|t| [t.v0.z, t.v1.z, t.v2.z])
+            .fold(f64::MIN, f64::max);
+        assert!((top - 5.0 * config.color_pixel_layer_thickness).abs() < 1e-9);
+    }
+}
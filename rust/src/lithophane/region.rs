@@ -0,0 +1,207 @@
+//! Region mask for per-area generation settings
+//!
+//! A second image labels the areas of the lithophane, so e.g. the subject can
+//! be printed in full color while the background stays a grayscale texture.
+//! Each mask pixel is matched to the closest label color:
+//!
+//! | Label  | Color     | Region                              |
+//! |--------|-----------|-------------------------------------|
+//! | White  | `#FFFFFF` | color and texture                   |
+//! | Grey   | `#808080` | texture only (grayscale)            |
+//! | Red    | `#FF0000` | color only                          |
+//! | Black  | `#000000` | excluded (also transparent pixels)  |
+//!
+//! The mask is stretched over the image, so it should have the same aspect
+//! ratio; any resolution works. Areas without color or texture are made
+//! transparent in the respective layer.
+
+use crate::error::Result;
+use crate::image::{is_pixel_transparent, load_image};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::path::Path;
+
+/// What is generated in an area of the region mask
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// Color layers and texture layer
+    Full,
+    /// Texture layer only (grayscale)
+    TextureOnly,
+    /// Color layers only
+    ColorOnly,
+    /// Nothing
+    Exclude,
+}
+
+impl Region {
+    /// Label colors of the regions
+    const LABELS: [([u8; 3], Region); 4] = [
+        ([255, 255, 255], Region::Full),
+        ([128, 128, 128], Region::TextureOnly),
+        ([255, 0, 0], Region::ColorOnly),
+        ([0, 0, 0], Region::Exclude),
+    ];
+
+    /// Gets the region of a mask pixel (closest label color)
+    #[must_use]
+    pub fn from_pixel(pixel: &Rgba<u8>) -> Self {
+        if is_pixel_transparent(pixel) {
+            return Region::Exclude;
+        }
+        let distance = |label: &[u8; 3]| -> i32 {
+            (0..3)
+                .map(|i| (i32::from(pixel[i]) - i32::from(label[i])).pow(2))
+                .sum()
+        };
+        Self::LABELS
+            .iter()
+            .min_by_key(|(label, _)| distance(label))
+            .map_or(Region::Full, |(_, region)| *region)
+    }
+
+    /// Returns true if color layers are generated in this region
+    #[must_use]
+    pub fn has_color(self) -> bool {
+        matches!(self, Region::Full | Region::ColorOnly)
+    }
+
+    /// Returns true if the texture layer is generated in this region
+    #[must_use]
+    pub fn has_texture(self) -> bool {
+        matches!(self, Region::Full | Region::TextureOnly)
+    }
+}
+
+/// Labelled regions of the lithophane
+#[derive(Debug, Clone)]
+pub struct RegionMask {
+    mask: RgbaImage,
+}
+
+impl RegionMask {
+    /// Creates a region mask from a labelled image
+    #[must_use]
+    pub fn new(image: &DynamicImage) -> Self {
+        Self {
+            mask: image.to_rgba8(),
+        }
+    }
+
+    /// Loads a region mask from an image file
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::new(&load_image(path)?))
+    }
+
+    /// Gets the mask size in pixels
+    #[must_use]
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.mask.dimensions()
+    }
+
+    /// Gets the region at pixel (x, y) of an image with the given size
+    ///
+    /// The mask is sampled at the pixel centre (nearest neighbour).
+    #[must_use]
+    pub fn region_at(&self, x: u32, y: u32, width: u32, height: u32) -> Region {
+        let (mask_width, mask_height) = self.mask.dimensions();
+        let sample = |p: u32, size: u32, mask_size: u32| -> u32 {
+            let position = (f64::from(p) + 0.5) * f64::from(mask_size) / f64::from(size);
+            (position as u32).min(mask_size - 1)
+        };
+        Region::from_pixel(
+            self.mask
+                .get_pixel(sample(x, width, mask_width), sample(y, height, mask_height)),
+        )
+    }
+
+    /// Returns true if any area is excluded
+    #[must_use]
+    pub fn has_exclusions(&self) -> bool {
+        self.mask
+            .pixels()
+            .any(|p| Region::from_pixel(p) == Region::Exclude)
+    }
+
+    /// Makes the pixels of an image transparent whose region is not kept
+    ///
+    /// The image must have the orientation of the mask (not flipped).
+    #[must_use]
+    pub fn apply(&self, image: &RgbaImage, keep: impl Fn(Region) -> bool) -> RgbaImage {
+        let (width, height) = image.dimensions();
+        let mut result = image.clone();
+        for (x, y, pixel) in result.enumerate_pixels_mut() {
+            if !keep(self.region_at(x, y, width, height)) {
+                *pixel = Rgba([0, 0, 0, 0]);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageBuffer;
+
+    /// 2x2 mask: white, grey / red, black
+    fn test_mask() -> RegionMask {
+        let buf = ImageBuffer::from_fn(2, 2, |x, y| match (x, y) {
+            (0, 0) => Rgba([255, 255, 255, 255]),
+            (1, 0) => Rgba([128, 128, 128, 255]),
+            (0, 1) => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 0, 0, 255]),
+        });
+        RegionMask::new(&DynamicImage::ImageRgba8(buf))
+    }
+
+    #[test]
+    fn test_region_from_pixel() {
+        assert_eq!(
+            Region::from_pixel(&Rgba([250, 250, 250, 255])),
+            Region::Full
+        );
+        assert_eq!(
+            Region::from_pixel(&Rgba([120, 130, 125, 255])),
+            Region::TextureOnly
+        );
+        assert_eq!(
+            Region::from_pixel(&Rgba([230, 20, 10, 255])),
+            Region::ColorOnly
+        );
+        assert_eq!(
+            Region::from_pixel(&Rgba([10, 10, 10, 255])),
+            Region::Exclude
+        );
+        assert_eq!(
+            Region::from_pixel(&Rgba([255, 255, 255, 0])),
+            Region::Exclude
+        );
+    }
+
+    #[test]
+    fn test_region_at_scales_the_mask() {
+        let mask = test_mask();
+        // 4x4 image: each mask pixel covers 2x2 image pixels
+        assert_eq!(mask.region_at(1, 1, 4, 4), Region::Full);
+        assert_eq!(mask.region_at(2, 0, 4, 4), Region::TextureOnly);
+        assert_eq!(mask.region_at(0, 3, 4, 4), Region::ColorOnly);
+        assert_eq!(mask.region_at(3, 3, 4, 4), Region::Exclude);
+        assert!(mask.has_exclusions());
+    }
+
+    #[test]
+    fn test_apply_clears_dropped_regions() {
+        let mask = test_mask();
+        let image = ImageBuffer::from_pixel(4, 4, Rgba([10, 20, 30, 255]));
+
+        let color = mask.apply(&image, Region::has_color);
+        assert!(!is_pixel_transparent(color.get_pixel(0, 0)));
+        assert!(is_pixel_transparent(color.get_pixel(3, 0)));
+        assert!(!is_pixel_transparent(color.get_pixel(0, 3)));
+        assert!(is_pixel_transparent(color.get_pixel(3, 3)));
+
+        let texture = mask.apply(&image, Region::has_texture);
+        assert!(!is_pixel_transparent(texture.get_pixel(3, 0)));
+        assert!(is_pixel_transparent(texture.get_pixel(0, 3)));
+    }
+}
@@ -0,0 +1,262 @@
+//! Backlit appearance simulation
+//!
+//! Renders what the finished print looks like on a light box, so settings and
+//! palettes can be compared without printing. Per output pixel the light
+//! passes (all in linear RGB):
+//!
+//! - the support plate and the plate fill under short stacks (base filament),
+//! - the color stack, whose predicted color is its transmittance, or the
+//!   outline filament over the full stack height,
+//! - the texture relief (texture filament) at its thickness.
+//!
+//! Filament attenuation follows the Beer–Lambert model of the height mode
+//! (see [`height_layer`](crate::lithophane::height_layer)). The result is
+//! normalized so that a white stack under the thinnest texture is white, and
+//! multiplied by the backlight intensity if a backlight model is configured.
+//! The image is rendered at the finer of the two pixel widths in image
+//! orientation; areas without any layer stay transparent.
+
+use crate::color::Rgb;
+use crate::error::Result;
+use crate::image::is_pixel_transparent;
+use crate::lithophane::config::LithophaneConfig;
+use crate::lithophane::height_layer::filament_transmittance;
+use crate::lithophane::texture_layer::texture_thickness;
+use crate::palette::{estimate_transmission_distance, Palette};
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Layer maps of a generated lithophane in print orientation (flipped)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimulationInput<'a> {
+    /// Quantized color map (transparent = no stack)
+    pub color_map: Option<&'a RgbaImage>,
+    /// Outline pixels of the color map (row-major)
+    pub outline: Option<&'a [bool]>,
+    /// Layers each color pixel lacks to the full stack height (row-major)
+    pub missing_layers: Option<&'a [u32]>,
+    /// Color pixels whose missing layers are added to the texture (row-major;
+    /// the others and all pixels without a mask are filled by the plate)
+    pub texture_fill: Option<&'a [bool]>,
+    /// Grayscale texture map
+    pub texture_map: Option<&'a RgbaImage>,
+    /// Whether the full support plate is printed
+    pub plate: bool,
+}
+
+/// Renders the backlit appearance of a lithophane as an image
+pub fn simulate_backlit(
+    input: &SimulationInput,
+    palette: &Palette,
+    config: &LithophaneConfig,
+) -> Result<RgbaImage> {
+    let color_pw = config.color_pixel_width;
+    let texture_pw = config.texture_pixel_width;
+    let (width_mm, height_mm, pixel_mm) = match (input.color_map, input.texture_map) {
+        (Some(color), texture) => {
+            let pixel_mm = if texture.is_some() {
+                color_pw.min(texture_pw)
+            } else {
+                color_pw
+            };
+            (
+                f64::from(color.width()) * color_pw,
+                f64::from(color.height()) * color_pw,
+                pixel_mm,
+            )
+        }
+        (None, Some(texture)) => (
+            f64::from(texture.width()) * texture_pw,
+            f64::from(texture.height()) * texture_pw,
+            texture_pw,
+        ),
+        (None, None) => return Ok(RgbaImage::new(0, 0)),
+    };
+    let width = ((width_mm / pixel_mm).round() as u32).max(1);
+    let height = ((height_mm / pixel_mm).round() as u32).max(1);
+
+    let transmittance = |hex: &str, thickness: f64| -> Result<[f64; 3]> {
+        let color = Rgb::from_hex(hex)?;
+        let td = palette
+            .transmission_distances()
+            .get(hex)
+            .copied()
+            .unwrap_or_else(|| estimate_transmission_distance(color));
+        Ok(filament_transmittance(color, td, thickness))
+    };
+    let layer = config.color_pixel_layer_thickness;
+    let plate = if input.plate {
+        transmittance(palette.base_hex(), config.plate_thickness)?
+    } else {
+        [1.0; 3]
+    };
+    let base_layer = transmittance(palette.base_hex(), layer)?;
+    let texture_unit = transmittance(&config.texture_color, 1.0)?;
+    let outline = transmittance(
+        &config.outline_hex,
+        layer * f64::from(palette.stack_height()),
+    )
+    .unwrap_or([0.0; 3]);
+    let thinnest_texture = if input.texture_map.is_some() {
+        texture_unit.map(|t| t.powf(config.texture_min_thickness))
+    } else {
+        [1.0; 3]
+    };
+    let reference: [f64; 3] = std::array::from_fn(|c| plate[c] * thinnest_texture[c]);
+
+    let intensity = config
+        .backlight
+        .as_ref()
+        .map(|backlight| backlight.intensity_map(width, height, pixel_mm));
+
+    let pixels: Vec<Rgba<u8>> = (0..height)
+        .into_par_iter()
+        .flat_map_iter(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            // Physical position of the pixel centre; y counts up as in the meshes
+            let px = (f64::from(x) + 0.5) * pixel_mm;
+            let py = height_mm - (f64::from(y) + 0.5) * pixel_mm;
+            let mut light = plate;
+            let mut covered = false;
+
+            if let Some(color) = input.color_map {
+                let cx = ((px / color_pw) as u32).min(color.width() - 1);
+                let cy = ((py / color_pw) as u32).min(color.height() - 1);
+                let index = (cy * color.width() + cx) as usize;
+                let pixel = color.get_pixel(cx, cy);
+                let stack = if !is_pixel_transparent(pixel) {
+                    let (r, g, b) = Rgb::new(pixel[0], pixel[1], pixel[2]).to_linear();
+                    Some([r, g, b])
+                } else if input.outline.is_some_and(|mask| mask[index]) {
+                    Some(outline)
+                } else {
+                    None
+                };
+                if let Some(stack) = stack {
+                    covered = true;
+                    let fill = input
+                        .missing_layers
+                        .filter(|_| !input.texture_fill.is_some_and(|mask| mask[index]))
+                        .map_or(0, |missing| missing[index]);
+                    for c in 0..3 {
+                        light[c] *= stack[c] * base_layer[c].powi(fill as i32);
+                    }
+                }
+            }
+
+            if let Some(texture) = input.texture_map {
+                let tx = ((px / texture_pw) as u32).min(texture.width() - 1);
+                let ty = ((py / texture_pw) as u32).min(texture.height() - 1);
+                let mut thickness = texture_thickness(
+                    texture,
+                    tx,
+                    ty,
+                    config.texture_min_thickness,
+                    config.texture_max_thickness,
+                );
+                if thickness > 0.0 {
+                    covered = true;
+                    if let (Some(mask), Some(color), Some(missing)) =
+                        (input.texture_fill, input.color_map, input.missing_layers)
+                    {
+                        let cx = ((px / color_pw) as u32).min(color.width() - 1);
+                        let cy = ((py / color_pw) as u32).min(color.height() - 1);
+                        let index = (cy * color.width() + cx) as usize;
+                        if mask[index] {
+                            thickness += f64::from(missing[index]) * layer;
+                        }
+                    }
+                    for c in 0..3 {
+                        light[c] *= texture_unit[c].powf(thickness);
+                    }
+                }
+            }
+
+            if !covered {
+                return Rgba([0, 0, 0, 0]);
+            }
+            let backlight = intensity
+                .as_ref()
+                .map_or(1.0, |map| map[(y * width + x) as usize]);
+            let [r, g, b]: [f64; 3] =
+                std::array::from_fn(|c| (light[c] / reference[c] * backlight).clamp(0.0, 1.0));
+            let rgb = Rgb::from_linear(r, g, b);
+            Rgba([rgb.r, rgb.g, rgb.b, 255])
+        })
+        .collect();
+
+    let mut image = RgbaImage::new(width, height);
+    for (target, pixel) in image.pixels_mut().zip(pixels) {
+        *target = pixel;
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::{ColorCombi, ColorLayer};
+    use image::ImageBuffer;
+
+    fn test_palette() -> Palette {
+        let mut palette = Palette::new(1);
+        for color in [Rgb::new(255, 255, 255), Rgb::new(200, 0, 0)] {
+            let layer = ColorLayer::new(color.to_hex(), 1, 0.0, 0.0, 0.5);
+            palette.insert_combi(color, ColorCombi::new(layer));
+        }
+        palette
+    }
+
+    fn config() -> LithophaneConfig {
+        LithophaneConfig {
+            color_pixel_width: 1.0,
+            texture_pixel_width: 0.5,
+            ..LithophaneConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_resolution_and_orientation() {
+        // Print orientation: row 0 is the bottom of the image
+        let color = ImageBuffer::from_fn(2, 2, |_, y| {
+            if y == 0 {
+                Rgba([200, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let input = SimulationInput {
+            color_map: Some(&color),
+            plate: true,
+            ..SimulationInput::default()
+        };
+        let image = simulate_backlit(&input, &test_palette(), &config()).unwrap();
+
+        assert_eq!(image.dimensions(), (2, 2));
+        // White stack over the plate is the reference white
+        assert_eq!(*image.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        // The red stack ends up at the bottom of the rendered image
+        assert_eq!(*image.get_pixel(0, 1), Rgba([200, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_thicker_texture_is_darker_and_holes_are_transparent() {
+        let texture = ImageBuffer::from_fn(4, 1, |x, _| match x {
+            0 => Rgba([255, 255, 255, 255]),
+            1 => Rgba([128, 128, 128, 255]),
+            2 => Rgba([0, 0, 0, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        });
+        let input = SimulationInput {
+            texture_map: Some(&texture),
+            ..SimulationInput::default()
+        };
+        let image = simulate_backlit(&input, &test_palette(), &config()).unwrap();
+
+        assert_eq!(image.dimensions(), (4, 1));
+        assert_eq!(image.get_pixel(0, 0)[0], 255);
+        assert!(image.get_pixel(1, 0)[0] < 255);
+        assert!(image.get_pixel(2, 0)[0] < image.get_pixel(1, 0)[0]);
+        assert_eq!(image.get_pixel(3, 0)[3], 0);
+    }
+}
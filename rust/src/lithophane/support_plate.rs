@@ -2,10 +2,14 @@
 //!
 //! Generates base plate for lithophanes
 
+use crate::color::Rgb;
 use crate::error::Result;
+use crate::image::is_pixel_transparent;
 use crate::lithophane::config::LithophaneConfig;
 use crate::lithophane::geometry::{Mesh, Vector3};
+use crate::palette::Palette;
 use image::RgbaImage;
+use rayon::prelude::*;
 
 /// Generates a flat support plate
 pub fn generate_support_plate(image: &RgbaImage, config: &LithophaneConfig) -> Result<Mesh> {
@@ -21,3 +25,67 @@ pub fn generate_support_plate(image: &RgbaImage, config: &LithophaneConfig) -> R
 
     Ok(mesh)
 }
+
+/// Generates plate material under color stacks lower than `stack_height`
+///
+/// Each short stack gets a column of `stack_height - total_layers` layers on
+/// top of the plate; the color layers are raised by the same amount (see
+/// `generate_color_layer`). Transparent pixels are skipped like in the color
+/// layers.
+pub fn generate_plate_fill(
+    image: &RgbaImage,
+    palette: &Palette,
+    stack_height: u32,
+    config: &LithophaneConfig,
+) -> Mesh {
+    let (width, height) = image.dimensions();
+
+    let missing = |x: u32, y: u32| -> u32 {
+        let pixel = image.get_pixel(x, y);
+        if is_pixel_transparent(pixel) {
+            return 0;
+        }
+        palette
+            .get_combi(&Rgb::new(pixel[0], pixel[1], pixel[2]))
+            .map_or(0, |combi| stack_height.saturating_sub(combi.total_layers()))
+    };
+
+    let row_meshes: Vec<Mesh> = (0..height)
+        .into_par_iter()
+        .map(|y| {
+            let mut mesh = Mesh::new();
+            let mut x = 0;
+            while x < width {
+                let layers = missing(x, y);
+                let mut k = 1;
+                while x + k < width && missing(x + k, y) == layers {
+                    k += 1;
+                }
+
+                if layers > 0 {
+                    let cube_width = config.color_pixel_width * k as f64;
+                    let cube_height = config.color_pixel_layer_thickness * layers as f64;
+                    let center = Vector3::new(
+                        x as f64 * config.color_pixel_width + cube_width / 2.0,
+                        (y as f64 + 0.5) * config.color_pixel_width,
+                        cube_height / 2.0,
+                    );
+                    mesh.merge_owned(Mesh::cube(
+                        cube_width,
+                        config.color_pixel_width,
+                        cube_height,
+                        center,
+                    ));
+                }
+                x += k;
+            }
+            mesh
+        })
+        .collect();
+
+    let mut fill = Mesh::new();
+    for row_mesh in row_meshes {
+        fill.merge_owned(row_mesh);
+    }
+    fill
+}
@@ -5,9 +5,11 @@
 
 use crate::color::Rgb;
 use crate::error::Result;
+use crate::image::is_pixel_transparent;
 use crate::lithophane::config::LithophaneConfig;
 use crate::lithophane::geometry::{Mesh, Triangle, Vector3};
-use image::RgbaImage;
+use crate::palette::Palette;
+use image::{Rgba, RgbaImage};
 use rayon::prelude::*;
 
 /// Calculates pixel height based on brightness
@@ -29,16 +31,42 @@ fn get_pixel_height(
     k * (max_thickness - min_thickness) + min_thickness
 }
 
+/// Thickness of a texture pixel in mm; transparent pixels get no thickness
+pub(crate) fn texture_thickness(
+    image: &RgbaImage,
+    x: u32,
+    y: u32,
+    min_thickness: f64,
+    max_thickness: f64,
+) -> f64 {
+    if is_pixel_transparent(image.get_pixel(x, y)) {
+        return 0.0;
+    }
+    get_pixel_height(image, x, y, min_thickness, max_thickness)
+}
+
 /// Generates texture layer mesh
 ///
 /// Based on Java CSGThreadTextureRow
 pub fn generate_texture_layer(image: &RgbaImage, config: &LithophaneConfig) -> Result<Mesh> {
+    generate_texture_layer_with_offset(image, config, |_, _| 0.0)
+}
+
+/// Generates texture layer mesh with an extra thickness per pixel
+///
+/// `offset(x, y)` is added to the brightness-based thickness of the texture
+/// pixel (in mm), e.g. to fill up color stacks lower than the full height.
+pub fn generate_texture_layer_with_offset(
+    image: &RgbaImage,
+    config: &LithophaneConfig,
+    offset: impl Fn(u32, u32) -> f64 + Sync,
+) -> Result<Mesh> {
     let (width, height) = image.dimensions();
 
     // Process rows in parallel
     let row_meshes: Vec<Mesh> = (0..height - 1)
         .into_par_iter()
-        .map(|y| process_texture_row(image, y, width, height, config))
+        .map(|y| process_texture_row(image, y, width, height, config, &offset))
         .collect();
 
     // Merge all row meshes with pre-allocation
@@ -68,6 +96,62 @@ pub fn generate_texture_layer(image: &RgbaImage, config: &LithophaneConfig) -> R
     Ok(final_mesh)
 }
 
+/// Brightens the texture above color stacks by their light attenuation
+///
+/// Light passes the color stack and the texture one after the other, so the
+/// transmitted luminance is the product of both (in linear light). The stack's
+/// predicted color gives its transmittance; each texture pixel is set to
+/// `target / stack` (at most white) so the product matches the source image.
+/// Dark stacks thus get a thin texture instead of a thick one on top.
+///
+/// `scale` converts texture pixel positions to color pixel positions
+/// (texture pixel width / color pixel width). Transparent pixels in either
+/// image and colors without a stack are left unchanged.
+#[must_use]
+pub fn compensate_stack_attenuation(
+    texture: &RgbaImage,
+    color: &RgbaImage,
+    palette: &Palette,
+    scale: f64,
+) -> RgbaImage {
+    let (color_width, color_height) = color.dimensions();
+    let mut result = texture.clone();
+
+    for (x, y, pixel) in result.enumerate_pixels_mut() {
+        if is_pixel_transparent(pixel) {
+            continue;
+        }
+        let cx = ((f64::from(x) * scale) as u32).min(color_width - 1);
+        let cy = ((f64::from(y) * scale) as u32).min(color_height - 1);
+        let stack = color.get_pixel(cx, cy);
+        if is_pixel_transparent(stack) {
+            continue;
+        }
+        // Palette colors are the predicted colors of their stacks
+        let stack_rgb = Rgb::new(stack[0], stack[1], stack[2]);
+        if palette.get_combi(&stack_rgb).is_none() {
+            continue;
+        }
+
+        let (r, g, b) = stack_rgb.to_linear();
+        let stack_transmittance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        if stack_transmittance <= 0.0 {
+            continue;
+        }
+        let (target, _, _) = Rgb::new(pixel[0], pixel[0], pixel[0]).to_linear();
+        let texture_transmittance = (target / stack_transmittance).min(1.0);
+        let gray = Rgb::from_linear(
+            texture_transmittance,
+            texture_transmittance,
+            texture_transmittance,
+        )
+        .r;
+        *pixel = Rgba([gray, gray, gray, 255]);
+    }
+
+    result
+}
+
 /// Processes a single row of quads for the texture layer mesh.
 ///
 /// For each pixel quad (2x2 group of adjacent pixels), generates two triangles
@@ -80,11 +164,19 @@ fn process_texture_row(
     width: u32,
     height: u32,
     config: &LithophaneConfig,
+    offset: &(impl Fn(u32, u32) -> f64 + Sync),
 ) -> Mesh {
     let mut mesh = Mesh::new();
     let pixel_width = config.texture_pixel_width;
     let min_thickness = config.texture_min_thickness;
     let max_thickness = config.texture_max_thickness;
+    let pixel_height = |x, y| {
+        let thickness = texture_thickness(image, x, y, min_thickness, max_thickness);
+        if thickness == 0.0 {
+            return 0.0;
+        }
+        thickness + offset(x, y)
+    };
 
     for x in 0..width - 1 {
         let i = x as f64 * pixel_width;
@@ -92,16 +184,16 @@ fn process_texture_row(
         let i1 = (x + 1) as f64 * pixel_width;
         let j1 = (y + 1) as f64 * pixel_width;
 
-        let h00 = get_pixel_height(image, x, y, min_thickness, max_thickness);
-        let h10 = get_pixel_height(image, x + 1, y, min_thickness, max_thickness);
-        let h01 = get_pixel_height(image, x, y + 1, min_thickness, max_thickness);
-        let h11 = get_pixel_height(image, x + 1, y + 1, min_thickness, max_thickness);
+        let h00 = pixel_height(x, y);
+        let h10 = pixel_height(x + 1, y);
+        let h01 = pixel_height(x, y + 1);
+        let h11 = pixel_height(x + 1, y + 1);
 
         // Create two triangles for this quad
         let t1 = Triangle::new(
             Vector3::new(i, j, h00),
-            Vector3::new(i, j1, h01),
             Vector3::new(i1, j, h10),
+            Vector3::new(i, j1, h01),
         );
 
         let t2 = Triangle::new(
@@ -139,16 +231,16 @@ fn add_left_edge(mesh: &mut Mesh, i: f64, j: f64, j1: f64, h00: f64, h01: f64, z
     ));
     mesh.add_triangle(Triangle::new(
         Vector3::new(i, j, h00),
-        Vector3::new(i, j, z_base),
         Vector3::new(i, j1, z_base),
+        Vector3::new(i, j, z_base),
     ));
 }
 
 fn add_top_edge(mesh: &mut Mesh, i: f64, i1: f64, j: f64, h00: f64, h10: f64, z_base: f64) {
     mesh.add_triangle(Triangle::new(
         Vector3::new(i, j, h00),
-        Vector3::new(i1, j, h10),
         Vector3::new(i1, j, z_base),
+        Vector3::new(i1, j, h10),
     ));
     mesh.add_triangle(Triangle::new(
         Vector3::new(i, j, h00),
@@ -160,8 +252,8 @@ fn add_top_edge(mesh: &mut Mesh, i: f64, i1: f64, j: f64, h00: f64, h10: f64, z_
 fn add_right_edge(mesh: &mut Mesh, i1: f64, j: f64, j1: f64, h10: f64, h11: f64, z_base: f64) {
     mesh.add_triangle(Triangle::new(
         Vector3::new(i1, j, h10),
-        Vector3::new(i1, j1, h11),
         Vector3::new(i1, j1, z_base),
+        Vector3::new(i1, j1, h11),
     ));
     mesh.add_triangle(Triangle::new(
         Vector3::new(i1, j, h10),
@@ -178,8 +270,8 @@ fn add_bottom_edge(mesh: &mut Mesh, i: f64, i1: f64, j1: f64, h01: f64, h11: f64
     ));
     mesh.add_triangle(Triangle::new(
         Vector3::new(i, j1, h01),
-        Vector3::new(i, j1, z_base),
         Vector3::new(i1, j1, z_base),
+        Vector3::new(i, j1, z_base),
     ));
 }
 
@@ -187,7 +279,7 @@ fn add_bottom_edge(mesh: &mut Mesh, i: f64, i1: f64, j1: f64, h01: f64, h11: f64
 mod tests {
     use super::*;
     use approx::assert_relative_eq;
-    use image::{ImageBuffer, Rgba};
+    use image::ImageBuffer;
 
     fn create_uniform_image(width: u32, height: u32, color: [u8; 3]) -> RgbaImage {
         ImageBuffer::from_fn(width, height, |_, _| {
@@ -243,6 +335,32 @@ mod tests {
         assert_eq!(mesh.triangle_count(), 26);
     }
 
+    #[test]
+    fn test_texture_layer_is_outward_facing_solid() {
+        // A flat 3x2 mm slab of the minimum thickness
+        let image = create_uniform_image(4, 3, [255, 255, 255]);
+        let config = LithophaneConfig {
+            texture_pixel_width: 1.0,
+            ..LithophaneConfig::default()
+        };
+        let mesh = generate_texture_layer(&image, &config).unwrap();
+        assert_relative_eq!(mesh.volume(), 3.0 * 2.0 * 0.3, epsilon = 1e-9);
+
+        let centre = Vector3::new(1.5, 1.0, 0.15);
+        for triangle in &mesh.triangles {
+            let face = (triangle.v0 + triangle.v1 + triangle.v2) * (1.0 / 3.0);
+            assert!(triangle.normal().dot(&(face - centre)) > 0.0);
+        }
+
+        // A relief encloses a positive volume as well
+        let relief = ImageBuffer::from_fn(4, 3, |x, y| {
+            let gray = (x * 60 + y * 20) as u8;
+            Rgba([gray, gray, gray, 255])
+        });
+        let mesh = generate_texture_layer(&relief, &config).unwrap();
+        assert!(mesh.volume() > 3.0 * 2.0 * 0.3);
+    }
+
     #[test]
     fn test_texture_heights_monotonic_with_darkness() {
         // Darker pixels should produce taller heights
@@ -259,4 +377,73 @@ mod tests {
             h_light
         );
     }
+
+    #[test]
+    fn test_texture_offset_raises_pixels() {
+        let image = create_uniform_image(2, 2, [255, 255, 255]);
+        let config = LithophaneConfig::default();
+        let max_z = |mesh: &Mesh| {
+            mesh.triangles
+                .iter()
+                .flat_map(|t| [t.v0.z, t.v1.z, t.v2.z])
+                .fold(f64::MIN, f64::max)
+        };
+
+        let flat = generate_texture_layer(&image, &config).unwrap();
+        let raised =
+            generate_texture_layer_with_offset(&image, &config, |x, _| f64::from(x) * 0.2).unwrap();
+
+        assert_relative_eq!(max_z(&flat), 0.3, epsilon = 0.01);
+        assert_relative_eq!(max_z(&raised), 0.5, epsilon = 0.01);
+        assert_eq!(raised.triangle_count(), flat.triangle_count());
+    }
+
+    #[test]
+    fn test_transparent_pixels_have_no_thickness() {
+        let mut image = create_uniform_image(2, 2, [0, 0, 0]);
+        image.put_pixel(1, 1, image::Rgba([0, 0, 0, 0]));
+        let config = LithophaneConfig::default();
+
+        let mesh = generate_texture_layer(&image, &config).unwrap();
+        let (x, y) = (config.texture_pixel_width, config.texture_pixel_width);
+        let corner_z: Vec<f64> = mesh
+            .triangles
+            .iter()
+            .flat_map(|t| [t.v0, t.v1, t.v2])
+            .filter(|v| (v.x - x).abs() < 1e-9 && (v.y - y).abs() < 1e-9)
+            .map(|v| v.z)
+            .collect();
+        assert!(!corner_z.is_empty());
+        assert!(corner_z.iter().all(|&z| z.abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_compensate_stack_attenuation() {
+        use crate::palette::{ColorCombi, ColorLayer};
+
+        let mut palette = Palette::new(1);
+        for color in [Rgb::new(128, 128, 128), Rgb::new(255, 255, 255)] {
+            let layer = ColorLayer::new(color.to_hex(), 1, 0.0, 0.0, 0.5);
+            palette.insert_combi(color, ColorCombi::new(layer));
+        }
+
+        // Columns: grey stack, white stack, color without a stack
+        let color = ImageBuffer::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([128, 128, 128, 255]),
+            1 => Rgba([255, 255, 255, 255]),
+            _ => Rgba([10, 20, 30, 255]),
+        });
+        let texture = create_uniform_image(3, 1, [64, 64, 64]);
+        let result = compensate_stack_attenuation(&texture, &color, &palette, 1.0);
+
+        // Grey stack (~22% transmittance) leaves ~24% for the texture
+        let (grey, _, _) = Rgb::new(128, 128, 128).to_linear();
+        let (target, _, _) = Rgb::new(64, 64, 64).to_linear();
+        let (compensated, _, _) = Rgb::new(result.get_pixel(0, 0)[0], 0, 0).to_linear();
+        assert_relative_eq!(compensated * grey, target, epsilon = 0.01);
+        assert!(result.get_pixel(0, 0)[0] > 64);
+        // A white stack and unknown colors leave the texture unchanged
+        assert_eq!(result.get_pixel(1, 0)[0], 64);
+        assert_eq!(result.get_pixel(2, 0)[0], 64);
+    }
 }
@@ -0,0 +1,454 @@
+//! Persistent on-disk cache for computed palettes
+//!
+//! Computing all color combinations of a large palette and matching every image
+//! color against them is the expensive part of each run. [`PaletteCache`] stores
+//! both results in a cache directory, keyed by a hash of the palette JSON and the
+//! [`PaletteLoaderConfig`], so repeated runs and batch jobs with the same palette
+//! start instantly:
+//!
+//! - the list of `ColorCombi` with their RGB colors (JSON),
+//! - the dense RGB → palette color [`ColorLookup`] filled during quantization,
+//!   as a binary file next to it: one little-endian `u32` per RGB color
+//!   (packed palette color plus a fill flag), loaded in one piece.
+//!
+//! The key covers the palette JSON and every [`PaletteLoaderConfig`] field,
+//! hashed one by one in a fixed order.
+//!
+//! A cache file that cannot be read or was written by another format version is
+//! treated as a miss.
+
+use crate::color::{ColorDistanceMethod, Rgb};
+use crate::error::Result;
+use crate::filament::FilamentProperties;
+use crate::palette::{
+    ColorCombi, ColorLookup, CombinationReport, MixingModel, Palette, PaletteLoaderConfig,
+    PixelCreationMethod, StackingStrategy, DEFAULT_BASE_HEX,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Format version; bump whenever the cached data or its computation changes
+const CACHE_VERSION: u32 = 8;
+
+/// On-disk cache for palettes and quantization lookups
+#[derive(Debug, Clone)]
+pub struct PaletteCache {
+    dir: PathBuf,
+}
+
+/// Serialized form of a cached palette
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    nb_layers: u32,
+    nb_groups: usize,
+    hex_codes: HashMap<String, String>,
+    hex_color_groups: Vec<Vec<String>>,
+    /// (RGB hex code, combination) pairs
+    combis: Vec<(String, ColorCombi)>,
+    /// Statistics of the original generation
+    #[serde(default)]
+    report: Option<CombinationReport>,
+    #[serde(default)]
+    stacking_strategy: StackingStrategy,
+    #[serde(default)]
+    mixing_model: MixingModel,
+    #[serde(default = "default_base_hex")]
+    base_hex: String,
+    #[serde(default)]
+    transmission_distances: HashMap<String, f64>,
+    /// Density, diameter and price per filament hex code
+    #[serde(default)]
+    filament_properties: HashMap<String, FilamentProperties>,
+    /// Distance method the lookup was filled with (None = no lookup file)
+    lookup_method: Option<String>,
+}
+
+impl PaletteCache {
+    /// Creates a cache in the given directory (created on first write)
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Gets the platform cache directory for PIXEstL
+    ///
+    /// Uses `$XDG_CACHE_HOME`, `~/.cache` or `%LOCALAPPDATA%` and falls back to
+    /// the temporary directory.
+    #[must_use]
+    pub fn default_dir() -> PathBuf {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+            .unwrap_or_else(std::env::temp_dir)
+            .join("pixestl")
+    }
+
+    /// Gets the cache directory
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Computes the cache key for a palette JSON and loader configuration
+    ///
+    /// The key is a stable 64-bit FNV-1a hash, so it is identical across runs
+    /// and platforms.
+    #[must_use]
+    pub fn key(json_content: &str, config: &PaletteLoaderConfig) -> String {
+        let mut hash = Fnv1a::new();
+        hash.write(&CACHE_VERSION.to_le_bytes());
+        hash.write(json_content.as_bytes());
+        hash.write(&[0]);
+        hash_config(&mut hash, config);
+        format!("{:016x}", hash.finish())
+    }
+
+    /// Computes the cache key for a palette file
+    pub fn key_for_file(path: &Path, config: &PaletteLoaderConfig) -> Result<String> {
+        let json_content = fs::read_to_string(path)?;
+        Ok(Self::key(&json_content, config))
+    }
+
+    /// Loads a cached palette
+    ///
+    /// Returns `None` on a cache miss or if the cache file is unusable.
+    #[must_use]
+    pub fn load(&self, key: &str) -> Option<Palette> {
+        let file = fs::File::open(self.file_path(key)).ok()?;
+        let cached: CacheFile = serde_json::from_reader(BufReader::new(file)).ok()?;
+        if cached.version != CACHE_VERSION {
+            return None;
+        }
+
+        let mut palette = Palette::new(cached.nb_layers);
+        palette.set_stacking(cached.stacking_strategy, cached.mixing_model);
+        palette.set_base_hex(cached.base_hex);
+        palette.set_transmission_distances(cached.transmission_distances);
+        palette.set_filament_properties(cached.filament_properties);
+        palette.set_hex_codes(cached.hex_codes);
+        palette.set_nb_groups(cached.nb_groups);
+        palette.set_hex_color_groups(cached.hex_color_groups);
+        for (hex, combi) in cached.combis {
+            palette.insert_combi(Rgb::from_hex(&hex).ok()?, combi);
+        }
+        if let Some(report) = cached.report {
+            palette.set_combination_report(report);
+        }
+
+        if let Some(method) = cached.lookup_method {
+            let method: ColorDistanceMethod = method.parse().ok()?;
+            let bytes = fs::read(self.lookup_path(key)).ok()?;
+            let table = bytes
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            palette.set_color_lookup(ColorLookup::from_table(method, table)?);
+        }
+
+        Some(palette)
+    }
+
+    /// Writes a palette and its lookup (if attached) to the cache
+    pub fn store(&self, key: &str, palette: &Palette) -> Result<()> {
+        // Write to temporary files first so readers never see partial data;
+        // the JSON file is written last and refers to the lookup file
+        fs::create_dir_all(&self.dir)?;
+        let lookup_method = match palette.color_lookup() {
+            Some(lookup) => {
+                let lookup = lookup.lock().map_err(|_| {
+                    crate::error::PixestlError::Other(
+                        "Palette color lookup is poisoned".to_string(),
+                    )
+                })?;
+                let path = self.lookup_path(key);
+                let tmp_path = path.with_extension("bin.tmp");
+                {
+                    let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+                    for chunk in lookup.table().chunks(1 << 16) {
+                        let bytes: Vec<u8> = chunk.iter().flat_map(|v| v.to_le_bytes()).collect();
+                        writer.write_all(&bytes)?;
+                    }
+                    writer.flush()?;
+                }
+                fs::rename(&tmp_path, &path)?;
+                Some(lookup.method().as_str().to_string())
+            }
+            None => None,
+        };
+
+        let cached = CacheFile {
+            version: CACHE_VERSION,
+            nb_layers: palette.nb_layers(),
+            nb_groups: palette.nb_groups(),
+            hex_codes: palette.hex_codes_map.clone(),
+            hex_color_groups: palette.hex_color_groups().to_vec(),
+            combis: palette
+                .quantized_colors
+                .iter()
+                .map(|(color, combi)| (color.to_hex(), combi.clone()))
+                .collect(),
+            report: palette.combination_report().cloned(),
+            stacking_strategy: palette.stacking_strategy(),
+            mixing_model: palette.mixing_model(),
+            base_hex: palette.base_hex().to_string(),
+            transmission_distances: palette.transmission_distances().clone(),
+            filament_properties: palette.filament_properties().clone(),
+            lookup_method,
+        };
+
+        let path = self.file_path(key);
+        let tmp_path = path.with_extension("json.tmp");
+        {
+            let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+            serde_json::to_writer(&mut writer, &cached)?;
+            writer.flush()?;
+        }
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    fn file_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("palette-{key}.json"))
+    }
+
+    fn lookup_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("palette-{key}.lookup.bin"))
+    }
+}
+
+/// Hashes every loader setting in a fixed order
+///
+/// Enums are hashed by their CLI names and floats by their bits, so the key
+/// does not depend on the `Debug` output of the configuration.
+fn hash_config(hash: &mut Fnv1a, config: &PaletteLoaderConfig) {
+    // Destructured so a new field cannot be forgotten here
+    let PaletteLoaderConfig {
+        nb_layers,
+        creation_method,
+        color_number,
+        distance_method,
+        prune_delta_e,
+        max_combinations,
+        merge_delta_e,
+        max_filaments_per_stack,
+        min_layers_per_filament,
+        stacking_strategy,
+        mixing_model,
+        min_stack_layers,
+        monochrome,
+    } = config;
+    let creation_method = match creation_method {
+        PixelCreationMethod::Additive => "additive",
+        PixelCreationMethod::Full => "full",
+        PixelCreationMethod::Height => "height",
+    };
+    // -0.0 and 0.0 both mean "off"
+    let float = |value: f64| (value + 0.0).to_bits().to_le_bytes();
+
+    hash.write(&nb_layers.to_le_bytes());
+    hash.write_str(creation_method);
+    hash.write(&(*color_number as u64).to_le_bytes());
+    hash.write_str(distance_method.as_str());
+    hash.write(&float(*prune_delta_e));
+    hash.write(&(*max_combinations as u64).to_le_bytes());
+    hash.write(&float(*merge_delta_e));
+    hash.write(&(*max_filaments_per_stack as u64).to_le_bytes());
+    hash.write(&min_layers_per_filament.to_le_bytes());
+    hash.write_str(stacking_strategy.as_str());
+    hash.write_str(mixing_model.as_str());
+    hash.write(&min_stack_layers.to_le_bytes());
+    hash.write(&[u8::from(*monochrome)]);
+}
+
+fn default_base_hex() -> String {
+    DEFAULT_BASE_HEX.to_string()
+}
+
+/// 64-bit FNV-1a hasher (stable, unlike `std::hash::DefaultHasher`)
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// Writes a string with a terminator, so adjacent strings cannot alias
+    fn write_str(&mut self, value: &str) {
+        self.write(value.as_bytes());
+        self.write(&[0]);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filament::FilamentUsage;
+    use crate::palette::{quantize_image_cached, PaletteLoader};
+    use tempfile::TempDir;
+
+    const PALETTE_JSON: &str = r##"{
+        "#FF0000": { "name": "Red", "layers": { "2": { "H": 0, "S": 100, "L": 60 } } },
+        "#0000FF": { "name": "Blue", "layers": { "2": { "H": 240, "S": 100, "L": 60 } } },
+        "#FFFFFF": { "name": "White", "layers": { "2": { "H": 0, "S": 0, "L": 100 } } }
+    }"##;
+
+    fn config() -> PaletteLoaderConfig {
+        PaletteLoaderConfig {
+            nb_layers: 4,
+            ..PaletteLoaderConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_key_is_stable_and_config_dependent() {
+        let key = PaletteCache::key(PALETTE_JSON, &config());
+        assert_eq!(key, PaletteCache::key(PALETTE_JSON, &config()));
+        assert_eq!(key.len(), 16);
+
+        let other = PaletteLoaderConfig {
+            nb_layers: 6,
+            ..PaletteLoaderConfig::default()
+        };
+        assert_ne!(key, PaletteCache::key(PALETTE_JSON, &other));
+        assert_ne!(key, PaletteCache::key("{}", &config()));
+
+        let variants = [
+            PaletteLoaderConfig {
+                creation_method: PixelCreationMethod::Full,
+                ..config()
+            },
+            PaletteLoaderConfig {
+                distance_method: ColorDistanceMethod::Rgb,
+                ..config()
+            },
+            PaletteLoaderConfig {
+                prune_delta_e: 1.5,
+                ..config()
+            },
+            PaletteLoaderConfig {
+                max_combinations: 10,
+                ..config()
+            },
+            PaletteLoaderConfig {
+                stacking_strategy: StackingStrategy::DiffuserTop,
+                ..config()
+            },
+            PaletteLoaderConfig {
+                monochrome: true,
+                ..config()
+            },
+        ];
+        for variant in &variants {
+            assert_ne!(key, PaletteCache::key(PALETTE_JSON, variant), "{variant:?}");
+        }
+    }
+
+    #[test]
+    fn test_load_missing_is_none() {
+        let dir = TempDir::new().unwrap();
+        let cache = PaletteCache::new(dir.path());
+        assert!(cache.load("0000000000000000").is_none());
+    }
+
+    #[test]
+    fn test_corrupt_file_is_miss() {
+        let dir = TempDir::new().unwrap();
+        let cache = PaletteCache::new(dir.path());
+        fs::write(cache.file_path("abc"), "not json").unwrap();
+        assert!(cache.load("abc").is_none());
+    }
+
+    #[test]
+    fn test_filament_properties_round_trip() {
+        let json = r##"{
+            "#FF0000": { "name": "Red", "density": 1.27, "price_per_kg": 25.0,
+                "layers": { "2": { "H": 0, "S": 100, "L": 60 } } },
+            "#FFFFFF": { "name": "White", "layers": { "2": { "H": 0, "S": 0, "L": 100 } } }
+        }"##;
+        let dir = TempDir::new().unwrap();
+        let cache = PaletteCache::new(dir.path());
+        let key = PaletteCache::key(json, &config());
+
+        let palette = PaletteLoader::load_from_str(json, config()).unwrap();
+        cache.store(&key, &palette).unwrap();
+        let loaded = cache.load(&key).unwrap();
+
+        assert_eq!(loaded.filament_properties(), palette.filament_properties());
+        let red = loaded.filament_properties()["#FF0000"];
+        assert_eq!(red.density, 1.27);
+        assert_eq!(red.price_per_kg, Some(25.0));
+
+        // Weight and cost estimates are the same with the cached palette
+        let usage = |palette: &Palette| {
+            FilamentUsage::from_volume(1000.0, &palette.filament_properties()["#FF0000"])
+        };
+        assert_eq!(usage(&loaded).grams, usage(&palette).grams);
+        assert_eq!(usage(&loaded).cost, usage(&palette).cost);
+        assert!(usage(&loaded).cost.is_some());
+    }
+
+    #[test]
+    fn test_store_and_load_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let cache = PaletteCache::new(dir.path());
+        let key = PaletteCache::key(PALETTE_JSON, &config());
+
+        let mut palette = PaletteLoader::load_from_str(PALETTE_JSON, config()).unwrap();
+        palette.set_color_lookup(ColorLookup::new(ColorDistanceMethod::CieLab));
+
+        // Fill the lookup with a small image
+        let image = vec![vec![Rgb::new(200, 30, 30), Rgb::new(30, 30, 200)]];
+        {
+            let mut lookup = palette.color_lookup().unwrap().lock().unwrap();
+            quantize_image_cached(
+                &image,
+                &palette.colors(),
+                ColorDistanceMethod::CieLab,
+                &mut lookup,
+            )
+            .unwrap();
+        }
+        cache.store(&key, &palette).unwrap();
+
+        let loaded = cache.load(&key).unwrap();
+        assert_eq!(loaded.color_count(), palette.color_count());
+        assert_eq!(loaded.nb_groups(), palette.nb_groups());
+        assert_eq!(loaded.hex_color_groups(), palette.hex_color_groups());
+        for color in palette.colors() {
+            assert_eq!(loaded.get_combi(&color), palette.get_combi(&color));
+        }
+
+        let lookup = loaded.color_lookup().unwrap().lock().unwrap();
+        assert_eq!(lookup.method(), ColorDistanceMethod::CieLab);
+        assert_eq!(lookup.len(), 2);
+        let original = palette.color_lookup().unwrap().lock().unwrap();
+        assert_eq!(
+            lookup.get(&Rgb::new(200, 30, 30)),
+            original.get(&Rgb::new(200, 30, 30))
+        );
+        drop(lookup);
+
+        // A truncated lookup file is a miss
+        let path = cache.lookup_path(&key);
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 4 << 24);
+        fs::write(&path, &bytes[..1024]).unwrap();
+        assert!(cache.load(&key).is_none());
+    }
+}
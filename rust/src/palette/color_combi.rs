@@ -1,7 +1,8 @@
 //! ColorCombi represents a combination of multiple ColorLayers
 
-use super::ColorLayer;
+use super::{ColorLayer, MixingModel, StackingStrategy};
 use crate::color::{Cmyk, Rgb};
+use serde::{Deserialize, Serialize};
 
 /// A combination of color layers that produces a final RGB color
 ///
@@ -10,7 +11,7 @@ use crate::color::{Cmyk, Rgb};
 /// and converting to RGB.
 ///
 /// Based on Java ColorCombi class
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ColorCombi {
     /// List of color layers in this combination
     layers: Vec<ColorLayer>,
@@ -28,6 +29,12 @@ impl ColorCombi {
         Self { layers }
     }
 
+    /// Creates a ColorCombi from layers in the given stacking order
+    #[must_use]
+    pub fn from_layers(layers: Vec<ColorLayer>) -> Self {
+        Self { layers }
+    }
+
     /// Attempts to combine this ColorCombi with a new ColorLayer
     ///
     /// Returns None if:
@@ -79,6 +86,45 @@ impl ColorCombi {
         self.layers.len()
     }
 
+    /// Gets the number of distinct filaments (hex codes) in this combination
+    #[must_use]
+    pub fn distinct_filaments(&self) -> usize {
+        let mut hex_codes: Vec<&str> = self.layers.iter().map(|l| l.hex_code()).collect();
+        hex_codes.sort_unstable();
+        hex_codes.dedup();
+        hex_codes.len()
+    }
+
+    /// Gets the number of filament changes from bottom to top of the stack
+    #[must_use]
+    pub fn tool_changes(&self) -> usize {
+        self.layers
+            .windows(2)
+            .filter(|w| w[0].hex_code() != w[1].hex_code())
+            .count()
+    }
+
+    /// Gets the print cost of this stack as (distinct filaments, tool changes)
+    ///
+    /// Lower is cheaper: fewer filaments make cleaner layers, fewer changes a
+    /// faster print.
+    #[must_use]
+    pub fn cost(&self) -> (usize, usize) {
+        (self.distinct_filaments(), self.tool_changes())
+    }
+
+    /// Compares two stacks by cost, with a deterministic tie-break on the layers
+    #[must_use]
+    pub fn cmp_cost(&self, other: &Self) -> std::cmp::Ordering {
+        self.cost().cmp(&other.cost()).then_with(|| {
+            // Borrowed (hex, count) pairs; no allocation inside sort comparators
+            self.layers
+                .iter()
+                .map(|l| (l.hex_code(), l.layer()))
+                .cmp(other.layers.iter().map(|l| (l.hex_code(), l.layer())))
+        })
+    }
+
     /// Gets the total number of layers (sum of all layer counts)
     ///
     /// Based on Java ColorCombi.getTotalLayers
@@ -98,20 +144,21 @@ impl ColorCombi {
     /// 3. Convert CMYK to RGB
     #[must_use]
     pub fn compute_rgb(&self) -> Rgb {
-        let mut c = 0.0;
-        let mut m = 0.0;
-        let mut y = 0.0;
-        let mut k = 0.0;
+        self.compute_rgb_with(MixingModel::Additive)
+    }
 
-        for layer in &self.layers {
-            c += layer.c();
-            m += layer.m();
-            y += layer.y();
-            k += layer.k();
-        }
+    /// Computes the final RGB color with the given mixing model
+    #[must_use]
+    pub fn compute_rgb_with(&self, model: MixingModel) -> Rgb {
+        let sum = model.mix(&self.layers);
 
         // Clamp to 1.0 maximum
-        let cmyk = Cmyk::new(c.min(1.0), m.min(1.0), y.min(1.0), k.min(1.0));
+        let cmyk = Cmyk::new(
+            sum.c.min(1.0),
+            sum.m.min(1.0),
+            sum.y.min(1.0),
+            sum.k.min(1.0),
+        );
 
         Rgb::from_cmyk(cmyk)
     }
@@ -127,8 +174,8 @@ impl ColorCombi {
 
     /// Factorizes consecutive layers with the same hex code
     ///
-    /// Combines adjacent layers that have the same hex code into a single
-    /// layer with the combined layer count.
+    /// Combines adjacent layers that have the same hex code (and the same CMYK
+    /// values) into a single layer with the combined layer count.
     ///
     /// Based on Java ColorCombi.factorize
     ///
@@ -151,7 +198,9 @@ impl ColorCombi {
             let last_idx = new_layers.len() - 1;
             let last_layer = &new_layers[last_idx];
 
-            if last_layer.hex_code() == layer.hex_code() {
+            // Layers of the same filament with a different layer count (e.g. the
+            // white layers of two AMS groups) have different CMYK values and stay separate
+            if last_layer.hex_code() == layer.hex_code() && last_layer.cmyk() == layer.cmyk() {
                 // Combine with the last layer
                 let combined = last_layer.combine_with(layer);
                 new_layers[last_idx] = combined;
@@ -209,38 +258,27 @@ impl ColorCombi {
         &self.layers
     }
 
-    /// Optimizes white layers by moving them to bottom and top
-    ///
-    /// Based on Java Palette.optimizeWhiteLayer
+    /// Reorders the layers (`order[i]` is the old index of the new i-th layer)
+    pub(crate) fn reorder_layers(&mut self, order: &[usize]) {
+        debug_assert_eq!(order.len(), self.layers.len());
+        self.layers = order.iter().map(|&i| self.layers[i].clone()).collect();
+    }
+
+    /// Arranges the layers with a stacking strategy (bottom to top)
     ///
-    /// # Algorithm
+    /// # Arguments
     ///
-    /// 1. Split layers into: bottom white, middle colored, top white
-    /// 2. Reassemble as: bottom + middle + top
-    pub(crate) fn optimize_white_layers(&mut self, nb_color_pool: usize, _nb_layers: usize) {
-        let mut bottom_white = Vec::new();
-        let mut middle_colored = Vec::new();
-        let mut top_white = Vec::new();
-
-        let mut layer_count = 0;
-
-        for layer in &self.layers {
-            if layer.hex_code() == "#FFFFFF" {
-                if layer_count <= nb_color_pool {
-                    bottom_white.push(layer.clone());
-                } else {
-                    top_white.push(layer.clone());
-                }
-            } else {
-                middle_colored.push(layer.clone());
-            }
-            layer_count += layer.layer() as usize;
-        }
-
-        self.layers.clear();
-        self.layers.extend(bottom_white);
-        self.layers.extend(middle_colored);
-        self.layers.extend(top_white);
+    /// * `strategy` - Stacking strategy
+    /// * `base_hex` - Hex code of the base filament
+    /// * `nb_color_pool` - Colors per AMS group (used by the classic strategy)
+    pub(crate) fn apply_stacking(
+        &mut self,
+        strategy: StackingStrategy,
+        base_hex: &str,
+        nb_color_pool: usize,
+    ) {
+        let layers = std::mem::take(&mut self.layers);
+        self.layers = strategy.arrange(layers, base_hex, nb_color_pool);
     }
 }
 
@@ -383,6 +421,38 @@ mod tests {
         assert_eq!(combi.layers()[0].layer(), 5); // Combined into one layer
     }
 
+    #[test]
+    fn test_cost() {
+        let mut combi = create_empty_combi();
+        combi.add_layer(create_white_layer(1));
+        combi.add_layer(create_red_layer(2));
+        combi.add_layer(create_white_layer(2));
+
+        assert_eq!(combi.distinct_filaments(), 2);
+        assert_eq!(combi.tool_changes(), 2);
+        assert_eq!(combi.cost(), (2, 2));
+
+        let single = ColorCombi::new(create_red_layer(5));
+        assert_eq!(single.cost(), (1, 0));
+        assert_eq!(single.cmp_cost(&combi), std::cmp::Ordering::Less);
+        assert_eq!(combi.cmp_cost(&combi.clone()), std::cmp::Ordering::Equal);
+    }
+
+    #[test]
+    fn test_factorize_keeps_different_cmyk_separate() {
+        let white1 = ColorLayer::from_cmyk("#FFFFFF".to_string(), 1, 0.0, 0.0, 0.0, 0.05);
+        let white3 = ColorLayer::from_cmyk("#FFFFFF".to_string(), 3, 0.0, 0.0, 0.0, 0.15);
+
+        let mut combi = create_empty_combi();
+        combi.add_layer(white1);
+        combi.add_layer(white3);
+
+        combi.factorize();
+
+        assert_eq!(combi.total_colors(), 2);
+        assert_eq!(combi.total_layers(), 4);
+    }
+
     #[test]
     fn test_factorize_non_consecutive() {
         let red = create_red_layer(2);
@@ -419,7 +489,7 @@ mod tests {
     }
 
     #[test]
-    fn test_optimize_white_layers() {
+    fn test_classic_stacking_moves_white_out() {
         let white1 = create_white_layer(1);
         let red = create_red_layer(3);
         let white2 = create_white_layer(1);
@@ -429,7 +499,7 @@ mod tests {
         combi.add_layer(red.clone());
         combi.add_layer(white2.clone());
 
-        combi.optimize_white_layers(2, 5);
+        combi.apply_stacking(StackingStrategy::Classic, "#FFFFFF", 2);
 
         // White layers should be at bottom and top
         assert_eq!(combi.layers()[0].hex_code(), "#FFFFFF"); // Bottom white
@@ -1,6 +1,7 @@
 //! ColorLayer represents a single layer of a filament color in CMYK color space
 
 use crate::color::{Cmyk, Hsl};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
 /// A single color layer with CMYK values
@@ -9,7 +10,7 @@ use std::cmp::Ordering;
 /// The layer count indicates how many physical layers this represents.
 ///
 /// Based on Java ColorLayer class
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ColorLayer {
     /// Hex code of the color (e.g., "#FF0000")
     hex_code: String,
@@ -0,0 +1,586 @@
+//! Perceptual gamut mapping into the achievable palette gamut
+//!
+//! Image colors that lie outside the set of colors the palette can reproduce
+//! (deep saturated reds, bright greens, ...) all snap to the same few boundary
+//! combinations during quantization, which produces flat patches. This module
+//! compresses image colors into the palette gamut *before* quantization so that
+//! gradations survive.
+//!
+//! ## Algorithm
+//!
+//! 1. The achievable gamut is the convex hull of all palette colors in CIELab
+//!    (built incrementally from `Palette::colors()`).
+//! 2. For each image color an anchor point inside the hull is chosen:
+//!    - [`GamutMappingMethod::Lightness`]: a point with the same lightness on the
+//!      palette's neutral axis, so lightness is kept and chroma is reduced.
+//!    - [`GamutMappingMethod::Chroma`]: a point on the neutral axis at the
+//!      lightness of the gamut cusp (the most chromatic palette color) of the
+//!      color's hue. The hue is kept and lightness is traded in towards the cusp,
+//!      so chroma is kept as far as possible.
+//! 3. Along the ray from the anchor through the color, the relative position `r`
+//!    (1.0 = hull boundary) is compressed with a soft knee: colors up to
+//!    [`COMPRESSION_KNEE`] stay untouched, everything beyond is smoothly squeezed
+//!    into the remaining space so that out-of-gamut colors stay distinguishable.
+//! 4. The result is blended with the original color according to the strength.
+
+use crate::color::{CieLab, Rgb};
+use rayon::prelude::*;
+
+/// Relative distance to the hull boundary from which compression starts
+pub const COMPRESSION_KNEE: f64 = 0.8;
+
+/// Numerical tolerance for hull construction and inside tests (in ΔE units)
+const HULL_EPSILON: f64 = 1e-7;
+
+/// Hue resolution of the cusp table (one entry per degree)
+const CUSP_HUE_STEPS: usize = 360;
+/// Lightness samples searched for the cusp of a hue
+const CUSP_LIGHTNESS_STEPS: usize = 64;
+
+/// Strategy used to pull out-of-gamut colors into the palette gamut
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GamutMappingMethod {
+    /// Keep lightness, reduce chroma towards the neutral axis
+    #[default]
+    Lightness,
+    /// Keep hue and as much chroma as possible, move lightness towards the
+    /// gamut cusp of the hue
+    Chroma,
+}
+
+/// A planar hull face with outward normal (`normal · p <= offset` inside)
+#[derive(Debug, Clone)]
+struct Face {
+    vertices: [usize; 3],
+    normal: [f64; 3],
+    offset: f64,
+}
+
+/// Convex hull of a set of CIELab colors
+#[derive(Debug, Clone)]
+pub struct GamutHull {
+    faces: Vec<Face>,
+    centroid: CieLab,
+    l_min: f64,
+    l_max: f64,
+}
+
+impl GamutHull {
+    /// Builds the convex hull of the given colors in CIELab space
+    ///
+    /// Returns `None` if the colors do not span a volume (fewer than four
+    /// colors, or all colors on one plane), in which case no meaningful gamut
+    /// mapping is possible.
+    #[must_use]
+    pub fn new(colors: &[Rgb]) -> Option<Self> {
+        let points: Vec<[f64; 3]> = colors
+            .iter()
+            .map(|c| {
+                let lab = CieLab::from(*c);
+                [lab.l, lab.a, lab.b]
+            })
+            .collect();
+
+        let faces = build_hull(&points)?;
+
+        let n = points.len() as f64;
+        let sum = points.iter().fold([0.0; 3], |acc, p| add(acc, *p));
+        let centroid = CieLab::new(sum[0] / n, sum[1] / n, sum[2] / n);
+
+        let l_min = points.iter().map(|p| p[0]).fold(f64::MAX, f64::min);
+        let l_max = points.iter().map(|p| p[0]).fold(f64::MIN, f64::max);
+
+        Some(Self {
+            faces,
+            centroid,
+            l_min,
+            l_max,
+        })
+    }
+
+    /// Gets the number of hull faces
+    #[must_use]
+    pub fn face_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    /// Gets the mean of all palette colors (always inside the hull)
+    #[must_use]
+    pub fn centroid(&self) -> CieLab {
+        self.centroid
+    }
+
+    /// Checks whether a color lies inside (or on) the hull
+    #[must_use]
+    pub fn contains(&self, lab: &CieLab) -> bool {
+        let p = [lab.l, lab.a, lab.b];
+        self.faces
+            .iter()
+            .all(|f| dot(f.normal, p) - f.offset <= HULL_EPSILON)
+    }
+
+    /// Distance from an interior point to the hull boundary along a unit direction
+    fn exit_distance(&self, origin: [f64; 3], direction: [f64; 3]) -> f64 {
+        self.faces
+            .iter()
+            .filter_map(|f| {
+                let denom = dot(f.normal, direction);
+                if denom > HULL_EPSILON {
+                    Some(((f.offset - dot(f.normal, origin)) / denom).max(0.0))
+                } else {
+                    None
+                }
+            })
+            .fold(f64::MAX, f64::min)
+    }
+}
+
+/// Compresses image colors into the gamut of a palette
+#[derive(Debug, Clone)]
+pub struct GamutMapper {
+    hull: GamutHull,
+    method: GamutMappingMethod,
+    strength: f64,
+    /// Cusp lightness per hue degree (chroma method only)
+    cusps: Vec<f64>,
+}
+
+impl GamutMapper {
+    /// Creates a gamut mapper for the given palette colors
+    ///
+    /// # Arguments
+    ///
+    /// * `palette_colors` - Achievable colors, typically `Palette::colors()`
+    /// * `method` - Lightness- or chroma-preserving mapping
+    /// * `strength` - Blend between original (0.0) and fully mapped (1.0) colors
+    ///
+    /// # Returns
+    ///
+    /// `None` if the palette colors do not span a 3D gamut
+    #[must_use]
+    pub fn new(palette_colors: &[Rgb], method: GamutMappingMethod, strength: f64) -> Option<Self> {
+        let hull = GamutHull::new(palette_colors)?;
+        let cusps = match method {
+            GamutMappingMethod::Lightness => Vec::new(),
+            GamutMappingMethod::Chroma => (0..CUSP_HUE_STEPS)
+                .map(|step| {
+                    let hue = (step as f64 / CUSP_HUE_STEPS as f64) * std::f64::consts::TAU;
+                    cusp_lightness(&hull, hue)
+                })
+                .collect(),
+        };
+        Some(Self {
+            hull,
+            method,
+            strength: strength.clamp(0.0, 1.0),
+            cusps,
+        })
+    }
+
+    /// Gets the underlying palette hull
+    #[must_use]
+    pub fn hull(&self) -> &GamutHull {
+        &self.hull
+    }
+
+    /// Maps a single color into the palette gamut
+    #[must_use]
+    pub fn map_color(&self, color: Rgb) -> Rgb {
+        if self.strength <= 0.0 {
+            return color;
+        }
+
+        let lab = CieLab::from(color);
+        let anchor = self.anchor_for(&lab);
+        let p = [lab.l, lab.a, lab.b];
+        let delta = sub(p, anchor);
+        let dist = length(delta);
+        if dist < HULL_EPSILON {
+            return color;
+        }
+
+        let direction = scale(delta, 1.0 / dist);
+        let boundary = self.hull.exit_distance(anchor, direction);
+        if boundary <= HULL_EPSILON || boundary == f64::MAX {
+            return color;
+        }
+
+        let r = dist / boundary;
+        let compressed = compress(r);
+        if (compressed - r).abs() < f64::EPSILON {
+            return color;
+        }
+
+        let mapped = add(anchor, scale(direction, compressed * boundary));
+        let blended = add(p, scale(sub(mapped, p), self.strength));
+
+        CieLab::new(blended[0], blended[1], blended[2]).to_rgb()
+    }
+
+    /// Maps every pixel of an image (rows processed in parallel)
+    #[must_use]
+    pub fn map_image(&self, image_data: &[Vec<Rgb>]) -> Vec<Vec<Rgb>> {
+        image_data
+            .par_iter()
+            .map(|row| row.iter().map(|pixel| self.map_color(*pixel)).collect())
+            .collect()
+    }
+
+    /// Chooses the interior point the color is compressed towards
+    fn anchor_for(&self, lab: &CieLab) -> [f64; 3] {
+        let center = self.hull.centroid;
+        let mut anchor = match self.method {
+            GamutMappingMethod::Lightness => CieLab::new(
+                lab.l.clamp(self.hull.l_min, self.hull.l_max),
+                center.a,
+                center.b,
+            ),
+            GamutMappingMethod::Chroma => {
+                // Hue around the neutral axis; the anchor keeps the color's hue plane
+                let hue = (lab.b - center.b)
+                    .atan2(lab.a - center.a)
+                    .rem_euclid(std::f64::consts::TAU);
+                let step = ((hue / std::f64::consts::TAU * CUSP_HUE_STEPS as f64).round() as usize)
+                    % CUSP_HUE_STEPS;
+                CieLab::new(self.cusps[step], center.a, center.b)
+            }
+        };
+
+        // Near the lightness extremes the hull narrows; walk towards the
+        // center until the anchor is strictly inside.
+        for _ in 0..32 {
+            if self.hull.contains(&anchor) {
+                break;
+            }
+            anchor = CieLab::new(
+                (anchor.l + center.l) / 2.0,
+                (anchor.a + center.a) / 2.0,
+                (anchor.b + center.b) / 2.0,
+            );
+        }
+
+        [anchor.l, anchor.a, anchor.b]
+    }
+}
+
+/// Lightness at which the hull reaches its highest chroma for a hue
+///
+/// Samples the neutral axis of the hull and measures the distance to the
+/// boundary in the hue direction; falls back to the centroid lightness.
+fn cusp_lightness(hull: &GamutHull, hue: f64) -> f64 {
+    let center = hull.centroid;
+    let direction = [0.0, hue.cos(), hue.sin()];
+    (1..CUSP_LIGHTNESS_STEPS)
+        .map(|i| hull.l_min + (hull.l_max - hull.l_min) * i as f64 / CUSP_LIGHTNESS_STEPS as f64)
+        .filter(|&l| hull.contains(&CieLab::new(l, center.a, center.b)))
+        .map(|l| (l, hull.exit_distance([l, center.a, center.b], direction)))
+        .filter(|&(_, chroma)| chroma < f64::MAX)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(center.l, |(l, _)| l)
+}
+
+/// Soft-knee compression of the relative boundary distance into `[0, 1)`
+fn compress(r: f64) -> f64 {
+    if r <= COMPRESSION_KNEE {
+        r
+    } else {
+        let span = 1.0 - COMPRESSION_KNEE;
+        let x = (r - COMPRESSION_KNEE) / span;
+        COMPRESSION_KNEE + span * x / (1.0 + x)
+    }
+}
+
+/// Builds the convex hull faces with an incremental algorithm
+///
+/// Starts from a non-degenerate tetrahedron and adds the remaining points one
+/// by one, replacing all faces visible from the new point by a fan connecting
+/// the horizon edges to it.
+fn build_hull(points: &[[f64; 3]]) -> Option<Vec<Face>> {
+    if points.len() < 4 {
+        return None;
+    }
+
+    // Initial tetrahedron: extreme points that span a volume
+    let i0 = (0..points.len()).min_by(|&a, &b| points[a][0].total_cmp(&points[b][0]))?;
+    let i1 = farthest(points, |p| length(sub(*p, points[i0])))?;
+    let axis = sub(points[i1], points[i0]);
+    let i2 = farthest(points, |p| length(cross(axis, sub(*p, points[i0]))))?;
+    let plane_normal = cross(axis, sub(points[i2], points[i0]));
+    let i3 = farthest(points, |p| dot(plane_normal, sub(*p, points[i0])).abs())?;
+
+    let extent = length(axis);
+    if extent < HULL_EPSILON
+        || length(plane_normal) < HULL_EPSILON * extent
+        || dot(plane_normal, sub(points[i3], points[i0])).abs()
+            < HULL_EPSILON * length(plane_normal) * extent
+    {
+        return None;
+    }
+
+    let interior = scale(
+        add(add(points[i0], points[i1]), add(points[i2], points[i3])),
+        0.25,
+    );
+
+    let mut faces: Vec<Face> = [[i0, i1, i2], [i0, i1, i3], [i0, i2, i3], [i1, i2, i3]]
+        .iter()
+        .filter_map(|v| make_face(points, *v, interior))
+        .collect();
+
+    let initial = [i0, i1, i2, i3];
+    for (idx, p) in points.iter().enumerate() {
+        if initial.contains(&idx) {
+            continue;
+        }
+
+        let visible: Vec<bool> = faces
+            .iter()
+            .map(|f| dot(f.normal, *p) - f.offset > HULL_EPSILON)
+            .collect();
+        if !visible.iter().any(|&v| v) {
+            continue;
+        }
+
+        // Directed edges of all visible faces; an edge is on the horizon if its
+        // reverse does not belong to another visible face.
+        let mut edges = std::collections::HashSet::new();
+        for (face, _) in faces.iter().zip(&visible).filter(|(_, v)| **v) {
+            let [a, b, c] = face.vertices;
+            edges.insert((a, b));
+            edges.insert((b, c));
+            edges.insert((c, a));
+        }
+        let horizon: Vec<(usize, usize)> = edges
+            .iter()
+            .filter(|(a, b)| !edges.contains(&(*b, *a)))
+            .copied()
+            .collect();
+
+        let mut kept: Vec<Face> = faces
+            .into_iter()
+            .zip(visible)
+            .filter(|(_, v)| !*v)
+            .map(|(f, _)| f)
+            .collect();
+        kept.extend(
+            horizon
+                .into_iter()
+                .filter_map(|(a, b)| make_face(points, [a, b, idx], interior)),
+        );
+        faces = kept;
+    }
+
+    Some(faces)
+}
+
+/// Creates a face with its normal oriented away from the interior point
+///
+/// The vertex order is adjusted to match the normal (counter-clockwise seen
+/// from outside), which the horizon detection in [`build_hull`] relies on.
+fn make_face(points: &[[f64; 3]], vertices: [usize; 3], interior: [f64; 3]) -> Option<Face> {
+    let [a, b, c] = vertices.map(|i| points[i]);
+    let mut normal = cross(sub(b, a), sub(c, a));
+    let len = length(normal);
+    if len < HULL_EPSILON {
+        return None;
+    }
+    normal = scale(normal, 1.0 / len);
+    let mut offset = dot(normal, a);
+    let mut vertices = vertices;
+    if dot(normal, interior) > offset {
+        normal = scale(normal, -1.0);
+        offset = -offset;
+        vertices.swap(1, 2);
+    }
+    Some(Face {
+        vertices,
+        normal,
+        offset,
+    })
+}
+
+fn farthest(points: &[[f64; 3]], metric: impl Fn(&[f64; 3]) -> f64) -> Option<usize> {
+    (0..points.len()).max_by(|&a, &b| metric(&points[a]).total_cmp(&metric(&points[b])))
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [f64; 3], s: f64) -> [f64; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Muted palette: grays plus desaturated primaries
+    fn muted_palette() -> Vec<Rgb> {
+        vec![
+            Rgb::new(30, 30, 30),
+            Rgb::new(230, 230, 230),
+            Rgb::new(128, 128, 128),
+            Rgb::new(180, 90, 90),
+            Rgb::new(90, 160, 90),
+            Rgb::new(90, 90, 180),
+            Rgb::new(190, 180, 90),
+        ]
+    }
+
+    #[test]
+    fn test_hull_degenerate_returns_none() {
+        // Grays only: all colors on the L axis
+        let grays: Vec<Rgb> = (0..10).map(|i| Rgb::new(i * 25, i * 25, i * 25)).collect();
+        assert!(GamutHull::new(&grays).is_none());
+        assert!(GamutHull::new(&[Rgb::new(0, 0, 0)]).is_none());
+    }
+
+    #[test]
+    fn test_hull_contains_palette_colors() {
+        let palette = muted_palette();
+        let hull = GamutHull::new(&palette).unwrap();
+
+        assert!(hull.face_count() >= 4);
+        for color in &palette {
+            assert!(hull.contains(&CieLab::from(*color)), "{color} outside hull");
+        }
+        assert!(hull.contains(&hull.centroid()));
+        assert!(!hull.contains(&CieLab::from(Rgb::new(255, 0, 0))));
+    }
+
+    #[test]
+    fn test_map_color_moves_out_of_gamut_color_inside() {
+        let palette = muted_palette();
+        let mapper = GamutMapper::new(&palette, GamutMappingMethod::Lightness, 1.0).unwrap();
+
+        let red = Rgb::new(255, 0, 0);
+        let mapped = mapper.map_color(red);
+
+        assert_ne!(mapped, red);
+        let center = mapper.hull().centroid();
+        assert!(CieLab::from(mapped).delta_e(&center) < CieLab::from(red).delta_e(&center));
+    }
+
+    #[test]
+    fn test_lightness_method_preserves_lightness() {
+        let palette = muted_palette();
+        let mapper = GamutMapper::new(&palette, GamutMappingMethod::Lightness, 1.0).unwrap();
+
+        let green = Rgb::new(60, 170, 40);
+        let before = CieLab::from(green);
+        let after = CieLab::from(mapper.map_color(green));
+
+        assert!((before.l - after.l).abs() < 2.0);
+        let chroma = |c: &CieLab| (c.a * c.a + c.b * c.b).sqrt();
+        assert!(chroma(&after) < chroma(&before));
+    }
+
+    #[test]
+    fn test_chroma_method_keeps_hue_and_more_chroma() {
+        let palette = muted_palette();
+        let chroma_mapper = GamutMapper::new(&palette, GamutMappingMethod::Chroma, 1.0).unwrap();
+        let lightness_mapper =
+            GamutMapper::new(&palette, GamutMappingMethod::Lightness, 1.0).unwrap();
+        let center = chroma_mapper.hull().centroid();
+        let chroma = |c: &CieLab| (c.a - center.a).hypot(c.b - center.b);
+        let hue = |c: &CieLab| (c.b - center.b).atan2(c.a - center.a);
+
+        for color in [
+            Rgb::new(255, 0, 0),
+            Rgb::new(60, 170, 40),
+            Rgb::new(0, 0, 255),
+            Rgb::new(255, 255, 0),
+        ] {
+            let before = CieLab::from(color);
+            let by_chroma = CieLab::from(chroma_mapper.map_color(color));
+            let by_lightness = CieLab::from(lightness_mapper.map_color(color));
+
+            // Tolerance for sRGB rounding and the sampled cusp lightness
+            assert!(
+                chroma(&by_chroma) >= chroma(&by_lightness) - 0.5,
+                "{color}: chroma {} < {}",
+                chroma(&by_chroma),
+                chroma(&by_lightness)
+            );
+            // sRGB rounding shifts the hue slightly
+            let shift = (hue(&by_chroma) - hue(&before)).abs();
+            assert!(
+                shift.min(std::f64::consts::TAU - shift) < 0.1,
+                "{color}: hue moved"
+            );
+        }
+    }
+
+    #[test]
+    fn test_zero_strength_is_identity() {
+        let palette = muted_palette();
+        let mapper = GamutMapper::new(&palette, GamutMappingMethod::Chroma, 0.0).unwrap();
+
+        let red = Rgb::new(255, 0, 0);
+        assert_eq!(mapper.map_color(red), red);
+    }
+
+    #[test]
+    fn test_center_color_unchanged() {
+        let palette = muted_palette();
+        let mapper = GamutMapper::new(&palette, GamutMappingMethod::Chroma, 1.0).unwrap();
+
+        let gray = Rgb::new(128, 128, 128);
+        assert_eq!(mapper.map_color(gray), gray);
+    }
+
+    #[test]
+    fn test_out_of_gamut_gradations_stay_distinct() {
+        let palette = muted_palette();
+        let mapper = GamutMapper::new(&palette, GamutMappingMethod::Lightness, 1.0).unwrap();
+
+        let a = mapper.map_color(Rgb::new(255, 0, 0));
+        let b = mapper.map_color(Rgb::new(200, 0, 0));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_compress_is_monotonic_and_bounded() {
+        let mut last = 0.0;
+        for i in 0..100 {
+            let r = f64::from(i) * 0.05;
+            let c = compress(r);
+            assert!(c >= last);
+            assert!(c < 1.0);
+            last = c;
+        }
+        assert_eq!(compress(0.5), 0.5);
+    }
+
+    #[test]
+    fn test_map_image_preserves_dimensions() {
+        let palette = muted_palette();
+        let mapper = GamutMapper::new(&palette, GamutMappingMethod::Lightness, 0.5).unwrap();
+
+        let image = vec![vec![Rgb::new(255, 0, 0); 3]; 2];
+        let mapped = mapper.map_image(&image);
+        assert_eq!(mapped.len(), 2);
+        assert_eq!(mapped[0].len(), 3);
+    }
+}
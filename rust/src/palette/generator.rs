@@ -1,22 +1,155 @@
-//! Recursive ColorCombi generator algorithm
+//! ColorCombi generator for large palettes
 //!
-//! This module implements the complex recursive algorithm for generating
-//! all possible color combinations from a palette of ColorLayers.
+//! Generates all stacks of filament layers whose layer counts add up to the
+//! target number of layers (or, with variable depth, to at least
+//! [`GeneratorOptions::min_stack_layers`]). Each filament appears at most once
+//! per stack, with one of the layer counts defined in the palette.
 //!
-//! Based on Java Palette.createMultiCombi and computeCombination methods
+//! The Java implementation (Palette.createMultiCombi / computeCombination)
+//! recursed over every ordering of the layers and cloned a ColorCombi at each
+//! step. This generator instead enumerates the integer partitions of the
+//! target over interned filament IDs:
+//!
+//! - every set of (filament, layer count) pairs is visited exactly once,
+//! - branches whose remaining filaments cannot fill the stack are cut early,
+//! - the branches are processed in parallel with rayon,
+//! - stacks with too many filaments or too thin filament runs are never built,
+//! - near-duplicate colors (ΔE below a threshold) are pruned while generating;
+//!   this is the only color pruning, a stack is never dropped for being darker
+//!   or more expensive than another one,
+//! - an optional hard cap bounds memory and runtime; hitting it is reported.
+//!
+//! There is no cap by default. With a cap, the branches run in fixed-size
+//! batches and are merged in enumeration order, so a truncated result always
+//! holds the same combinations: the first ones that survive pruning. Each
+//! branch only keeps what is left of the cap when its batch starts.
+//!
+//! Multi-group palettes (AMS) combine the per-group results with
+//! [`combine_groups`], which applies the same pruning and cap to the
+//! cartesian product.
 
 use super::{ColorCombi, ColorLayer};
+use crate::color::{CieLab, Cmyk, Rgb};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
-/// Generates all valid ColorCombi combinations from a list of ColorLayers
-///
-/// Based on Java Palette.createMultiCombi
+/// Default hard cap for the number of generated combinations (0 = no limit)
+pub const DEFAULT_MAX_COMBINATIONS: usize = 0;
+
+/// Number of branches run in parallel at a time when a cap is set
 ///
-/// # Algorithm
+/// Fixed (not the thread count) so that a truncated result does not depend
+/// on the machine.
+const CAPPED_BATCH_SIZE: usize = 64;
+
+/// Options controlling combination generation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeneratorOptions {
+    /// Combinations closer than this ΔE (CIE76) to an already kept combination
+    /// are dropped (0.0 = keep all)
+    pub prune_delta_e: f64,
+    /// Hard cap for the number of kept combinations (0 = no limit)
+    pub max_combinations: usize,
+    /// Maximum number of distinct filaments in one stack (0 = no limit)
+    pub max_filaments_per_stack: usize,
+    /// Minimum number of consecutive layers of a filament in a stack (0 = no limit)
+    pub min_layers_per_filament: u32,
+    /// Minimum total layers of a stack; stacks between this and the target are
+    /// generated too (0 = exactly the target)
+    pub min_stack_layers: u32,
+}
+
+impl GeneratorOptions {
+    /// Gets the effective cap (`usize::MAX` if unlimited)
+    fn cap(&self) -> usize {
+        if self.max_combinations == 0 {
+            usize::MAX
+        } else {
+            self.max_combinations
+        }
+    }
+
+    /// Gets the effective filament limit (`usize::MAX` if unlimited)
+    fn max_filaments(&self) -> usize {
+        if self.max_filaments_per_stack == 0 {
+            usize::MAX
+        } else {
+            self.max_filaments_per_stack
+        }
+    }
+
+    /// Gets how many layers a stack may stay below the target
+    fn depth_slack(&self, nb_layers_target: u32) -> u32 {
+        if self.min_stack_layers == 0 {
+            0
+        } else {
+            nb_layers_target.saturating_sub(self.min_stack_layers.max(1))
+        }
+    }
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        Self {
+            prune_delta_e: 0.0,
+            max_combinations: DEFAULT_MAX_COMBINATIONS,
+            max_filaments_per_stack: 0,
+            min_layers_per_filament: 0,
+            min_stack_layers: 0,
+        }
+    }
+}
+
+/// Statistics about a combination generation run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CombinationReport {
+    /// Number of candidate combinations visited
+    pub generated: usize,
+    /// Number of candidates dropped as near-duplicates
+    pub pruned: usize,
+    /// Number of combinations kept
+    pub kept: usize,
+    /// Whether the hard cap was reached (the result is incomplete)
+    pub truncated: bool,
+    /// The hard cap in effect
+    pub cap: usize,
+    /// Number of combinations merged into a cheaper, visually identical stack
+    #[serde(default)]
+    pub merged: usize,
+}
+
+impl CombinationReport {
+    /// Adds the counts of another report (e.g. of another group)
+    pub fn merge(&mut self, other: &Self) {
+        self.generated += other.generated;
+        self.pruned += other.pruned;
+        self.kept += other.kept;
+        self.truncated |= other.truncated;
+        self.cap = self.cap.max(other.cap);
+        self.merged += other.merged;
+    }
+}
+
+impl fmt::Display for CombinationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} generated, {} pruned as near-duplicates, {} kept",
+            self.generated, self.pruned, self.kept
+        )?;
+        if self.truncated {
+            write!(f, " (truncated at the limit of {})", self.cap)?;
+        }
+        Ok(())
+    }
+}
+
+/// Generates all valid ColorCombi combinations from a list of ColorLayers
 ///
-/// 1. For each ColorLayer in the list:
-///    - Create a ColorCombi with just that layer
-///    - Recursively compute all valid combinations
-/// 2. Filter to only combinations with exact layer count
+/// Convenience wrapper around [`generate_combis`] with default options (no
+/// pruning); the report is discarded.
 ///
 /// # Arguments
 ///
@@ -32,127 +165,456 @@ pub fn create_multi_combi(
     color_layers: &[ColorLayer],
     nb_layers_target: u32,
 ) -> Vec<ColorCombi> {
-    let mut color_combi_list = Vec::new();
-
-    for (i, color_layer) in color_layers.iter().enumerate() {
-        // Skip if not in restriction list
-        if let Some(restrict) = restrict_colors {
-            if !restrict.contains(&color_layer.hex_code().to_string()) {
-                continue;
-            }
-        }
-
-        // Create base combination
-        let base_combi = ColorCombi::new(color_layer.clone());
-        color_combi_list.push(base_combi.clone());
-
-        // Recursively compute combinations if more layers available
-        if i + 1 < color_layers.len() {
-            let recursive_combis =
-                compute_combination(restrict_colors, &base_combi, color_layers, nb_layers_target);
-            color_combi_list.extend(recursive_combis);
-        }
-    }
-
-    // Filter to only exact layer count
-    color_combi_list
-        .into_iter()
-        .filter(|c| c.total_layers() == nb_layers_target)
-        .collect()
+    generate_combis(
+        restrict_colors,
+        color_layers,
+        nb_layers_target,
+        &GeneratorOptions::default(),
+    )
+    .0
 }
 
-/// Recursively computes all valid combinations by adding layers
+/// Generates all stacks with exactly `nb_layers_target` layers
 ///
-/// Based on Java Palette.computeCombination
+/// With [`GeneratorOptions::min_stack_layers`] set, shorter stacks down to
+/// that many layers are generated as well.
+///
+/// The layers of each generated ColorCombi are ordered lightest first (the
+/// reverse of the K-descending order of `color_layers`).
 ///
 /// # Arguments
 ///
 /// * `restrict_colors` - Optional list of hex codes to restrict to
-/// * `current_combi` - The current ColorCombi being built
-/// * `color_layers` - Available color layers to add
-/// * `nb_layers_max` - Maximum number of layers allowed
+/// * `color_layers` - Available color layers (one entry per filament and layer count)
+/// * `nb_layers_target` - Target number of layers
+/// * `options` - Pruning threshold, hard cap and stack constraints
 ///
 /// # Returns
 ///
-/// Vector of all valid ColorCombis that can be formed
-fn compute_combination(
+/// The kept combinations (in deterministic order) and a report
+pub fn generate_combis(
     restrict_colors: Option<&[String]>,
-    current_combi: &ColorCombi,
     color_layers: &[ColorLayer],
-    nb_layers_max: u32,
-) -> Vec<ColorCombi> {
-    let mut color_combi_list = Vec::new();
+    nb_layers_target: u32,
+    options: &GeneratorOptions,
+) -> (Vec<ColorCombi>, CombinationReport) {
+    let restrict: Option<HashSet<&str>> =
+        restrict_colors.map(|r| r.iter().map(String::as_str).collect());
+    let filaments = intern_filaments(
+        color_layers,
+        restrict.as_ref(),
+        nb_layers_target,
+        options.min_layers_per_filament,
+    );
+
+    // Maximum number of layers the filaments from index i onwards can contribute
+    let mut suffix_max = vec![0u32; filaments.len() + 1];
+    for i in (0..filaments.len()).rev() {
+        let max = filaments[i].iter().map(|o| o.count).max().unwrap_or(0);
+        suffix_max[i] = suffix_max[i + 1] + max;
+    }
+
+    let search = Search {
+        color_layers,
+        filaments: &filaments,
+        suffix_max: &suffix_max,
+        slack: options.depth_slack(nb_layers_target),
+        options,
+    };
+
+    // One branch per (first filament, layer count)
+    let roots: Vec<(usize, LayerOption)> = filaments
+        .iter()
+        .enumerate()
+        .flat_map(|(f, options)| options.iter().map(move |o| (f, *o)))
+        .collect();
+
+    let run = |root: usize, branch: &mut Branch| {
+        let (f, option) = roots[root];
+        let mut chosen = Vec::new();
+        search.choose(
+            f,
+            option,
+            nb_layers_target - option.count,
+            &mut chosen,
+            branch,
+        );
+    };
+
+    run_branches(roots.len(), options, run, |entries| {
+        let mut entries = entries.to_vec();
+        entries.sort_unstable_by(|a, b| b.cmp(a));
+        ColorCombi::from_layers(entries.iter().map(|&e| color_layers[e].clone()).collect())
+    })
+}
+
+/// Combines the combinations of several AMS groups (cartesian product)
+///
+/// Each result stacks one combination of every group, in group order. The
+/// product is built group by group; near-duplicates are pruned and the cap is
+/// applied after every step. Products with more distinct filaments than
+/// allowed are skipped.
+///
+/// # Arguments
+///
+/// * `groups` - Combinations per group (must not be empty)
+/// * `options` - Pruning threshold, hard cap and stack constraints
+pub fn combine_groups(
+    groups: &[Vec<ColorCombi>],
+    options: &GeneratorOptions,
+) -> (Vec<ColorCombi>, CombinationReport) {
+    let mut report = CombinationReport {
+        cap: options.max_combinations,
+        ..CombinationReport::default()
+    };
+    let Some((first, rest)) = groups.split_first() else {
+        return (Vec::new(), report);
+    };
 
-    for (i, color_layer) in color_layers.iter().enumerate() {
-        // Skip if not in restriction list
-        if let Some(restrict) = restrict_colors {
-            if !restrict.contains(&color_layer.hex_code().to_string()) {
-                continue;
+    let mut current: Vec<(ColorCombi, Cmyk)> =
+        first.iter().map(|c| (c.clone(), cmyk_sum(c))).collect();
+    let max_filaments = options.max_filaments();
+
+    for next in rest {
+        let next_sums: Vec<Cmyk> = next.iter().map(cmyk_sum).collect();
+        let next_hex: Vec<Vec<&str>> = next.iter().map(hex_codes).collect();
+        let run = |i: usize, branch: &mut Branch| {
+            let (left, left_sum) = &current[i];
+            let left_hex = hex_codes(left);
+            for (j, right_sum) in next_sums.iter().enumerate() {
+                if branch.full {
+                    break;
+                }
+                if max_filaments != usize::MAX && union_len(&left_hex, &next_hex[j]) > max_filaments
+                {
+                    continue;
+                }
+                let sum = add_cmyk(left_sum, right_sum);
+                branch.offer(vec![i, j], &sum);
             }
-        }
+        };
 
-        let layer_count = color_layer.layer();
+        let (combined, step) = run_branches(current.len(), options, run, |pair| {
+            let (left, left_sum) = &current[pair[0]];
+            (
+                left.combine_with_combi(&next[pair[1]]),
+                add_cmyk(left_sum, &next_sums[pair[1]]),
+            )
+        });
+        report.generated += step.generated;
+        report.pruned += step.pruned;
+        report.truncated |= step.truncated;
+        current = combined;
+    }
+
+    report.kept = current.len();
+    (current.into_iter().map(|(c, _)| c).collect(), report)
+}
+
+/// One selectable layer count of a filament
+#[derive(Debug, Clone, Copy)]
+struct LayerOption {
+    count: u32,
+    /// Index into `color_layers`
+    entry: usize,
+}
+
+/// Groups the color layers by filament (interned by first appearance)
+///
+/// Layer counts below `min_layers` are left out, so every filament run in a
+/// generated stack is at least that thick.
+fn intern_filaments(
+    color_layers: &[ColorLayer],
+    restrict: Option<&HashSet<&str>>,
+    nb_layers_target: u32,
+    min_layers: u32,
+) -> Vec<Vec<LayerOption>> {
+    let mut ids: HashMap<&str, u16> = HashMap::new();
+    let mut filaments: Vec<Vec<LayerOption>> = Vec::new();
 
-        // Skip if would exceed max layers
-        if current_combi.total_layers() + layer_count > nb_layers_max {
+    for (entry, layer) in color_layers.iter().enumerate() {
+        if restrict.is_some_and(|r| !r.contains(layer.hex_code())) {
+            continue;
+        }
+        if layer.layer() == 0 || layer.layer() < min_layers || layer.layer() > nb_layers_target {
             continue;
         }
+        let id = *ids.entry(layer.hex_code()).or_insert_with(|| {
+            filaments.push(Vec::new());
+            (filaments.len() - 1) as u16
+        });
+        filaments[id as usize].push(LayerOption {
+            count: layer.layer(),
+            entry,
+        });
+    }
+
+    filaments
+}
 
-        // Skip if would have too many unique colors (optimization)
-        // Note: This is a heuristic from the Java code
-        if current_combi.total_colors() >= color_layers.len() {
-            break;
+/// Shared state of the partition enumeration
+struct Search<'a> {
+    color_layers: &'a [ColorLayer],
+    filaments: &'a [Vec<LayerOption>],
+    suffix_max: &'a [u32],
+    /// Layers a stack may stay below the target (variable depth)
+    slack: u32,
+    options: &'a GeneratorOptions,
+}
+
+impl Search<'_> {
+    /// Adds `option` of filament `f` to the stack, offers it and continues
+    ///
+    /// `remaining` is the number of layers still missing after the option.
+    fn choose(
+        &self,
+        f: usize,
+        option: LayerOption,
+        remaining: u32,
+        chosen: &mut Vec<usize>,
+        branch: &mut Branch,
+    ) {
+        if branch.full {
+            return;
+        }
+        chosen.push(option.entry);
+        if remaining <= self.slack {
+            let sum = self.entries_sum(chosen);
+            branch.offer(chosen.clone(), &sum);
         }
+        if remaining > 0 {
+            self.descend(f + 1, remaining, chosen, branch);
+        }
+        chosen.pop();
+    }
 
-        // Try to combine
-        if let Some(new_combi) =
-            current_combi.combine_with_layer(color_layer.clone(), nb_layers_max)
+    /// Chooses a layer count (or nothing) for filament `f` and the following ones
+    fn descend(&self, f: usize, remaining: u32, chosen: &mut Vec<usize>, branch: &mut Branch) {
+        if branch.full
+            || f >= self.filaments.len()
+            || self.suffix_max[f] + self.slack < remaining
+            || chosen.len() >= self.options.max_filaments()
         {
-            // Add if exact match
-            if new_combi.total_layers() == nb_layers_max {
-                color_combi_list.push(new_combi.clone());
-            }
+            return;
+        }
 
-            // Recurse if more layers available
-            if i + 1 < color_layers.len() {
-                let recursive_combis =
-                    compute_combination(restrict_colors, &new_combi, color_layers, nb_layers_max);
-                color_combi_list.extend(recursive_combis);
+        for option in &self.filaments[f] {
+            if option.count <= remaining {
+                self.choose(f, *option, remaining - option.count, chosen, branch);
             }
         }
+        self.descend(f + 1, remaining, chosen, branch);
     }
 
-    color_combi_list
+    /// Sums the CMYK values in the final stacking order (see `generate_combis`)
+    fn entries_sum(&self, chosen: &[usize]) -> Cmyk {
+        if self.options.prune_delta_e <= 0.0 {
+            // Colors are only needed for pruning
+            return Cmyk::new(0.0, 0.0, 0.0, 0.0);
+        }
+        let mut sorted = chosen.to_vec();
+        sorted.sort_unstable_by(|a, b| b.cmp(a));
+        sorted
+            .iter()
+            .fold(Cmyk::new(0.0, 0.0, 0.0, 0.0), |acc, &e| {
+                add_cmyk(&acc, self.color_layers[e].cmyk())
+            })
+    }
 }
 
-/// Combines multiple ColorCombi lists by pairing each element
-///
-/// Based on Java Palette.computeColorsByGroup logic
-///
-/// # Algorithm
+/// Results of one parallel branch
+struct Branch {
+    items: Vec<(Vec<usize>, Option<CieLab>)>,
+    filter: Option<DeltaEFilter>,
+    generated: usize,
+    pruned: usize,
+    /// What is left of the cap when the branch starts
+    limit: usize,
+    /// Whether a candidate was refused because the limit was reached
+    full: bool,
+}
+
+impl Branch {
+    fn new(options: &GeneratorOptions, limit: usize) -> Self {
+        Self {
+            items: Vec::new(),
+            filter: (options.prune_delta_e > 0.0).then(|| DeltaEFilter::new(options.prune_delta_e)),
+            generated: 0,
+            pruned: 0,
+            limit,
+            full: false,
+        }
+    }
+
+    /// Records a candidate unless it is a near-duplicate within this branch
+    /// or the limit is reached
+    fn offer(&mut self, key: Vec<usize>, cmyk_sum: &Cmyk) {
+        self.generated += 1;
+        let lab = match &mut self.filter {
+            Some(filter) => {
+                let lab = CieLab::from(rgb_of(cmyk_sum));
+                if !filter.insert(lab) {
+                    self.pruned += 1;
+                    return;
+                }
+                Some(lab)
+            }
+            None => None,
+        };
+        if self.items.len() >= self.limit {
+            self.full = true;
+            return;
+        }
+        self.items.push((key, lab));
+    }
+}
+
+/// Runs the branches in parallel and merges their results in order
 ///
-/// For groups [A1, A2] and [B1, B2]:
-/// Result = [A1+B1, A1+B2, A2+B1, A2+B2]
+/// Candidates are pruned across branches and the cap is applied in branch
+/// order. Without a cap all branches run at once; with a cap they run in
+/// batches of [`CAPPED_BATCH_SIZE`], each branch limited to what is left of
+/// the cap.
 ///
 /// # Arguments
 ///
-/// * `group1` - First group of ColorCombis
-/// * `group2` - Second group of ColorCombis
-///
-/// # Returns
-///
-/// Vector of all pairwise combinations
-#[cfg(test)]
-pub fn combine_combi_groups(group1: &[ColorCombi], group2: &[ColorCombi]) -> Vec<ColorCombi> {
-    let mut result = Vec::new();
+/// * `nb_roots` - Number of branches
+/// * `options` - Pruning threshold and hard cap
+/// * `run` - Fills the branch with the given index
+/// * `materialize` - Builds a result from a kept candidate key
+fn run_branches<T>(
+    nb_roots: usize,
+    options: &GeneratorOptions,
+    run: impl Fn(usize, &mut Branch) + Sync,
+    materialize: impl Fn(&[usize]) -> T,
+) -> (Vec<T>, CombinationReport) {
+    let mut report = CombinationReport {
+        cap: options.max_combinations,
+        ..CombinationReport::default()
+    };
+    let mut filter =
+        (options.prune_delta_e > 0.0).then(|| DeltaEFilter::new(options.prune_delta_e));
+    let mut kept = Vec::new();
+    // Whether candidates were dropped for the cap (not as near-duplicates)
+    let mut refused = false;
+
+    let batch_size = if options.max_combinations == 0 {
+        nb_roots.max(1)
+    } else {
+        CAPPED_BATCH_SIZE
+    };
+
+    for start in (0..nb_roots).step_by(batch_size) {
+        let limit = options.cap() - kept.len();
+        let branches: Vec<Branch> = (start..nb_roots.min(start + batch_size))
+            .into_par_iter()
+            .map(|root| {
+                let mut branch = Branch::new(options, limit);
+                run(root, &mut branch);
+                branch
+            })
+            .collect();
+
+        for branch in branches {
+            report.generated += branch.generated;
+            report.pruned += branch.pruned;
+            refused |= branch.full;
+            for (key, lab) in branch.items {
+                if let (Some(filter), Some(lab)) = (&mut filter, lab) {
+                    if !filter.insert(lab) {
+                        report.pruned += 1;
+                        continue;
+                    }
+                }
+                if kept.len() >= options.cap() {
+                    refused = true;
+                    break;
+                }
+                kept.push(materialize(&key));
+            }
+        }
+    }
+
+    report.kept = kept.len();
+    report.truncated = refused;
+    (kept, report)
+}
+
+/// Grid-accelerated ΔE filter (cell size = threshold)
+pub(crate) struct DeltaEFilter {
+    threshold: f64,
+    cells: HashMap<(i64, i64, i64), Vec<CieLab>>,
+}
 
-    for combi1 in group1 {
-        for combi2 in group2 {
-            result.push(combi1.combine_with_combi(combi2));
+impl DeltaEFilter {
+    pub(crate) fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            cells: HashMap::new(),
         }
     }
 
-    result
+    fn cell(&self, lab: &CieLab) -> (i64, i64, i64) {
+        (
+            (lab.l / self.threshold).floor() as i64,
+            (lab.a / self.threshold).floor() as i64,
+            (lab.b / self.threshold).floor() as i64,
+        )
+    }
+
+    /// Inserts the color unless a kept color is closer than the threshold
+    pub(crate) fn insert(&mut self, lab: CieLab) -> bool {
+        let (cl, ca, cb) = self.cell(&lab);
+        for dl in -1..=1 {
+            for da in -1..=1 {
+                for db in -1..=1 {
+                    if let Some(colors) = self.cells.get(&(cl + dl, ca + da, cb + db)) {
+                        if colors.iter().any(|c| c.delta_e(&lab) < self.threshold) {
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+        self.cells.entry((cl, ca, cb)).or_default().push(lab);
+        true
+    }
+}
+
+/// Sorted distinct hex codes of a combination
+fn hex_codes(combi: &ColorCombi) -> Vec<&str> {
+    let mut hex_codes: Vec<&str> = combi.layers().iter().map(|l| l.hex_code()).collect();
+    hex_codes.sort_unstable();
+    hex_codes.dedup();
+    hex_codes
+}
+
+/// Size of the union of two sorted, deduplicated lists
+fn union_len(a: &[&str], b: &[&str]) -> usize {
+    a.len() + b.iter().filter(|h| a.binary_search(h).is_err()).count()
+}
+
+fn cmyk_sum(combi: &ColorCombi) -> Cmyk {
+    combi
+        .layers()
+        .iter()
+        .fold(Cmyk::new(0.0, 0.0, 0.0, 0.0), |acc, l| {
+            add_cmyk(&acc, l.cmyk())
+        })
+}
+
+fn add_cmyk(a: &Cmyk, b: &Cmyk) -> Cmyk {
+    Cmyk::new(a.c + b.c, a.m + b.m, a.y + b.y, a.k + b.k)
+}
+
+/// RGB of an (unclamped) CMYK sum, as in `ColorCombi::compute_rgb`
+fn rgb_of(sum: &Cmyk) -> Rgb {
+    Rgb::from_cmyk(Cmyk::new(
+        sum.c.min(1.0),
+        sum.m.min(1.0),
+        sum.y.min(1.0),
+        sum.k.min(1.0),
+    ))
 }
 
 #[cfg(test)]
@@ -175,6 +637,35 @@ mod tests {
         ColorLayer::from_cmyk("#FFFFFF".to_string(), layers, 0.0, 0.0, 0.0, 0.0)
     }
 
+    /// Graded filament with one entry per layer count 1..=max
+    fn graded_layers(hex: &str, max: u32, c: f64, m: f64, y: f64) -> Vec<ColorLayer> {
+        (1..=max)
+            .map(|n| {
+                let f = f64::from(n) / f64::from(max);
+                ColorLayer::from_cmyk(hex.to_string(), n, c * f, m * f, y * f, 0.02 * f)
+            })
+            .collect()
+    }
+
+    fn large_palette() -> Vec<ColorLayer> {
+        let mut layers = Vec::new();
+        layers.extend(graded_layers("#FF0000", 5, 0.0, 0.9, 0.8));
+        layers.extend(graded_layers("#00FF00", 5, 0.8, 0.0, 0.9));
+        layers.extend(graded_layers("#0000FF", 5, 0.9, 0.7, 0.0));
+        layers.extend(graded_layers("#FFFF00", 5, 0.0, 0.1, 0.9));
+        layers.extend(graded_layers("#FF00FF", 5, 0.1, 0.9, 0.0));
+        layers.extend(graded_layers("#FFFFFF", 5, 0.0, 0.0, 0.0));
+        layers
+    }
+
+    fn combine_combi_groups(group1: &[ColorCombi], group2: &[ColorCombi]) -> Vec<ColorCombi> {
+        combine_groups(
+            &[group1.to_vec(), group2.to_vec()],
+            &GeneratorOptions::default(),
+        )
+        .0
+    }
+
     #[test]
     fn test_create_multi_combi_single_layer() {
         let layers = vec![create_red_layer(5)];
@@ -213,7 +704,7 @@ mod tests {
         // - Red[1] + Green[2] + Blue[2] = 5 ✓
         // (Note: Red[1]+Red[1]+Red[1]+Red[1]+Red[1] won't work due to duplicate check)
 
-        assert!(!combis.is_empty());
+        assert_eq!(combis.len(), 1);
         assert!(combis.iter().all(|c| c.total_layers() == 5));
     }
 
@@ -230,6 +721,7 @@ mod tests {
         let combis = create_multi_combi(Some(&restrict), &layers, 5);
 
         // Should only use Red and Green (Blue is restricted)
+        assert!(!combis.is_empty());
         assert!(combis.iter().all(|c| {
             c.layers()
                 .iter()
@@ -288,30 +780,26 @@ mod tests {
     }
 
     #[test]
-    fn test_compute_combination_no_duplicates() {
-        let red1 = create_red_layer(2);
-        let red2 = create_red_layer(3);
-
-        let layers = vec![red1, red2];
-        let base = ColorCombi::new(create_red_layer(1));
+    fn test_no_duplicate_filaments() {
+        let layers = vec![
+            create_red_layer(1),
+            create_red_layer(2),
+            create_red_layer(3),
+        ];
 
-        let combis = compute_combination(None, &base, &layers, 5);
+        let combis = create_multi_combi(None, &layers, 5);
 
-        // Should not be able to add red again (duplicate hex code)
+        // Red may only be used once per stack, and no single red entry has 5 layers
         assert_eq!(combis.len(), 0);
     }
 
     #[test]
-    fn test_compute_combination_respects_max_layers() {
-        let red = create_red_layer(3);
-        let green = create_green_layer(4); // Would exceed max
-
-        let layers = vec![green];
-        let base = ColorCombi::new(red);
+    fn test_respects_max_layers() {
+        let layers = vec![create_red_layer(3), create_green_layer(4)];
 
-        let combis = compute_combination(None, &base, &layers, 5);
+        let combis = create_multi_combi(None, &layers, 5);
 
-        // 3 + 4 = 7 > 5, should be empty
+        // 3 + 4 = 7 > 5, and neither fills the stack alone
         assert_eq!(combis.len(), 0);
     }
 
@@ -329,13 +817,238 @@ mod tests {
 
         let combis = create_multi_combi(None, &layers, 5);
 
-        // Should generate many valid combinations
-        assert!(!combis.is_empty());
+        // R1+G2+W2, R2+G1+W2, R2+G2+W1
+        assert_eq!(combis.len(), 3);
         assert!(combis.iter().all(|c| c.total_layers() == 5));
+    }
+
+    #[test]
+    fn test_count_matches_partitions() {
+        // 6 filaments with counts 1..=5 each, target 5:
+        // number of ways to pick distinct filaments whose counts sum to 5
+        let layers = large_palette();
+        let (combis, report) = generate_combis(None, &layers, 5, &GeneratorOptions::default());
+
+        // Partitions of 5 into distinct-filament parts over 6 filaments:
+        // 5 → 6, 4+1 → 30, 3+2 → 30, 3+1+1 → 60, 2+2+1 → 60, 2+1+1+1 → 60, 1×5 → 6
+        assert_eq!(combis.len(), 252);
+        assert_eq!(report.kept, 252);
+        assert_eq!(report.generated, 252);
+        assert_eq!(report.pruned, 0);
+        assert!(!report.truncated);
+    }
+
+    #[test]
+    fn test_layers_ordered_lightest_first() {
+        let layers = vec![create_red_layer(3), create_white_layer(2)];
+        let combis = create_multi_combi(None, &layers, 5);
+
+        assert_eq!(combis[0].layers()[0].hex_code(), "#FFFFFF");
+        assert_eq!(combis[0].layers()[1].hex_code(), "#FF0000");
+    }
+
+    #[test]
+    fn test_generation_is_deterministic() {
+        let layers = large_palette();
+        let options = GeneratorOptions {
+            prune_delta_e: 2.0,
+            ..GeneratorOptions::default()
+        };
+
+        let first = generate_combis(None, &layers, 5, &options);
+        let second = generate_combis(None, &layers, 5, &options);
+        assert_eq!(first, second);
+    }
 
-        // Each should have unique color combinations
-        for combi in &combis {
-            println!("{}", combi);
+    #[test]
+    fn test_pruning_removes_near_duplicates() {
+        let layers = large_palette();
+        let options = GeneratorOptions {
+            prune_delta_e: 5.0,
+            ..GeneratorOptions::default()
+        };
+
+        let (combis, report) = generate_combis(None, &layers, 5, &options);
+
+        assert!(report.pruned > 0);
+        assert_eq!(report.kept + report.pruned, report.generated);
+        assert_eq!(combis.len(), report.kept);
+
+        let labs: Vec<CieLab> = combis
+            .iter()
+            .map(|c| CieLab::from(c.compute_rgb()))
+            .collect();
+        for (i, a) in labs.iter().enumerate() {
+            for b in &labs[i + 1..] {
+                // Small tolerance for summation order differences
+                assert!(a.delta_e(b) >= 5.0 - 1.0, "kept colors too close");
+            }
+        }
+    }
+
+    #[test]
+    fn test_cap_is_reported() {
+        let layers = large_palette();
+        let options = GeneratorOptions {
+            max_combinations: 10,
+            ..GeneratorOptions::default()
+        };
+
+        let (combis, report) = generate_combis(None, &layers, 5, &options);
+
+        assert_eq!(combis.len(), 10);
+        assert!(report.truncated);
+        assert_eq!(report.cap, 10);
+    }
+
+    #[test]
+    fn test_cap_equal_to_count_is_not_truncated() {
+        let layers = large_palette();
+        let cap = |max_combinations| GeneratorOptions {
+            max_combinations,
+            ..GeneratorOptions::default()
+        };
+
+        let (combis, report) = generate_combis(None, &layers, 5, &cap(252));
+        assert_eq!(combis.len(), 252);
+        assert!(!report.truncated);
+
+        // The cap applies to all branches together, not per branch
+        let (combis, report) = generate_combis(None, &layers, 5, &cap(251));
+        assert_eq!(combis.len(), 251);
+        assert!(report.truncated);
+    }
+
+    #[test]
+    fn test_truncation_is_deterministic() {
+        let layers = large_palette();
+        let options = GeneratorOptions {
+            prune_delta_e: 2.0,
+            max_combinations: 30,
+            ..GeneratorOptions::default()
+        };
+
+        let (first, report) = generate_combis(None, &layers, 5, &options);
+        assert!(report.truncated);
+        for _ in 0..5 {
+            assert_eq!(generate_combis(None, &layers, 5, &options).0, first);
         }
+
+        // The truncated result is the start of the full (uncapped) result
+        let (all, _) = generate_combis(
+            None,
+            &layers,
+            5,
+            &GeneratorOptions {
+                max_combinations: 0,
+                ..options
+            },
+        );
+        assert_eq!(first[..], all[..first.len()]);
+    }
+
+    #[test]
+    fn test_report_display_states_truncation() {
+        let layers = large_palette();
+        let options = GeneratorOptions {
+            max_combinations: 10,
+            ..GeneratorOptions::default()
+        };
+        let (_, report) = generate_combis(None, &layers, 5, &options);
+        assert!(report
+            .to_string()
+            .ends_with("(truncated at the limit of 10)"));
+
+        let (_, report) = generate_combis(None, &layers, 5, &GeneratorOptions::default());
+        assert!(!report.to_string().contains("truncated"));
+    }
+
+    #[test]
+    fn test_combine_groups_prunes_and_caps() {
+        let group1 = create_multi_combi(None, &large_palette()[..10], 5);
+        let group2 = create_multi_combi(None, &large_palette()[10..20], 5);
+
+        let (all, report) = combine_groups(
+            &[group1.clone(), group2.clone()],
+            &GeneratorOptions::default(),
+        );
+        assert_eq!(all.len(), group1.len() * group2.len());
+        assert_eq!(report.generated, all.len());
+
+        let (pruned, report) = combine_groups(
+            &[group1, group2],
+            &GeneratorOptions {
+                prune_delta_e: 3.0,
+                max_combinations: 5,
+                ..GeneratorOptions::default()
+            },
+        );
+        assert!(pruned.len() <= 5);
+        assert!(report.truncated || report.pruned > 0);
+    }
+
+    #[test]
+    fn test_max_filaments_per_stack() {
+        let layers = large_palette();
+        let options = GeneratorOptions {
+            max_filaments_per_stack: 2,
+            ..GeneratorOptions::default()
+        };
+
+        let (all, _) = generate_combis(None, &layers, 5, &GeneratorOptions::default());
+        let (combis, _) = generate_combis(None, &layers, 5, &options);
+
+        assert!(!combis.is_empty());
+        assert!(combis.iter().all(|c| c.distinct_filaments() <= 2));
+        let expected = all.iter().filter(|c| c.distinct_filaments() <= 2).count();
+        assert_eq!(combis.len(), expected);
+    }
+
+    #[test]
+    fn test_min_layers_per_filament() {
+        let layers = large_palette();
+        let options = GeneratorOptions {
+            min_layers_per_filament: 2,
+            ..GeneratorOptions::default()
+        };
+
+        let (combis, _) = generate_combis(None, &layers, 5, &options);
+
+        // 5 = 5, 3+2: one single filament or a pair of distinct filaments
+        assert_eq!(combis.len(), 6 + 6 * 5);
+        assert!(combis
+            .iter()
+            .all(|c| c.layers().iter().all(|l| l.layer() >= 2)));
+    }
+
+    #[test]
+    fn test_variable_depth_stacks() {
+        let layers = large_palette();
+        let options = GeneratorOptions {
+            min_layers_per_filament: 2,
+            min_stack_layers: 3,
+            ..GeneratorOptions::default()
+        };
+
+        let (combis, _) = generate_combis(None, &layers, 5, &options);
+
+        // 5 = 5, 3+2 (36); 4 = 4, 2+2 (6 + 15); 3 = 3 (6)
+        assert_eq!(combis.len(), 36 + 21 + 6);
+        assert!(combis.iter().all(|c| (3..=5).contains(&c.total_layers())));
+    }
+
+    #[test]
+    fn test_combine_groups_respects_max_filaments() {
+        let group1 = create_multi_combi(None, &large_palette()[..10], 5);
+        let group2 = create_multi_combi(None, &large_palette()[10..20], 5);
+        let options = GeneratorOptions {
+            max_filaments_per_stack: 3,
+            ..GeneratorOptions::default()
+        };
+
+        let (combis, _) = combine_groups(&[group1, group2], &options);
+
+        assert!(!combis.is_empty());
+        assert!(combis.iter().all(|c| c.distinct_filaments() <= 3));
     }
 }
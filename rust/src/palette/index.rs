@@ -0,0 +1,315 @@
+//! Spatial index for nearest palette color lookup
+//!
+//! Palettes with many filaments and layers contain tens of thousands of
+//! combinations, so a linear scan per pixel dominates quantization time.
+//! [`ColorIndex`] is a k-d tree over the palette coordinates (RGB or CIELab,
+//! depending on the distance method) that returns exactly the same color as
+//! the brute-force search in [`find_closest_color`](crate::color::find_closest_color):
+//!
+//! - candidate distances are computed with the same [`ColorDistance`] functions,
+//! - ties are resolved in favor of the color that comes first in the input slice,
+//! - subtrees are only skipped when they cannot contain an equal or closer color.
+
+use crate::color::{CieLab, ColorDistance, ColorDistanceMethod, Rgb};
+
+/// Maximum number of colors in a leaf that is scanned linearly
+const LEAF_SIZE: usize = 8;
+
+/// Relative safety margin for pruning with floating point CIELab distances
+const PRUNE_EPSILON: f64 = 1e-9;
+
+/// k-d tree over palette colors for exact nearest neighbor queries
+#[derive(Debug, Clone)]
+pub struct ColorIndex {
+    method: ColorDistanceMethod,
+    colors: Vec<Rgb>,
+    labs: Vec<CieLab>,
+    points: Vec<[f64; 3]>,
+    /// Permutation of color indices forming the implicit tree
+    order: Vec<usize>,
+    /// Split axis of the node whose pivot is stored at this position of `order`
+    axes: Vec<u8>,
+}
+
+impl ColorIndex {
+    /// Builds an index over the given palette colors
+    ///
+    /// # Arguments
+    ///
+    /// * `colors` - Palette colors; their order defines the tie-breaking
+    /// * `method` - Color distance method the index answers queries for
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pixestl::color::{ColorDistanceMethod, Rgb};
+    /// use pixestl::palette::ColorIndex;
+    ///
+    /// let palette = vec![Rgb::new(0, 0, 0), Rgb::new(128, 128, 128), Rgb::new(255, 255, 255)];
+    /// let index = ColorIndex::new(&palette, ColorDistanceMethod::Rgb);
+    ///
+    /// assert_eq!(index.nearest(&Rgb::new(100, 100, 100)), Some(Rgb::new(128, 128, 128)));
+    /// ```
+    #[must_use]
+    pub fn new(colors: &[Rgb], method: ColorDistanceMethod) -> Self {
+        let labs: Vec<CieLab> = match method {
+            ColorDistanceMethod::Rgb => Vec::new(),
+            ColorDistanceMethod::CieLab | ColorDistanceMethod::Luminance => {
+                colors.iter().map(|c| CieLab::from(*c)).collect()
+            }
+        };
+        let points: Vec<[f64; 3]> = match method {
+            ColorDistanceMethod::Rgb => colors
+                .iter()
+                .map(|c| [f64::from(c.r), f64::from(c.g), f64::from(c.b)])
+                .collect(),
+            ColorDistanceMethod::CieLab => labs.iter().map(|l| [l.l, l.a, l.b]).collect(),
+            ColorDistanceMethod::Luminance => labs.iter().map(|l| [l.l, 0.0, 0.0]).collect(),
+        };
+
+        let mut index = Self {
+            method,
+            colors: colors.to_vec(),
+            labs,
+            points,
+            order: (0..colors.len()).collect(),
+            axes: vec![0; colors.len()],
+        };
+        index.build(0, colors.len());
+        index
+    }
+
+    /// Gets the distance method of this index
+    #[must_use]
+    pub fn method(&self) -> ColorDistanceMethod {
+        self.method
+    }
+
+    /// Gets the number of indexed colors
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    /// Checks whether the index is empty
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Finds the closest indexed color
+    ///
+    /// Returns `None` if the index is empty.
+    #[must_use]
+    pub fn nearest(&self, target: &Rgb) -> Option<Rgb> {
+        self.nearest_index(target).map(|i| self.colors[i])
+    }
+
+    /// Finds the position of the closest color in the slice the index was built from
+    #[must_use]
+    pub fn nearest_index(&self, target: &Rgb) -> Option<usize> {
+        if self.colors.is_empty() {
+            return None;
+        }
+
+        let query = match self.method {
+            ColorDistanceMethod::Rgb => Query {
+                rgb: *target,
+                lab: None,
+                point: [
+                    f64::from(target.r),
+                    f64::from(target.g),
+                    f64::from(target.b),
+                ],
+            },
+            ColorDistanceMethod::CieLab => {
+                let lab = CieLab::from(*target);
+                Query {
+                    rgb: *target,
+                    lab: Some(lab),
+                    point: [lab.l, lab.a, lab.b],
+                }
+            }
+            ColorDistanceMethod::Luminance => {
+                let lab = CieLab::from(*target);
+                Query {
+                    rgb: *target,
+                    lab: Some(lab),
+                    point: [lab.l, 0.0, 0.0],
+                }
+            }
+        };
+
+        let mut best = Best {
+            distance: f64::MAX,
+            index: usize::MAX,
+        };
+        self.search(0, self.order.len(), &query, &mut best);
+        Some(best.index)
+    }
+
+    /// Recursively splits `order[lo..hi]` at the median of the widest axis
+    fn build(&mut self, lo: usize, hi: usize) {
+        if hi - lo <= LEAF_SIZE {
+            return;
+        }
+
+        let axis = self.widest_axis(lo, hi);
+        let mid = lo + (hi - lo) / 2;
+        let points = &self.points;
+        self.order[lo..hi].select_nth_unstable_by(mid - lo, |&a, &b| {
+            points[a][axis].total_cmp(&points[b][axis])
+        });
+        self.axes[mid] = axis as u8;
+
+        self.build(lo, mid);
+        self.build(mid + 1, hi);
+    }
+
+    fn widest_axis(&self, lo: usize, hi: usize) -> usize {
+        let mut min = [f64::MAX; 3];
+        let mut max = [f64::MIN; 3];
+        for &i in &self.order[lo..hi] {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(self.points[i][axis]);
+                max[axis] = max[axis].max(self.points[i][axis]);
+            }
+        }
+        (0..3)
+            .max_by(|&a, &b| (max[a] - min[a]).total_cmp(&(max[b] - min[b])))
+            .unwrap_or(0)
+    }
+
+    fn search(&self, lo: usize, hi: usize, query: &Query, best: &mut Best) {
+        if hi - lo <= LEAF_SIZE {
+            for &i in &self.order[lo..hi] {
+                self.visit(i, query, best);
+            }
+            return;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        let pivot = self.order[mid];
+        let axis = self.axes[mid] as usize;
+        self.visit(pivot, query, best);
+
+        let diff = query.point[axis] - self.points[pivot][axis];
+        let (near, far) = if diff < 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+
+        self.search(near.0, near.1, query, best);
+        if self.plane_bound(diff) <= best.distance + PRUNE_EPSILON * (1.0 + best.distance) {
+            self.search(far.0, far.1, query, best);
+        }
+    }
+
+    /// Lower bound of the distance to any color on the other side of a split plane
+    fn plane_bound(&self, diff: f64) -> f64 {
+        match self.method {
+            // Rgb::distance is the squared Euclidean distance
+            ColorDistanceMethod::Rgb => diff * diff,
+            ColorDistanceMethod::CieLab | ColorDistanceMethod::Luminance => diff.abs(),
+        }
+    }
+
+    fn visit(&self, i: usize, query: &Query, best: &mut Best) {
+        let distance = match (query.lab, self.method) {
+            (Some(lab), ColorDistanceMethod::Luminance) => (lab.l - self.labs[i].l).abs(),
+            (Some(lab), _) => lab.distance(&self.labs[i]),
+            (None, _) => query.rgb.distance(&self.colors[i]),
+        };
+        if distance < best.distance || (distance == best.distance && i < best.index) {
+            best.distance = distance;
+            best.index = i;
+        }
+    }
+}
+
+struct Query {
+    rgb: Rgb,
+    lab: Option<CieLab>,
+    point: [f64; 3],
+}
+
+struct Best {
+    distance: f64,
+    index: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::find_closest_color;
+    use proptest::prelude::*;
+
+    fn grid_palette() -> Vec<Rgb> {
+        let mut colors = Vec::new();
+        for r in (0..=255).step_by(51) {
+            for g in (0..=255).step_by(51) {
+                for b in (0..=255).step_by(85) {
+                    colors.push(Rgb::new(r as u8, g as u8, b as u8));
+                }
+            }
+        }
+        colors
+    }
+
+    #[test]
+    fn test_empty_index() {
+        let index = ColorIndex::new(&[], ColorDistanceMethod::CieLab);
+        assert!(index.is_empty());
+        assert_eq!(index.nearest(&Rgb::new(1, 2, 3)), None);
+    }
+
+    #[test]
+    fn test_exact_match() {
+        let palette = grid_palette();
+        let index = ColorIndex::new(&palette, ColorDistanceMethod::CieLab);
+
+        assert_eq!(index.len(), palette.len());
+        for color in &palette {
+            assert_eq!(index.nearest(color), Some(*color));
+        }
+    }
+
+    #[test]
+    fn test_ties_prefer_first_color() {
+        // Both candidates are equally far away from the target
+        let palette = vec![
+            Rgb::new(10, 0, 0),
+            Rgb::new(0, 0, 0),
+            Rgb::new(20, 0, 0),
+            Rgb::new(0, 0, 0),
+        ];
+        let index = ColorIndex::new(&palette, ColorDistanceMethod::Rgb);
+
+        assert_eq!(index.nearest_index(&Rgb::new(15, 0, 0)), Some(0));
+        assert_eq!(index.nearest_index(&Rgb::new(0, 0, 0)), Some(1));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_matches_brute_force(
+            palette in prop::collection::vec(any::<(u8, u8, u8)>(), 1..300),
+            targets in prop::collection::vec(any::<(u8, u8, u8)>(), 1..50),
+        ) {
+            let palette: Vec<Rgb> = palette.into_iter().map(|(r, g, b)| Rgb::new(r, g, b)).collect();
+
+            for method in [
+                ColorDistanceMethod::Rgb,
+                ColorDistanceMethod::CieLab,
+                ColorDistanceMethod::Luminance,
+            ] {
+                let index = ColorIndex::new(&palette, method);
+                for &(r, g, b) in &targets {
+                    let target = Rgb::new(r, g, b);
+                    let expected = find_closest_color(&target, &palette, method).unwrap();
+                    prop_assert_eq!(index.nearest(&target), Some(expected));
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,15 @@
 //! JSON Palette loader with serde
 
-use crate::color::{ColorDistanceMethod, Rgb};
+use crate::color::{CieLab, ColorDistanceMethod, Rgb};
 use crate::error::{PixestlError, Result};
-use crate::palette::{create_multi_combi, ColorCombi, ColorLayer, Palette};
+use crate::filament::{FilamentProperties, DEFAULT_DENSITY, DEFAULT_DIAMETER};
+use crate::palette::generator::{
+    combine_groups, generate_combis, CombinationReport, GeneratorOptions, DEFAULT_MAX_COMBINATIONS,
+};
+use crate::palette::stacking::shortest_run;
+use crate::palette::{
+    ColorLayer, ColorLookup, MixingModel, Palette, PaletteCache, StackingStrategy, DEFAULT_BASE_HEX,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
@@ -33,12 +40,55 @@ pub struct PaletteColorEntry {
     pub active: bool,
     #[serde(default)]
     pub layers: Option<HashMap<String, LayerDefinition>>,
+    /// Marks the base (white / diffuser) filament that fills the stacks.
+    /// At most one active entry may be marked; without a mark "#FFFFFF" is used.
+    #[serde(default)]
+    pub base: bool,
+    /// Transmission distance in mm (thickness that lets almost no light
+    /// through, as in HueForge). Used by the height mode; estimated from the
+    /// lightness of the hex code if missing.
+    #[serde(default)]
+    pub td: Option<f64>,
+    /// Density in g/cm³ for the usage estimate (PLA's 1.24 if missing)
+    #[serde(default)]
+    pub density: Option<f64>,
+    /// Filament diameter in mm for the usage estimate (1.75 if missing)
+    #[serde(default)]
+    pub diameter: Option<f64>,
+    /// Price per kilogram for the cost estimate
+    #[serde(default)]
+    pub price_per_kg: Option<f64>,
 }
 
 fn default_active() -> bool {
     true
 }
 
+/// Transmission distance of a black filament for the estimate (mm)
+const MIN_ESTIMATED_TD: f64 = 0.5;
+/// Transmission distance of a white filament for the estimate (mm)
+const MAX_ESTIMATED_TD: f64 = 3.5;
+
+/// Estimates the transmission distance of a filament from its color
+///
+/// Lighter filaments let more light through; the estimate interpolates
+/// linearly in CIELab lightness between black and white. Measured values
+/// (`"td"` in the palette) are much more accurate.
+#[must_use]
+pub fn estimate_transmission_distance(color: Rgb) -> f64 {
+    let lightness = CieLab::from(color).l.clamp(0.0, 100.0) / 100.0;
+    MIN_ESTIMATED_TD + (MAX_ESTIMATED_TD - MIN_ESTIMATED_TD) * lightness
+}
+
+/// Maximum CIELab chroma of a filament that counts as neutral (white, grey, black)
+const MAX_NEUTRAL_CHROMA: f64 = 10.0;
+
+/// Checks whether a filament color is neutral enough for monochrome mode
+fn is_neutral(color: Rgb) -> bool {
+    let lab = CieLab::from(color);
+    lab.a.hypot(lab.b) <= MAX_NEUTRAL_CHROMA
+}
+
 /// Generation mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PixelCreationMethod {
@@ -46,6 +96,9 @@ pub enum PixelCreationMethod {
     Additive,
     /// Full color layers (no mixing)
     Full,
+    /// One heightfield with filament swaps at fixed heights (HueForge-style);
+    /// the palette holds one full-height color per filament
+    Height,
 }
 
 /// Palette loader configuration
@@ -59,6 +112,40 @@ pub struct PaletteLoaderConfig {
     pub color_number: usize,
     /// Color distance method for quantization
     pub distance_method: ColorDistanceMethod,
+    /// Drop combinations closer than this ΔE to an already generated one (0 = keep all)
+    pub prune_delta_e: f64,
+    /// Hard cap for the number of generated combinations (0 = no limit)
+    pub max_combinations: usize,
+    /// Merge combinations closer than this ΔE, keeping the stack with the fewest
+    /// filaments and tool changes (0 = off)
+    pub merge_delta_e: f64,
+    /// Maximum number of distinct filaments in one color stack (0 = no limit)
+    pub max_filaments_per_stack: usize,
+    /// Minimum number of consecutive layers of a filament in a stack (0 = no limit)
+    pub min_layers_per_filament: u32,
+    /// Order of the filament runs inside each stack
+    pub stacking_strategy: StackingStrategy,
+    /// Model used to predict the color of a stack
+    pub mixing_model: MixingModel,
+    /// Minimum color layers per stack for variable-depth stacks; the remaining
+    /// height is filled by the texture layer or the plate (0 = always `nb_layers`)
+    pub min_stack_layers: u32,
+    /// Only stack the neutral filaments (white, greys, black) for grayscale images
+    pub monochrome: bool,
+}
+
+impl PaletteLoaderConfig {
+    /// Gets the combination generator options of this configuration
+    #[must_use]
+    pub fn generator_options(&self) -> GeneratorOptions {
+        GeneratorOptions {
+            prune_delta_e: self.prune_delta_e,
+            max_combinations: self.max_combinations,
+            max_filaments_per_stack: self.max_filaments_per_stack,
+            min_layers_per_filament: self.min_layers_per_filament,
+            min_stack_layers: self.min_stack_layers,
+        }
+    }
 }
 
 impl Default for PaletteLoaderConfig {
@@ -68,6 +155,15 @@ impl Default for PaletteLoaderConfig {
             creation_method: PixelCreationMethod::Additive,
             color_number: 0,
             distance_method: ColorDistanceMethod::CieLab,
+            prune_delta_e: 0.0,
+            max_combinations: DEFAULT_MAX_COMBINATIONS,
+            merge_delta_e: 0.0,
+            max_filaments_per_stack: 0,
+            min_layers_per_filament: 0,
+            stacking_strategy: StackingStrategy::default(),
+            mixing_model: MixingModel::default(),
+            min_stack_layers: 0,
+            monochrome: false,
         }
     }
 }
@@ -202,10 +298,60 @@ impl PaletteLoader {
     /// println!("Loaded {} colors", palette.color_count());
     /// ```
     pub fn load(path: &Path, config: PaletteLoaderConfig) -> Result<Palette> {
-        // Read and parse JSON
         let json_content = fs::read_to_string(path)?;
+        Self::load_from_str(&json_content, config)
+    }
 
-        let palette_data: HashMap<String, PaletteColorEntry> = serde_json::from_str(&json_content)?;
+    /// Loads a palette from a JSON file, reusing an on-disk cache
+    ///
+    /// On a cache hit the combinations and the quantization lookup are read
+    /// from the cache. On a miss the palette is computed as in [`Self::load`],
+    /// an empty lookup is attached and the result is written to the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the JSON palette file
+    /// * `config` - Loader configuration (part of the cache key)
+    /// * `cache` - Cache to read from and write to
+    pub fn load_cached(
+        path: &Path,
+        config: PaletteLoaderConfig,
+        cache: &PaletteCache,
+    ) -> Result<Palette> {
+        let json_content = fs::read_to_string(path)?;
+        let key = PaletteCache::key(&json_content, &config);
+        if let Some(palette) = cache.load(&key) {
+            return Ok(palette);
+        }
+
+        let distance_method = config.distance_method;
+        let mut palette = Self::load_from_str(&json_content, config)?;
+        palette.set_color_lookup(ColorLookup::new(distance_method));
+        cache.store(&key, &palette)?;
+        Ok(palette)
+    }
+
+    /// Loads a palette from JSON content
+    ///
+    /// # Arguments
+    ///
+    /// * `json_content` - Palette JSON
+    /// * `config` - Loader configuration
+    pub fn load_from_str(json_content: &str, config: PaletteLoaderConfig) -> Result<Palette> {
+        let palette_data: HashMap<String, PaletteColorEntry> = serde_json::from_str(json_content)?;
+
+        if config.min_layers_per_filament > config.nb_layers {
+            return Err(PixestlError::Config(format!(
+                "min_layers_per_filament ({}) must not exceed the number of color layers ({})",
+                config.min_layers_per_filament, config.nb_layers
+            )));
+        }
+        if config.min_stack_layers > config.nb_layers {
+            return Err(PixestlError::Config(format!(
+                "min_stack_layers ({}) must not exceed the number of color layers ({})",
+                config.min_stack_layers, config.nb_layers
+            )));
+        }
 
         let mut palette = Palette::new(config.nb_layers);
 
@@ -226,14 +372,78 @@ impl PaletteLoader {
         // Sort by hex code for deterministic order
         hex_color_list.sort();
 
-        // Validate white color in additive mode
+        // Resolve the base filament and validate it in additive mode
+        let base_hex = Self::resolve_base_hex(&palette_data)?;
+
+        // Monochrome: keep only the neutral filaments
+        if config.monochrome {
+            if !is_neutral(Rgb::from_hex(&base_hex)?) {
+                return Err(PixestlError::InvalidPalette(format!(
+                    "Base filament {base_hex} is not neutral. Monochrome mode needs a white or grey base filament."
+                )));
+            }
+            let mut neutral = Vec::with_capacity(hex_color_list.len());
+            for hex in hex_color_list {
+                if is_neutral(Rgb::from_hex(&hex)?) {
+                    neutral.push(hex);
+                }
+            }
+            hex_color_list = neutral;
+        }
         if config.creation_method == PixelCreationMethod::Additive
-            && !hex_color_list.contains(&"#FFFFFF".to_string())
+            && !hex_color_list.contains(&base_hex)
         {
-            return Err(PixestlError::InvalidPalette(
-                "\"#FFFFFF\" not found in the palette. The code \"#FFFFFF\" is mandatory in additive mode.".to_string()
-            ));
+            return Err(PixestlError::InvalidPalette(format!(
+                "\"{base_hex}\" not found in the palette. A base filament (\"#FFFFFF\" or an entry with \"base\": true) is mandatory in additive mode."
+            )));
+        }
+        palette.set_base_hex(base_hex);
+
+        // Transmission distances of the active filaments (height mode)
+        let mut transmission_distances = HashMap::new();
+        for hex in &hex_color_list {
+            let td = match palette_data[hex].td {
+                Some(td) if td > 0.0 => td,
+                Some(td) => {
+                    return Err(PixestlError::InvalidPalette(format!(
+                        "Transmission distance of {hex} must be positive, got {td}"
+                    )))
+                }
+                None => estimate_transmission_distance(Rgb::from_hex(hex)?),
+            };
+            transmission_distances.insert(hex.clone(), td);
         }
+        palette.set_transmission_distances(transmission_distances);
+
+        // Material properties of the active filaments (usage estimate)
+        let mut filament_properties = HashMap::new();
+        for hex in &hex_color_list {
+            let entry = &palette_data[hex];
+            let positive = |value: Option<f64>, default: f64, what: &str| match value {
+                Some(value) if value > 0.0 => Ok(value),
+                Some(value) => Err(PixestlError::InvalidPalette(format!(
+                    "{what} of {hex} must be positive, got {value}"
+                ))),
+                None => Ok(default),
+            };
+            let price_per_kg = match entry.price_per_kg {
+                Some(price) if price < 0.0 => {
+                    return Err(PixestlError::InvalidPalette(format!(
+                        "Price per kg of {hex} must not be negative, got {price}"
+                    )))
+                }
+                price => price,
+            };
+            filament_properties.insert(
+                hex.clone(),
+                FilamentProperties {
+                    density: positive(entry.density, DEFAULT_DENSITY, "Density")?,
+                    diameter: positive(entry.diameter, DEFAULT_DIAMETER, "Diameter")?,
+                    price_per_kg,
+                },
+            );
+        }
+        palette.set_filament_properties(filament_properties);
 
         // Create ColorLayers
         let color_layers = Self::create_color_layers(&palette_data, &config)?;
@@ -285,8 +495,8 @@ impl PaletteLoader {
                         }
                     }
                 }
-                PixelCreationMethod::Full => {
-                    // In full mode, use the hex code directly
+                PixelCreationMethod::Full | PixelCreationMethod::Height => {
+                    // In full and height mode, use the hex code directly
                     let rgb = Rgb::from_hex(hex_code)?;
                     let hsl = crate::color::Hsl::from(rgb);
 
@@ -316,10 +526,11 @@ impl PaletteLoader {
         hex_color_list: &[String],
         config: &PaletteLoaderConfig,
     ) -> Result<()> {
-        // Remove white from the list for grouping
+        // Remove the base filament from the list for grouping
+        let base_hex = palette.base_hex().to_string();
         let working_hex_list: Vec<String> = hex_color_list
             .iter()
-            .filter(|h| *h != "#FFFFFF")
+            .filter(|h| **h != base_hex)
             .cloned()
             .collect();
 
@@ -348,80 +559,120 @@ impl PaletteLoader {
             }
         }
 
-        // Add white to each group
+        // Add the base filament to each group
         for group in &mut hex_color_groups {
-            group.push("#FFFFFF".to_string());
+            group.push(base_hex.clone());
         }
 
         // Generate combinations for each group
-        let mut color_combi_list_list: Vec<Vec<ColorCombi>> = Vec::new();
+        let options = config.generator_options();
+        let mut report = CombinationReport {
+            cap: options.max_combinations,
+            ..CombinationReport::default()
+        };
+        let mut color_combi_list_list = Vec::new();
 
         for group in &hex_color_groups {
-            let combis = create_multi_combi(Some(group), color_layers, config.nb_layers);
+            let (combis, group_report) =
+                generate_combis(Some(group), color_layers, config.nb_layers, &options);
+            report.merge(&group_report);
             color_combi_list_list.push(combis);
         }
 
-        // Combine groups progressively
-        let mut temp_color_combi_list = vec![color_combi_list_list[0].clone()];
-
-        for i in 0..nb_groups - 1 {
-            let mut temp_list = Vec::new();
-
-            for combi_i in &temp_color_combi_list[i] {
-                for combi_i1 in &color_combi_list_list[i + 1] {
-                    temp_list.push(combi_i.combine_with_combi(combi_i1));
-                }
-            }
-
-            temp_color_combi_list.push(temp_list);
+        if color_combi_list_list.is_empty() {
+            return Err(PixestlError::InvalidPalette(
+                "No color combination groups were generated".to_string(),
+            ));
         }
 
-        let final_combi_list = temp_color_combi_list.last().ok_or_else(|| {
-            PixestlError::InvalidPalette("No color combination groups were generated".to_string())
-        })?;
+        // Combine groups (cartesian product with the same pruning and cap)
+        let final_combi_list = if color_combi_list_list.len() == 1 {
+            color_combi_list_list.remove(0)
+        } else {
+            let (combis, product_report) = combine_groups(&color_combi_list_list, &options);
+            report.merge(&product_report);
+            combis
+        };
+        report.kept = final_combi_list.len();
 
         // Set group information
         palette.set_nb_groups(nb_groups);
 
-        // Factorize and add all combinations
-        for mut combi in final_combi_list.clone() {
+        // Factorize, arrange the stacks and add all combinations (the color
+        // may depend on the arrangement)
+        palette.set_stacking(config.stacking_strategy, config.mixing_model);
+        for mut combi in final_combi_list {
             combi.factorize();
+            combi.apply_stacking(config.stacking_strategy, &base_hex, nb_color_pool);
+            // The arrangement splits the base runs; a stack must not end up
+            // with a run thinner than the minimum
+            if shortest_run(combi.layers()) < config.min_layers_per_filament {
+                report.kept -= 1;
+                continue;
+            }
             palette.add_combi(combi);
         }
 
-        // Optimize white layers
-        palette.optimize_white_layers(nb_color_pool);
+        // Merge near-identical colors into the cheapest stack
+        report.merged = palette.merge_similar(config.merge_delta_e);
+        palette.set_combination_report(report);
 
         // Initialize hex color group list for AMS
-        Self::init_hex_color_group_list(palette, &hex_color_groups, nb_color_pool);
+        Self::init_hex_color_group_list(palette, &hex_color_groups, &base_hex, nb_color_pool);
 
         Ok(())
     }
 
+    /// Gets the hex code of the base filament
+    ///
+    /// The active entry marked with `"base": true`, otherwise "#FFFFFF".
+    fn resolve_base_hex(palette_data: &HashMap<String, PaletteColorEntry>) -> Result<String> {
+        let mut marked: Vec<&String> = palette_data
+            .iter()
+            .filter(|(_, entry)| entry.active && entry.base)
+            .map(|(hex, _)| hex)
+            .collect();
+        marked.sort();
+
+        match marked.as_slice() {
+            [] => Ok(DEFAULT_BASE_HEX.to_string()),
+            [hex] => Ok((*hex).clone()),
+            _ => Err(PixestlError::InvalidPalette(format!(
+                "Only one base filament is allowed, found: {}",
+                marked
+                    .iter()
+                    .map(|h| h.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+        }
+    }
+
     /// Initializes hex color group list for AMS
     ///
     /// Based on Java Palette.initHexColorGroupList
     fn init_hex_color_group_list(
         palette: &mut Palette,
         hex_color_groups: &[Vec<String>],
+        base_hex: &str,
         nb_color_pool: usize,
     ) {
         let mut hex_color_group_list: Vec<Vec<String>> =
             (0..nb_color_pool).map(|_| Vec::new()).collect();
 
         for group in hex_color_groups {
-            let group_without_white: Vec<String> =
-                group.iter().filter(|h| *h != "#FFFFFF").cloned().collect();
+            let group_without_base: Vec<String> =
+                group.iter().filter(|h| *h != base_hex).cloned().collect();
 
-            for (i, hex_code) in group_without_white.iter().enumerate() {
+            for (i, hex_code) in group_without_base.iter().enumerate() {
                 if i < nb_color_pool {
                     hex_color_group_list[i].push(hex_code.clone());
                 }
             }
         }
 
-        // Add white group at the end
-        hex_color_group_list.push(vec!["#FFFFFF".to_string()]);
+        // Add the base group at the end
+        hex_color_group_list.push(vec![base_hex.to_string()]);
 
         palette.set_hex_color_groups(hex_color_group_list);
     }
@@ -430,6 +681,7 @@ impl PaletteLoader {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::color::{CieLab, ColorDistance};
     use std::io::Write;
     use tempfile::NamedTempFile;
 
@@ -560,6 +812,11 @@ mod tests {
                 name: "Red".to_string(),
                 active: true,
                 layers: Some(layers),
+                base: false,
+                td: None,
+                density: None,
+                diameter: None,
+                price_per_kg: None,
             },
         );
 
@@ -615,6 +872,11 @@ mod tests {
                 name: "Cyan".to_string(),
                 active: true,
                 layers: Some(layers),
+                base: false,
+                td: None,
+                density: None,
+                diameter: None,
+                price_per_kg: None,
             },
         );
 
@@ -645,6 +907,11 @@ mod tests {
                 name: "Cyan".to_string(),
                 active: true,
                 layers: Some(layers),
+                base: false,
+                td: None,
+                density: None,
+                diameter: None,
+                price_per_kg: None,
             },
         );
 
@@ -675,6 +942,11 @@ mod tests {
                 name: "Cyan".to_string(),
                 active: true,
                 layers: Some(layers),
+                base: false,
+                td: None,
+                density: None,
+                diameter: None,
+                price_per_kg: None,
             },
         );
 
@@ -703,6 +975,11 @@ mod tests {
                 name: "Blue".to_string(),
                 active: false,
                 layers: Some(layers),
+                base: false,
+                td: None,
+                density: None,
+                diameter: None,
+                price_per_kg: None,
             },
         );
 
@@ -720,6 +997,11 @@ mod tests {
                 name: "Red".to_string(),
                 active: true,
                 layers: None,
+                base: false,
+                td: None,
+                density: None,
+                diameter: None,
+                price_per_kg: None,
             },
         );
 
@@ -747,10 +1029,345 @@ mod tests {
                 name: "Cyan".to_string(),
                 active: true,
                 layers: Some(layers),
+                base: false,
+                td: None,
+                density: None,
+                diameter: None,
+                price_per_kg: None,
             },
         );
 
         let warnings = PaletteLoader::validate_completeness(&data, 1);
         assert!(warnings.is_empty());
     }
+
+    #[test]
+    fn test_load_cached_miss_then_hit() {
+        let file = create_test_palette_json();
+        let dir = tempfile::TempDir::new().unwrap();
+        let cache = PaletteCache::new(dir.path());
+
+        let computed =
+            PaletteLoader::load_cached(file.path(), PaletteLoaderConfig::default(), &cache)
+                .unwrap();
+        assert!(computed.color_lookup().is_some());
+
+        let cached =
+            PaletteLoader::load_cached(file.path(), PaletteLoaderConfig::default(), &cache)
+                .unwrap();
+        assert_eq!(cached.color_count(), computed.color_count());
+        for color in computed.colors() {
+            assert_eq!(cached.get_combi(&color), computed.get_combi(&color));
+        }
+    }
+
+    #[test]
+    fn test_load_reports_combinations() {
+        let file = create_test_palette_json();
+        let palette = PaletteLoader::load(file.path(), PaletteLoaderConfig::default()).unwrap();
+
+        let report = palette.combination_report().unwrap();
+        assert!(!report.truncated);
+        assert_eq!(report.pruned, 0);
+        assert!(report.kept >= palette.color_count());
+        assert_eq!(report.merged, 0);
+    }
+
+    #[test]
+    fn test_load_stack_constraints() {
+        let file = create_test_palette_json();
+        let config = PaletteLoaderConfig {
+            max_filaments_per_stack: 2,
+            min_layers_per_filament: 2,
+            ..PaletteLoaderConfig::default()
+        };
+        let palette = PaletteLoader::load(file.path(), config).unwrap();
+
+        assert!(palette.color_count() > 0);
+        for color in palette.colors() {
+            let combi = palette.get_combi(&color).unwrap();
+            assert!(combi.distinct_filaments() <= 2);
+        }
+
+        let invalid = PaletteLoaderConfig {
+            min_layers_per_filament: 6,
+            ..PaletteLoaderConfig::default()
+        };
+        assert!(matches!(
+            PaletteLoader::load(file.path(), invalid),
+            Err(PixestlError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_min_layers_per_filament_after_arrangement() {
+        // Two AMS groups (one color each), so every stack holds two base runs
+        // that the strategies move around
+        let json = r##"{
+            "#FF0000": { "name": "Red", "layers": {
+                "1": { "H": 0, "S": 100, "L": 80 }, "2": { "H": 0, "S": 100, "L": 70 },
+                "3": { "H": 0, "S": 100, "L": 60 } } },
+            "#00FF00": { "name": "Green", "layers": {
+                "1": { "H": 120, "S": 100, "L": 80 }, "2": { "H": 120, "S": 100, "L": 70 },
+                "3": { "H": 120, "S": 100, "L": 60 } } },
+            "#FFFFFF": { "name": "White", "layers": {
+                "1": { "H": 0, "S": 0, "L": 100 }, "2": { "H": 0, "S": 0, "L": 98 },
+                "3": { "H": 0, "S": 0, "L": 96 }, "4": { "H": 0, "S": 0, "L": 94 } } }
+        }"##;
+
+        for stacking_strategy in [StackingStrategy::Classic, StackingStrategy::DiffuserTop] {
+            let config = PaletteLoaderConfig {
+                nb_layers: 4,
+                color_number: 2,
+                min_layers_per_filament: 2,
+                stacking_strategy,
+                ..PaletteLoaderConfig::default()
+            };
+            let palette = PaletteLoader::load_from_str(json, config).unwrap();
+
+            assert_eq!(palette.nb_groups(), 2);
+            assert!(palette.color_count() > 0);
+            for color in palette.colors() {
+                let layers = palette.get_combi(&color).unwrap().layers();
+                assert!(
+                    shortest_run(layers) >= 2,
+                    "{stacking_strategy:?}: {layers:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_variable_depth() {
+        let file = create_test_palette_json();
+        let config = PaletteLoaderConfig {
+            min_stack_layers: 2,
+            ..PaletteLoaderConfig::default()
+        };
+        let variable = PaletteLoader::load(file.path(), config).unwrap();
+        let full = PaletteLoader::load(file.path(), PaletteLoaderConfig::default()).unwrap();
+
+        assert!(variable.color_count() > full.color_count());
+        assert_eq!(variable.stack_height(), 5);
+        assert!(variable
+            .colors()
+            .iter()
+            .any(|c| variable.get_combi(c).unwrap().total_layers() < 5));
+
+        let invalid = PaletteLoaderConfig {
+            min_stack_layers: 6,
+            ..PaletteLoaderConfig::default()
+        };
+        assert!(matches!(
+            PaletteLoader::load(file.path(), invalid),
+            Err(PixestlError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_stacking_and_mixing_model() {
+        let file = create_test_palette_json();
+        let config = PaletteLoaderConfig {
+            stacking_strategy: StackingStrategy::DiffuserTop,
+            ..PaletteLoaderConfig::default()
+        };
+        let additive = PaletteLoader::load(file.path(), config.clone()).unwrap();
+        assert_eq!(additive.stacking_strategy(), StackingStrategy::DiffuserTop);
+        for color in additive.colors() {
+            let combi = additive.get_combi(&color).unwrap();
+            let last = combi.layers().last().unwrap();
+            if combi.layers_with_hex("#FFFFFF").len() == 1 {
+                assert_eq!(last.hex_code(), "#FFFFFF");
+            }
+        }
+
+        let order_aware = PaletteLoader::load(
+            file.path(),
+            PaletteLoaderConfig {
+                mixing_model: MixingModel::OrderAware,
+                ..config
+            },
+        )
+        .unwrap();
+        assert_eq!(order_aware.mixing_model(), MixingModel::OrderAware);
+        for color in order_aware.colors() {
+            let combi = order_aware.get_combi(&color).unwrap();
+            assert_eq!(combi.compute_rgb_with(MixingModel::OrderAware), color);
+        }
+        // Colors covered by the white diffuser are predicted differently
+        let mut additive_colors = additive.colors();
+        let mut order_aware_colors = order_aware.colors();
+        additive_colors.sort_by_key(|c| (c.r, c.g, c.b));
+        order_aware_colors.sort_by_key(|c| (c.r, c.g, c.b));
+        assert_ne!(additive_colors, order_aware_colors);
+    }
+
+    #[test]
+    fn test_load_merge_delta_e() {
+        // Two almost identical grays produce many near-duplicate stacks
+        let json = r##"{
+            "#808080": { "name": "Gray", "layers": {
+                "1": { "H": 0, "S": 0, "L": 95 }, "2": { "H": 0, "S": 0, "L": 88 },
+                "3": { "H": 0, "S": 0, "L": 80 }, "4": { "H": 0, "S": 0, "L": 72 } } },
+            "#848484": { "name": "Gray 2", "layers": {
+                "1": { "H": 0, "S": 0, "L": 94 }, "2": { "H": 0, "S": 0, "L": 87 },
+                "3": { "H": 0, "S": 0, "L": 79 }, "4": { "H": 0, "S": 0, "L": 71 } } },
+            "#FFFFFF": { "name": "White", "layers": {
+                "1": { "H": 0, "S": 0, "L": 100 }, "2": { "H": 0, "S": 0, "L": 100 } } }
+        }"##;
+        let base = PaletteLoaderConfig {
+            nb_layers: 4,
+            ..PaletteLoaderConfig::default()
+        };
+        let full = PaletteLoader::load_from_str(json, base.clone()).unwrap();
+        let config = PaletteLoaderConfig {
+            merge_delta_e: 3.0,
+            ..base
+        };
+        let merged = PaletteLoader::load_from_str(json, config).unwrap();
+
+        let report = merged.combination_report().unwrap();
+        assert!(report.merged > 0);
+        assert_eq!(merged.color_count() + report.merged, full.color_count());
+
+        // Every removed color has a kept neighbour within the threshold
+        for color in full.colors() {
+            let closest = merged
+                .find_closest(&color, ColorDistanceMethod::CieLab)
+                .unwrap();
+            assert!(CieLab::from(color).distance(&CieLab::from(closest)) < 3.0);
+        }
+    }
+
+    #[test]
+    fn test_load_custom_base_filament() {
+        let json = r##"{
+            "#FF0000": { "name": "Red", "layers": {
+                "1": { "H": 0, "S": 100, "L": 80 },
+                "2": { "H": 0, "S": 100, "L": 60 }
+            } },
+            "#F5F0E6": { "name": "Natural", "base": true, "layers": {
+                "1": { "H": 40, "S": 40, "L": 95 },
+                "2": { "H": 40, "S": 40, "L": 93 }
+            } }
+        }"##;
+        let config = PaletteLoaderConfig {
+            nb_layers: 2,
+            stacking_strategy: StackingStrategy::DiffuserTop,
+            ..PaletteLoaderConfig::default()
+        };
+
+        let palette = PaletteLoader::load_from_str(json, config).unwrap();
+        assert_eq!(palette.base_hex(), "#F5F0E6");
+        assert_eq!(
+            palette.hex_color_groups().last().unwrap(),
+            &vec!["#F5F0E6".to_string()]
+        );
+
+        // The base filament acts as the diffuser on the viewing side
+        for color in palette.colors() {
+            let combi = palette.get_combi(&color).unwrap();
+            if !combi.layers_with_hex("#F5F0E6").is_empty() {
+                assert_eq!(combi.layers().last().unwrap().hex_code(), "#F5F0E6");
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_filament_properties() {
+        let json = r##"{
+            "#FF0000": { "name": "Red", "density": 1.27, "diameter": 2.85, "price_per_kg": 25.0,
+                "layers": { "1": { "H": 0, "S": 100, "L": 50 } } },
+            "#FFFFFF": { "name": "White", "layers": { "1": { "H": 0, "S": 0, "L": 100 } } }
+        }"##;
+        let config = || PaletteLoaderConfig {
+            nb_layers: 1,
+            ..PaletteLoaderConfig::default()
+        };
+
+        let palette = PaletteLoader::load_from_str(json, config()).unwrap();
+        let red = palette.filament_properties()["#FF0000"];
+        assert_eq!(red.density, 1.27);
+        assert_eq!(red.diameter, 2.85);
+        assert_eq!(red.price_per_kg, Some(25.0));
+        assert_eq!(
+            palette.filament_properties()["#FFFFFF"],
+            FilamentProperties::default()
+        );
+
+        let invalid = json.replace("\"density\": 1.27", "\"density\": 0");
+        let err = PaletteLoader::load_from_str(&invalid, config()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Density of #FF0000 must be positive"));
+    }
+
+    #[test]
+    fn test_load_rejects_multiple_base_filaments() {
+        let json = r##"{
+            "#FFFFFF": { "name": "White", "base": true, "layers": {
+                "1": { "H": 0, "S": 0, "L": 100 }
+            } },
+            "#F5F0E6": { "name": "Natural", "base": true, "layers": {
+                "1": { "H": 40, "S": 40, "L": 95 }
+            } }
+        }"##;
+        let config = PaletteLoaderConfig {
+            nb_layers: 1,
+            ..PaletteLoaderConfig::default()
+        };
+
+        let err = PaletteLoader::load_from_str(json, config).unwrap_err();
+        assert!(err.to_string().contains("Only one base filament"));
+    }
+
+    #[test]
+    fn test_load_monochrome_uses_neutral_filaments() {
+        let json = r##"{
+            "#FFFFFF": { "name": "White", "layers": {
+                "1": { "H": 0, "S": 0, "L": 100 },
+                "2": { "H": 0, "S": 0, "L": 100 }
+            } },
+            "#808080": { "name": "Grey", "layers": {
+                "1": { "H": 0, "S": 0, "L": 75 },
+                "2": { "H": 0, "S": 0, "L": 55 }
+            } },
+            "#000000": { "name": "Black", "layers": {
+                "1": { "H": 0, "S": 0, "L": 30 },
+                "2": { "H": 0, "S": 0, "L": 10 }
+            } },
+            "#FF0000": { "name": "Red", "layers": {
+                "1": { "H": 0, "S": 100, "L": 80 },
+                "2": { "H": 0, "S": 100, "L": 60 }
+            } }
+        }"##;
+        let config = PaletteLoaderConfig {
+            nb_layers: 2,
+            monochrome: true,
+            ..PaletteLoaderConfig::default()
+        };
+
+        let palette = PaletteLoader::load_from_str(json, config).unwrap();
+        assert!(palette.color_count() > 3);
+        for color in palette.colors() {
+            let combi = palette.get_combi(&color).unwrap();
+            assert!(combi.layers_with_hex("#FF0000").is_empty());
+            assert!(is_neutral(color));
+        }
+        assert!(palette
+            .hex_color_groups()
+            .iter()
+            .flatten()
+            .all(|hex| hex != "#FF0000"));
+
+        // A colored base filament cannot be used
+        let colored_base = json.replace(r#""name": "Red","#, r#""name": "Red", "base": true,"#);
+        let config = PaletteLoaderConfig {
+            nb_layers: 2,
+            monochrome: true,
+            ..PaletteLoaderConfig::default()
+        };
+        let err = PaletteLoader::load_from_str(&colored_base, config).unwrap_err();
+        assert!(err.to_string().contains("not neutral"));
+    }
 }
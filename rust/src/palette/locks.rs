@@ -0,0 +1,498 @@
+//! Locked color mappings for brand and spot colors
+//!
+//! Logos must show the same corporate colors in every print. A lock file maps
+//! source colors (hex codes) either to a specific stack of the palette or to
+//! the nearest palette color, optionally with a maximum ΔE. Locked pixels are
+//! mapped before the general nearest-neighbour search and are not affected by
+//! gamut mapping, posterization, color map cleanup or the outline.
+//!
+//! Resampling and compression shift the colors of a logo slightly, so a pixel
+//! is locked if it lies within a ΔE tolerance of a lock source (the closest
+//! source wins). The tolerance defaults to [`DEFAULT_LOCK_TOLERANCE`] and can
+//! be set per lock.
+//!
+//! ## Format
+//!
+//! ```json
+//! {
+//!   "#E30613": { "stack": { "#FF0000": 4, "#FFFFFF": 1 } },
+//!   "#003DA5": { "max_delta_e": 5.0 },
+//!   "#FFD500": { "tolerance": 6.0 }
+//! }
+//! ```
+//!
+//! A `stack` lists the number of layers per filament; the order of the layers
+//! is taken from the palette. Without a stack the nearest palette color is
+//! used, and `max_delta_e` makes the run fail if it is further away.
+//! `tolerance` overrides the matching tolerance of the source color.
+
+use crate::color::{CieLab, ColorDistanceMethod, Rgb};
+use crate::error::{PixestlError, Result};
+use crate::palette::{quantize, ColorCombi, Palette};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Default ΔE (CIE76) within which pixels match a lock source
+pub const DEFAULT_LOCK_TOLERANCE: f64 = 3.0;
+
+/// Lock entry in the JSON file
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ColorLockEntry {
+    /// Layers per filament hex code of the required stack
+    #[serde(default)]
+    pub stack: Option<HashMap<String, u32>>,
+    /// Maximum ΔE (CIE76) to the nearest palette color
+    #[serde(default)]
+    pub max_delta_e: Option<f64>,
+    /// ΔE (CIE76) within which pixels match the source color
+    #[serde(default)]
+    pub tolerance: Option<f64>,
+}
+
+/// What a locked source color maps to
+#[derive(Debug, Clone, PartialEq)]
+pub enum LockTarget {
+    /// A specific stack: layers per filament, sorted by filament color
+    Stack(Vec<(Rgb, u32)>),
+    /// The nearest palette color, optionally within a maximum ΔE
+    Nearest { max_delta_e: Option<f64> },
+}
+
+/// A source color with its locked target
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorLock {
+    pub source: Rgb,
+    pub target: LockTarget,
+    /// Matching tolerance of this lock (None = the tolerance of the set)
+    pub tolerance: Option<f64>,
+}
+
+/// How closely a locked color is achieved with the current palette
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockedColorReport {
+    /// Source color of the lock
+    pub source: Rgb,
+    /// Palette color the source is mapped to
+    pub color: Rgb,
+    /// ΔE (CIE76) between source and palette color
+    pub delta_e: f64,
+    /// Description of the stack, bottom to top (e.g. "White x1 + Red x4")
+    pub stack: String,
+    /// Number of image pixels matched by the lock
+    pub pixels: usize,
+}
+
+/// A set of locked color mappings
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorLocks {
+    locks: Vec<ColorLock>,
+    tolerance: f64,
+}
+
+impl Default for ColorLocks {
+    fn default() -> Self {
+        Self {
+            locks: Vec::new(),
+            tolerance: DEFAULT_LOCK_TOLERANCE,
+        }
+    }
+}
+
+impl ColorLocks {
+    /// Loads locks from a JSON file
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Parses locks from JSON (see the module documentation)
+    pub fn from_json(json: &str) -> Result<Self> {
+        let entries: HashMap<String, ColorLockEntry> = serde_json::from_str(json)?;
+        let mut locks = entries
+            .into_iter()
+            .map(|(hex, entry)| {
+                let source = Rgb::from_hex(&hex)?;
+                if entry.tolerance.is_some_and(|t| t < 0.0) {
+                    return Err(PixestlError::Config(format!(
+                        "Color lock {hex}: the tolerance must not be negative"
+                    )));
+                }
+                let target = match entry.stack {
+                    Some(stack) => {
+                        if entry.max_delta_e.is_some() {
+                            return Err(PixestlError::Config(format!(
+                                "Color lock {hex}: use either \"stack\" or \"max_delta_e\""
+                            )));
+                        }
+                        LockTarget::Stack(parse_stack(&hex, &stack)?)
+                    }
+                    None => LockTarget::Nearest {
+                        max_delta_e: entry.max_delta_e,
+                    },
+                };
+                Ok(ColorLock {
+                    source,
+                    target,
+                    tolerance: entry.tolerance,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        locks.sort_by_key(|lock| quantize::pack(&lock.source));
+        Ok(Self {
+            locks,
+            ..Self::default()
+        })
+    }
+
+    /// Sets the ΔE tolerance for locks without their own tolerance
+    #[must_use]
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Gets the ΔE tolerance for locks without their own tolerance
+    #[must_use]
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    /// Gets the locks sorted by source color
+    #[must_use]
+    pub fn locks(&self) -> &[ColorLock] {
+        &self.locks
+    }
+
+    /// Returns true if there are no locks
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.locks.is_empty()
+    }
+
+    /// Resolves the locks against a palette
+    ///
+    /// # Returns
+    ///
+    /// The source → palette color map and a report per lock
+    ///
+    /// # Errors
+    ///
+    /// Fails if a locked stack is not in the palette or the nearest palette
+    /// color is further away than the allowed ΔE.
+    pub fn resolve(
+        &self,
+        palette: &Palette,
+        method: ColorDistanceMethod,
+    ) -> Result<(HashMap<Rgb, Rgb>, Vec<LockedColorReport>)> {
+        let mut map = HashMap::new();
+        let mut reports = Vec::new();
+
+        for lock in &self.locks {
+            let color = match &lock.target {
+                LockTarget::Stack(stack) => find_stack(palette, stack).ok_or_else(|| {
+                    PixestlError::ColorLock(format!(
+                        "{}: stack {} is not in the palette",
+                        lock.source.to_hex(),
+                        stack
+                            .iter()
+                            .map(|(hex, layers)| format!("{} x{layers}", hex.to_hex()))
+                            .collect::<Vec<_>>()
+                            .join(" + ")
+                    ))
+                })?,
+                LockTarget::Nearest { max_delta_e } => {
+                    let color = palette.find_closest(&lock.source, method).ok_or_else(|| {
+                        PixestlError::ColorLock(format!(
+                            "{}: the palette has no colors",
+                            lock.source.to_hex()
+                        ))
+                    })?;
+                    let delta_e = delta_e(lock.source, color);
+                    if let Some(max) = max_delta_e {
+                        if delta_e > *max {
+                            return Err(PixestlError::ColorLock(format!(
+                                "{}: nearest palette color {} is Delta E {delta_e:.1} away (max {max})",
+                                lock.source.to_hex(),
+                                color.to_hex()
+                            )));
+                        }
+                    }
+                    color
+                }
+            };
+
+            map.insert(lock.source, color);
+            reports.push(LockedColorReport {
+                source: lock.source,
+                color,
+                delta_e: delta_e(lock.source, color),
+                stack: palette
+                    .get_combi(&color)
+                    .map(|combi| describe_stack(palette, combi))
+                    .unwrap_or_default(),
+                pixels: 0,
+            });
+        }
+
+        Ok((map, reports))
+    }
+
+    /// Finds the lock whose source is closest to a color within its tolerance
+    ///
+    /// Returns the index into [`locks`](Self::locks).
+    #[must_use]
+    pub fn match_color(&self, color: Rgb) -> Option<usize> {
+        let lab = CieLab::from(color);
+        self.locks
+            .iter()
+            .enumerate()
+            .map(|(i, lock)| (i, lab.delta_e(&CieLab::from(lock.source)), lock))
+            .filter(|(_, distance, lock)| *distance <= lock.tolerance.unwrap_or(self.tolerance))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _, _)| i)
+    }
+
+    /// Maps the image pixels that match a lock to the lock's palette color
+    ///
+    /// `targets` and `reports` come from [`resolve`](Self::resolve); the number
+    /// of matched pixels is added to the report of each lock.
+    ///
+    /// # Returns
+    ///
+    /// The pixel color → palette color map of all locked pixel colors
+    pub fn lock_pixels<'a>(
+        &self,
+        pixels: impl IntoIterator<Item = &'a Rgb>,
+        targets: &HashMap<Rgb, Rgb>,
+        reports: &mut [LockedColorReport],
+    ) -> HashMap<Rgb, Rgb> {
+        let mut matches: HashMap<Rgb, Option<usize>> = HashMap::new();
+        let mut locked = HashMap::new();
+        for pixel in pixels {
+            let lock = *matches
+                .entry(*pixel)
+                .or_insert_with(|| self.match_color(*pixel));
+            if let Some(i) = lock {
+                let source = self.locks[i].source;
+                locked.insert(*pixel, targets[&source]);
+                if let Some(report) = reports.iter_mut().find(|r| r.source == source) {
+                    report.pixels += 1;
+                }
+            }
+        }
+        locked
+    }
+}
+
+/// Parses the layers per filament of a stack lock
+fn parse_stack(hex: &str, stack: &HashMap<String, u32>) -> Result<Vec<(Rgb, u32)>> {
+    let mut layers = stack
+        .iter()
+        .filter(|(_, &layers)| layers > 0)
+        .map(|(filament, &layers)| Ok((Rgb::from_hex(filament)?, layers)))
+        .collect::<Result<Vec<_>>>()?;
+    if layers.is_empty() {
+        return Err(PixestlError::Config(format!(
+            "Color lock {hex}: the stack has no layers"
+        )));
+    }
+    layers.sort_by_key(|(color, _)| quantize::pack(color));
+    Ok(layers)
+}
+
+/// Finds the palette color of the stack with the given layers per filament
+///
+/// If several stacks match (different layer orders), the one with the lowest
+/// packed color is used.
+fn find_stack(palette: &Palette, stack: &[(Rgb, u32)]) -> Option<Rgb> {
+    palette
+        .quantized_colors
+        .iter()
+        .filter(|(_, combi)| stack_layers(combi).as_deref() == Some(stack))
+        .map(|(color, _)| *color)
+        .min_by_key(quantize::pack)
+}
+
+/// Layers per filament of a stack, sorted like a parsed lock
+fn stack_layers(combi: &ColorCombi) -> Option<Vec<(Rgb, u32)>> {
+    let mut layers: Vec<(Rgb, u32)> = Vec::new();
+    for layer in combi.layers() {
+        let color = Rgb::from_hex(layer.hex_code()).ok()?;
+        match layers.iter_mut().find(|(c, _)| *c == color) {
+            Some((_, count)) => *count += layer.layer(),
+            None => layers.push((color, layer.layer())),
+        }
+    }
+    layers.retain(|(_, count)| *count > 0);
+    layers.sort_by_key(|(color, _)| quantize::pack(color));
+    Some(layers)
+}
+
+/// Describes a stack bottom to top with filament names
+fn describe_stack(palette: &Palette, combi: &ColorCombi) -> String {
+    combi
+        .layers()
+        .iter()
+        .filter(|layer| layer.layer() > 0)
+        .map(|layer| {
+            let name = palette
+                .get_color_name(layer.hex_code())
+                .unwrap_or(layer.hex_code());
+            format!("{name} x{}", layer.layer())
+        })
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+fn delta_e(a: Rgb, b: Rgb) -> f64 {
+    CieLab::from(a).delta_e(&CieLab::from(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::ColorLayer;
+    use std::collections::HashMap;
+
+    /// Palette with a solid red, a solid white and a red/white stack
+    fn test_palette() -> Palette {
+        let mut palette = Palette::new(2);
+        palette.set_hex_codes(HashMap::from([
+            ("#FF0000".to_string(), "Red".to_string()),
+            ("#FFFFFF".to_string(), "White".to_string()),
+        ]));
+        let red = ColorLayer::new("#FF0000".to_string(), 2, 0.0, 1.0, 0.5);
+        let white = ColorLayer::new("#FFFFFF".to_string(), 2, 0.0, 0.0, 1.0);
+        let mixed = ColorCombi::from_layers(vec![
+            ColorLayer::new("#FFFFFF".to_string(), 1, 0.0, 0.0, 1.0),
+            ColorLayer::new("#FF0000".to_string(), 1, 0.0, 1.0, 0.5),
+        ]);
+        palette.insert_combi(Rgb::new(255, 0, 0), ColorCombi::new(red));
+        palette.insert_combi(Rgb::new(255, 255, 255), ColorCombi::new(white));
+        palette.insert_combi(Rgb::new(255, 128, 128), mixed);
+        palette
+    }
+
+    #[test]
+    fn test_from_json() {
+        let locks = ColorLocks::from_json(
+            r##"{
+                "#e30613": { "stack": { "#FF0000": 1, "#ffffff": 1 } },
+                "#003DA5": { "max_delta_e": 5.0 },
+                "#FFD500": {}
+            }"##,
+        )
+        .unwrap();
+
+        assert_eq!(locks.locks().len(), 3);
+        assert_eq!(locks.locks()[0].source, Rgb::new(0, 61, 165));
+        assert_eq!(
+            locks.locks()[0].target,
+            LockTarget::Nearest {
+                max_delta_e: Some(5.0)
+            }
+        );
+        assert_eq!(
+            locks.locks()[1].target,
+            LockTarget::Stack(vec![(Rgb::new(255, 0, 0), 1), (Rgb::new(255, 255, 255), 1)])
+        );
+    }
+
+    #[test]
+    fn test_from_json_rejects_invalid_entries() {
+        assert!(ColorLocks::from_json(r#"{ "red": {} }"#).is_err());
+        assert!(ColorLocks::from_json(r##"{ "#FF0000": { "stack": {} } }"##).is_err());
+        assert!(ColorLocks::from_json(
+            r##"{ "#FF0000": { "stack": { "#FF0000": 1 }, "max_delta_e": 2.0 } }"##
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_resolve_stack_lock() {
+        let locks = ColorLocks::from_json(
+            r##"{ "#E30613": { "stack": { "#FF0000": 1, "#FFFFFF": 1 } } }"##,
+        )
+        .unwrap();
+        let (map, reports) = locks
+            .resolve(&test_palette(), ColorDistanceMethod::CieLab)
+            .unwrap();
+
+        // The lock wins over the closer solid red
+        assert_eq!(map[&Rgb::new(227, 6, 19)], Rgb::new(255, 128, 128));
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].stack, "White x1 + Red x1");
+        assert!(reports[0].delta_e > 0.0);
+    }
+
+    #[test]
+    fn test_resolve_missing_stack_fails() {
+        let locks =
+            ColorLocks::from_json(r##"{ "#E30613": { "stack": { "#FF0000": 3 } } }"##).unwrap();
+        let err = locks
+            .resolve(&test_palette(), ColorDistanceMethod::CieLab)
+            .unwrap_err();
+        assert!(err.to_string().contains("not in the palette"));
+    }
+
+    #[test]
+    fn test_match_color_within_tolerance() {
+        let locks =
+            ColorLocks::from_json(r##"{ "#E30613": {}, "#003DA5": { "tolerance": 10.0 } }"##)
+                .unwrap();
+        let red = locks
+            .locks()
+            .iter()
+            .position(|l| l.source == Rgb::new(227, 6, 19));
+        let blue = locks
+            .locks()
+            .iter()
+            .position(|l| l.source == Rgb::new(0, 61, 165));
+
+        assert_eq!(locks.match_color(Rgb::new(227, 6, 19)), red);
+        assert_eq!(locks.match_color(Rgb::new(228, 7, 20)), red);
+        assert_eq!(locks.match_color(Rgb::new(200, 6, 19)), None);
+        // The per-lock tolerance overrides the default
+        assert_eq!(locks.match_color(Rgb::new(10, 70, 170)), blue);
+        assert!(ColorLocks::from_json(r##"{ "#E30613": { "tolerance": -1.0 } }"##).is_err());
+
+        let exact = locks.with_tolerance(0.0);
+        assert_eq!(exact.match_color(Rgb::new(228, 7, 20)), None);
+        assert_eq!(exact.match_color(Rgb::new(227, 6, 19)), red);
+    }
+
+    #[test]
+    fn test_lock_pixels_counts_pixels() {
+        let locks = ColorLocks::from_json(r##"{ "#FA0000": {} }"##).unwrap();
+        let (targets, mut reports) = locks
+            .resolve(&test_palette(), ColorDistanceMethod::CieLab)
+            .unwrap();
+        let pixels = [
+            Rgb::new(250, 0, 0),
+            Rgb::new(251, 1, 0),
+            Rgb::new(250, 0, 0),
+            Rgb::new(0, 0, 255),
+        ];
+        let locked = locks.lock_pixels(&pixels, &targets, &mut reports);
+
+        assert_eq!(locked.len(), 2);
+        assert_eq!(locked[&Rgb::new(251, 1, 0)], Rgb::new(255, 0, 0));
+        assert_eq!(reports[0].pixels, 3);
+    }
+
+    #[test]
+    fn test_resolve_nearest_within_delta_e() {
+        let palette = test_palette();
+        let near = ColorLocks::from_json(r##"{ "#FA0000": { "max_delta_e": 5.0 } }"##).unwrap();
+        let (map, reports) = near.resolve(&palette, ColorDistanceMethod::CieLab).unwrap();
+        assert_eq!(map[&Rgb::new(250, 0, 0)], Rgb::new(255, 0, 0));
+        assert_eq!(reports[0].stack, "Red x2");
+
+        let far = ColorLocks::from_json(r##"{ "#003DA5": { "max_delta_e": 5.0 } }"##).unwrap();
+        let err = far
+            .resolve(&palette, ColorDistanceMethod::CieLab)
+            .unwrap_err();
+        assert!(err.to_string().contains("Delta E"));
+    }
+}
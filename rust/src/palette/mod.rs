@@ -14,30 +14,82 @@
 //! 2. **ColorLayer erzeugen**: Jeder Filament-Eintrag wird in einen `ColorLayer` umgewandelt.
 //!    Der CMYK-Wert wird aus der HSL-Farbe berechnet und beschreibt die Deckkraft des Filaments.
 //!
-//! 3. **Kombinationen berechnen** (`create_multi_combi`): Alle gültigen Stapel von ColorLayern
-//!    werden rekursiv berechnet, sodass die Gesamtschichtzahl genau dem Zielwert entspricht.
-//!    Beispiel bei 5 Schichten: `Rot[3]+Weiß[2]`, `Cyan[2]+Magenta[1]+Weiß[2]`, usw.
+//! 3. **Kombinationen berechnen** (`generate_combis`): Alle gültigen Stapel von ColorLayern
+//!    werden als Zerlegungen der Zielschichtzahl parallel aufgezählt (jedes Filament höchstens
+//!    einmal pro Stapel). Beispiel bei 5 Schichten: `Rot[3]+Weiß[2]`,
+//!    `Cyan[2]+Magenta[1]+Weiß[2]`, usw. Nahezu gleiche Farben können per ΔE-Schwelle früh
+//!    verworfen werden; eine harte Obergrenze begrenzt die Anzahl. Mit variabler Tiefe
+//!    (`min_stack_layers`) sind auch kürzere Stapel erlaubt; die fehlende Höhe übernimmt
+//!    die Texturschicht oder die Stützplatte.
 //!
-//! 4. **RGB-Farbe berechnen**: Für jede Kombination wird die resultierende Mischfarbe als
-//!    RGB-Wert berechnet. Die CMYK-Werte werden addiert und dann in RGB umgerechnet.
+//! 4. **RGB-Farbe berechnen**: Die Schichten jeder Kombination werden nach der gewählten
+//!    Stapelstrategie (`stacking`) angeordnet, dann wird die resultierende Mischfarbe als
+//!    RGB-Wert berechnet. Die CMYK-Werte werden addiert und dann in RGB umgerechnet; das
+//!    optionale reihenfolgeabhängige Mischmodell schwächt verdeckte Schichten ab.
 //!
-//! 5. **Quantisierung**: Jeder Pixel des Eingangsbildes wird der ähnlichsten Palette-Farbe
+//! 5. **Gamut-Mapping** (optional, `gamut`): Bildfarben außerhalb der erreichbaren
+//!    Palette-Farben werden vor der Quantisierung weich in die konvexe Hülle der
+//!    Palette (im CIELab-Raum) komprimiert, damit keine flachen Flächen entstehen.
+//!
+//! 6. **Quantisierung**: Jeder Pixel des Eingangsbildes wird der ähnlichsten Palette-Farbe
 //!    zugeordnet (via Delta-E-Abstand im CIELab-Farbraum oder euklidischem RGB-Abstand).
+//!    Im Monochrom-Modus werden nur neutrale Filamente (Weiß, Grau, Schwarz) gestapelt
+//!    und nur die Helligkeit L* verglichen. Im Poster-Modus (`posterize`) werden die
+//!    Bildfarben vorher per k-Means bzw. Median-Cut in CIELab auf K Cluster reduziert;
+//!    nur die zu den Clusterzentren nächsten K Stapel stehen dann zur Auswahl.
+//!
+//! 7. **Stapelreihenfolge** (optional, `ordering`): Die Schichten der im Bild verwendeten
+//!    Stapel werden gemeinsam so umsortiert, dass jede Druckschicht möglichst wenige
+//!    verschiedene Filamente enthält (weniger Werkzeugwechsel). Die Mischfarbe ändert sich
+//!    dabei nicht.
+//!
+//! Die Schritte 2–4 sowie die Zuordnung Bildfarbe → Palette-Farbe können mit
+//! `PaletteCache` auf der Festplatte zwischengespeichert werden.
 
+pub mod cache;
 pub mod color_combi;
 pub mod color_layer;
+pub mod gamut;
 pub mod generator;
+pub mod index;
 pub mod loader;
+pub mod locks;
+pub mod ordering;
+pub mod posterize;
 pub mod quantize;
+pub mod stacking;
 
+pub use cache::PaletteCache;
 pub use color_combi::ColorCombi;
 pub use color_layer::ColorLayer;
-pub use generator::create_multi_combi;
-pub use loader::{PaletteColorEntry, PaletteLoader, PaletteLoaderConfig, PixelCreationMethod};
-pub use quantize::{quantize_image, quantize_pixels, quantize_with_stats, QuantizationStats};
-
-use crate::color::{find_closest_color, ColorDistanceMethod, Rgb};
-use std::collections::HashMap;
+pub use gamut::{GamutHull, GamutMapper, GamutMappingMethod};
+pub use generator::{
+    combine_groups, create_multi_combi, generate_combis, CombinationReport, GeneratorOptions,
+};
+pub use index::ColorIndex;
+pub use loader::{
+    estimate_transmission_distance, PaletteColorEntry, PaletteLoader, PaletteLoaderConfig,
+    PixelCreationMethod,
+};
+pub use locks::{
+    ColorLock, ColorLockEntry, ColorLocks, LockTarget, LockedColorReport, DEFAULT_LOCK_TOLERANCE,
+};
+pub use ordering::{
+    estimate_tool_changes, optimize_stack_order, optimize_stack_order_ranked, ToolChangeReport,
+};
+pub use posterize::{cluster_colors, posterize_palette_colors, PosterizeMethod};
+pub use quantize::{
+    quantize_image, quantize_image_cached, quantize_pixels, quantize_with_stats, ColorLookup,
+    DeltaESummary, QuantizationStats,
+};
+pub use stacking::{MixingModel, StackingStrategy, DEFAULT_BASE_HEX, ORDER_FALLOFF};
+
+use crate::color::{CieLab, ColorDistanceMethod, Rgb};
+use crate::filament::FilamentProperties;
+use generator::DeltaEFilter;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, OnceLock};
 
 /// A palette of colors with combinations for lithophane generation
 #[derive(Debug, Clone)]
@@ -59,6 +111,32 @@ pub struct Palette {
 
     /// Color groups for AMS (each group contains hex codes)
     hex_color_group_list: Vec<Vec<String>>,
+
+    /// Lazily built spatial indices over `colors()` (RGB / CIELab)
+    rgb_index: OnceLock<ColorIndex>,
+    lab_index: OnceLock<ColorIndex>,
+    luminance_index: OnceLock<ColorIndex>,
+
+    /// Optional persistent RGB → palette color lookup (see `PaletteCache`)
+    color_lookup: Option<Arc<Mutex<ColorLookup>>>,
+
+    /// Statistics of the combination generation
+    combination_report: Option<CombinationReport>,
+
+    /// Order of the runs inside each stack
+    stacking_strategy: StackingStrategy,
+
+    /// Model used to predict the color of a stack
+    mixing_model: MixingModel,
+
+    /// Hex code of the base (white / diffuser) filament
+    base_hex: String,
+
+    /// Transmission distance in mm per active filament (hex code)
+    transmission_distances: HashMap<String, f64>,
+
+    /// Density, diameter and price per active filament (hex code)
+    filament_properties: HashMap<String, FilamentProperties>,
 }
 
 impl Palette {
@@ -71,6 +149,16 @@ impl Palette {
             nb_groups: 0,
             layer_count: 0,
             hex_color_group_list: Vec::new(),
+            rgb_index: OnceLock::new(),
+            lab_index: OnceLock::new(),
+            luminance_index: OnceLock::new(),
+            color_lookup: None,
+            combination_report: None,
+            stacking_strategy: StackingStrategy::default(),
+            mixing_model: MixingModel::default(),
+            base_hex: DEFAULT_BASE_HEX.to_string(),
+            transmission_distances: HashMap::new(),
+            filament_properties: HashMap::new(),
         }
     }
 
@@ -109,6 +197,14 @@ impl Palette {
         self.nb_groups
     }
 
+    /// Gets the full height of a color stack in layers (all AMS groups)
+    ///
+    /// Variable-depth stacks may be lower; the difference is filled by the
+    /// texture layer or the plate.
+    pub fn stack_height(&self) -> u32 {
+        self.nb_layers * self.nb_groups.max(1) as u32
+    }
+
     /// Gets the total layer count
     pub fn layer_count(&self) -> usize {
         self.layer_count
@@ -121,17 +217,164 @@ impl Palette {
 
     /// Finds the closest palette color to the given RGB color
     pub fn find_closest(&self, color: &Rgb, method: ColorDistanceMethod) -> Option<Rgb> {
-        let colors = self.colors();
-        if colors.is_empty() {
-            return None;
+        self.color_index(method).nearest(color)
+    }
+
+    /// Gets the spatial index over all palette colors for the given method
+    ///
+    /// The index is built on first use and reused for subsequent lookups.
+    pub fn color_index(&self, method: ColorDistanceMethod) -> &ColorIndex {
+        let cell = match method {
+            ColorDistanceMethod::Rgb => &self.rgb_index,
+            ColorDistanceMethod::CieLab => &self.lab_index,
+            ColorDistanceMethod::Luminance => &self.luminance_index,
+        };
+        cell.get_or_init(|| ColorIndex::new(&self.colors(), method))
+    }
+
+    /// Gets the image colors that map to a palette color without a distance search
+    ///
+    /// Every palette color maps to itself, and the hex code of each filament
+    /// maps to the tallest stack printed from that filament alone (if any).
+    /// Used for pixel art drawn with the palette colors.
+    pub fn exact_color_map(&self) -> HashMap<Rgb, Rgb> {
+        let mut solid: HashMap<&str, (u32, Rgb)> = HashMap::new();
+        for (color, combi) in &self.quantized_colors {
+            let Some(first) = combi.layers().first() else {
+                continue;
+            };
+            let hex = first.hex_code();
+            if combi.layers().iter().any(|l| l.hex_code() != hex) {
+                continue;
+            }
+            let candidate = (combi.total_layers(), *color);
+            solid
+                .entry(hex)
+                .and_modify(|best| {
+                    if (candidate.0, Reverse(quantize::pack(&candidate.1)))
+                        > (best.0, Reverse(quantize::pack(&best.1)))
+                    {
+                        *best = candidate;
+                    }
+                })
+                .or_insert(candidate);
+        }
+
+        let mut map: HashMap<Rgb, Rgb> = self.quantized_colors.keys().map(|&c| (c, c)).collect();
+        for (hex, (_, color)) in solid {
+            if let Ok(filament) = Rgb::from_hex(hex) {
+                map.entry(filament).or_insert(color);
+            }
         }
-        find_closest_color(color, &colors, method).ok()
+        map
+    }
+
+    /// Gets the statistics of the combination generation, if available
+    pub fn combination_report(&self) -> Option<&CombinationReport> {
+        self.combination_report.as_ref()
+    }
+
+    /// Sets the combination generation statistics
+    pub(crate) fn set_combination_report(&mut self, report: CombinationReport) {
+        self.combination_report = Some(report);
+    }
+
+    /// Gets the quantization lookup attached to this palette, if any
+    pub fn color_lookup(&self) -> Option<&Mutex<ColorLookup>> {
+        self.color_lookup.as_deref()
+    }
+
+    /// Attaches a quantization lookup that is reused for all images
+    ///
+    /// The lookup must have been filled with this palette's colors.
+    pub fn set_color_lookup(&mut self, lookup: ColorLookup) {
+        self.color_lookup = Some(Arc::new(Mutex::new(lookup)));
     }
 
     /// Adds a color combination to the palette
+    ///
+    /// The color is predicted with the palette's mixing model. If another
+    /// combination already produces the same RGB color, the cheaper stack (see
+    /// [`ColorCombi::cost`]) is kept.
     pub(crate) fn add_combi(&mut self, combi: ColorCombi) {
-        let color = combi.compute_rgb();
+        let color = combi.compute_rgb_with(self.mixing_model);
+        if let Some(existing) = self.quantized_colors.get(&color) {
+            if combi.cmp_cost(existing) != std::cmp::Ordering::Less {
+                return;
+            }
+        }
+        self.insert_combi(color, combi);
+    }
+
+    /// Merges combinations whose colors are closer than `delta_e` (CIE76)
+    ///
+    /// Within each group of similar colors the cheapest stack (fewest distinct
+    /// filaments, then fewest tool changes) is kept; the others are removed, so
+    /// their pixels are matched to the kept neighbour.
+    ///
+    /// # Returns
+    ///
+    /// The number of removed combinations
+    pub fn merge_similar(&mut self, delta_e: f64) -> usize {
+        if delta_e <= 0.0 {
+            return 0;
+        }
+
+        let before = self.quantized_colors.len();
+        let mut entries: Vec<(Rgb, ColorCombi)> = self.quantized_colors.drain().collect();
+        entries.sort_by(|(color_a, a), (color_b, b)| {
+            a.cmp_cost(b).then_with(|| {
+                (color_a.r, color_a.g, color_a.b).cmp(&(color_b.r, color_b.g, color_b.b))
+            })
+        });
+
+        let mut filter = DeltaEFilter::new(delta_e);
+        for (color, combi) in entries {
+            if filter.insert(CieLab::from(color)) {
+                self.insert_combi(color, combi);
+            }
+        }
+
+        before - self.quantized_colors.len()
+    }
+
+    /// Reorders the layers of the stacks used by an image to reduce tool changes
+    ///
+    /// Only permutations allowed by the stacking strategy and mixing model are
+    /// used (see [`StackingStrategy`]), so the colors of the stacks do not
+    /// change and the palette stays valid for lookups. See
+    /// [`optimize_stack_order_ranked`].
+    ///
+    /// # Arguments
+    ///
+    /// * `used_colors` - Palette colors that appear in the quantized image
+    ///
+    /// # Returns
+    ///
+    /// The estimated tool changes before and after
+    pub fn optimize_stack_order(&mut self, used_colors: &HashSet<Rgb>) -> ToolChangeReport {
+        let mut used: Vec<(&Rgb, &mut ColorCombi)> = self
+            .quantized_colors
+            .iter_mut()
+            .filter(|(color, _)| used_colors.contains(color))
+            .collect();
+        used.sort_by_key(|(color, _)| (color.r, color.g, color.b));
+
+        let mut combis: Vec<&mut ColorCombi> = used.into_iter().map(|(_, c)| c).collect();
+        let (strategy, model) = (self.stacking_strategy, self.mixing_model);
+        let base_hex = self.base_hex.as_str();
+        optimize_stack_order_ranked(&mut combis, |combi| {
+            strategy.permutation_ranks(combi.layers(), base_hex, model)
+        })
+    }
+
+    /// Adds a color combination under an already computed RGB color
+    pub(crate) fn insert_combi(&mut self, color: Rgb, combi: ColorCombi) {
         self.quantized_colors.insert(color, combi);
+        self.rgb_index = OnceLock::new();
+        self.lab_index = OnceLock::new();
+        self.luminance_index = OnceLock::new();
+        self.color_lookup = None;
     }
 
     /// Sets the hex codes map
@@ -150,11 +393,53 @@ impl Palette {
         self.hex_color_group_list = groups;
     }
 
-    /// Optimizes white layers (moves white to bottom and top)
-    pub(crate) fn optimize_white_layers(&mut self, nb_color_pool: usize) {
-        for combi in self.quantized_colors.values_mut() {
-            combi.optimize_white_layers(nb_color_pool, self.nb_layers as usize);
-        }
+    /// Gets the stacking strategy of the palette's stacks
+    pub fn stacking_strategy(&self) -> StackingStrategy {
+        self.stacking_strategy
+    }
+
+    /// Gets the mixing model used to compute the palette colors
+    pub fn mixing_model(&self) -> MixingModel {
+        self.mixing_model
+    }
+
+    /// Gets the hex code of the base (white / diffuser) filament
+    pub fn base_hex(&self) -> &str {
+        &self.base_hex
+    }
+
+    /// Gets the transmission distance in mm of every active filament
+    pub fn transmission_distances(&self) -> &HashMap<String, f64> {
+        &self.transmission_distances
+    }
+
+    /// Sets the transmission distances of the active filaments
+    pub(crate) fn set_transmission_distances(&mut self, distances: HashMap<String, f64>) {
+        self.transmission_distances = distances;
+    }
+
+    /// Gets the material properties of every active filament
+    pub fn filament_properties(&self) -> &HashMap<String, FilamentProperties> {
+        &self.filament_properties
+    }
+
+    /// Sets the material properties of the active filaments
+    pub(crate) fn set_filament_properties(
+        &mut self,
+        properties: HashMap<String, FilamentProperties>,
+    ) {
+        self.filament_properties = properties;
+    }
+
+    /// Sets the hex code of the base filament
+    pub(crate) fn set_base_hex(&mut self, base_hex: String) {
+        self.base_hex = base_hex;
+    }
+
+    /// Sets the stacking strategy and mixing model (before adding combinations)
+    pub(crate) fn set_stacking(&mut self, strategy: StackingStrategy, model: MixingModel) {
+        self.stacking_strategy = strategy;
+        self.mixing_model = model;
     }
 }
 
@@ -181,6 +466,39 @@ mod tests {
         assert_eq!(palette.color_count(), 1);
     }
 
+    #[test]
+    fn test_exact_color_map() {
+        let mut palette = Palette::new(5);
+        palette.add_combi(ColorCombi::new(ColorLayer::new(
+            "#FF0000".to_string(),
+            5,
+            0.0,
+            100.0,
+            50.0,
+        )));
+        palette.add_combi(ColorCombi::new(ColorLayer::new(
+            "#FF0000".to_string(),
+            2,
+            0.0,
+            100.0,
+            80.0,
+        )));
+        let mut mixed =
+            ColorCombi::new(ColorLayer::new("#FF0000".to_string(), 2, 0.0, 100.0, 80.0));
+        mixed.add_layer(ColorLayer::new("#FFFFFF".to_string(), 3, 0.0, 0.0, 100.0));
+        palette.add_combi(mixed);
+
+        let map = palette.exact_color_map();
+        for color in palette.colors() {
+            assert_eq!(map.get(&color), Some(&color));
+        }
+
+        // The filament hex code maps to its tallest solid stack
+        let solid = *map.get(&Rgb::new(255, 0, 0)).unwrap();
+        assert_eq!(palette.get_combi(&solid).unwrap().total_layers(), 5);
+        assert!(!map.contains_key(&Rgb::new(255, 255, 255)));
+    }
+
     #[test]
     fn test_palette_find_closest() {
         let mut palette = Palette::new(5);
@@ -194,6 +512,127 @@ mod tests {
         assert!(closest.is_some());
     }
 
+    #[test]
+    fn test_palette_find_closest_after_add_combi() {
+        let mut palette = Palette::new(5);
+        assert_eq!(
+            palette.find_closest(&Rgb::new(0, 0, 0), ColorDistanceMethod::CieLab),
+            None
+        );
+
+        let red_layer = ColorLayer::new("#FF0000".to_string(), 5, 0.0, 100.0, 50.0);
+        palette.add_combi(ColorCombi::new(red_layer));
+        let blue_layer = ColorLayer::new("#0000FF".to_string(), 5, 240.0, 100.0, 50.0);
+        palette.add_combi(ColorCombi::new(blue_layer));
+
+        // The cached index is rebuilt after adding combinations
+        assert_eq!(palette.color_index(ColorDistanceMethod::CieLab).len(), 2);
+        for color in palette.colors() {
+            assert_eq!(
+                palette.find_closest(&color, ColorDistanceMethod::CieLab),
+                Some(color)
+            );
+        }
+    }
+
+    #[test]
+    fn test_add_combi_keeps_cheaper_stack() {
+        let mut palette = Palette::new(4);
+        let black = |n| ColorLayer::from_cmyk("#000000".to_string(), n, 0.0, 0.0, 0.0, 1.0);
+        let red = |n| ColorLayer::from_cmyk("#FF0000".to_string(), n, 0.0, 1.0, 1.0, 0.0);
+
+        // Both stacks saturate to pure black
+        let mut mixed = ColorCombi::new(red(2));
+        mixed.add_layer(black(2));
+        let pure = ColorCombi::new(black(4));
+        assert_eq!(mixed.compute_rgb(), pure.compute_rgb());
+
+        palette.add_combi(pure.clone());
+        palette.add_combi(mixed.clone());
+        assert_eq!(palette.get_combi(&pure.compute_rgb()), Some(&pure));
+
+        // Insertion order does not matter
+        let mut palette = Palette::new(4);
+        palette.add_combi(mixed);
+        palette.add_combi(pure.clone());
+        assert_eq!(palette.get_combi(&pure.compute_rgb()), Some(&pure));
+    }
+
+    #[test]
+    fn test_optimize_stack_order_only_used_colors() {
+        let white = ColorLayer::from_cmyk("#FFFFFF".to_string(), 1, 0.0, 0.0, 0.0, 0.0);
+        let red = |m| ColorLayer::from_cmyk("#FF0000".to_string(), 1, 0.0, m, m, 0.0);
+        let cyan = |c| ColorLayer::from_cmyk("#00FFFF".to_string(), 1, c, 0.0, 0.0, 0.0);
+        let a = ColorCombi::from_layers(vec![white.clone(), red(0.5), cyan(0.5)]);
+        let b = ColorCombi::from_layers(vec![white.clone(), cyan(0.3), red(0.3)]);
+        let unused = ColorCombi::from_layers(vec![cyan(0.2), white, red(0.2)]);
+        let used: HashSet<Rgb> = [a.compute_rgb(), b.compute_rgb()].into_iter().collect();
+
+        let create = |strategy| {
+            let mut palette = Palette::new(3);
+            palette.set_stacking(strategy, MixingModel::Additive);
+            for combi in [&a, &b, &unused] {
+                palette.add_combi(combi.clone());
+            }
+            palette
+        };
+
+        let mut palette = create(StackingStrategy::Classic);
+        let report = palette.optimize_stack_order(&used);
+
+        assert_eq!(report.before, 2);
+        assert_eq!(report.after, 0);
+        let hex_order = |palette: &Palette, color: &Rgb| -> Vec<String> {
+            palette
+                .get_combi(color)
+                .unwrap()
+                .layers()
+                .iter()
+                .map(|l| l.hex_code().to_string())
+                .collect()
+        };
+        assert_eq!(
+            hex_order(&palette, &a.compute_rgb()),
+            hex_order(&palette, &b.compute_rgb())
+        );
+        // White stays in place and unused stacks are untouched
+        assert_eq!(hex_order(&palette, &a.compute_rgb())[0], "#FFFFFF");
+        assert_eq!(palette.get_combi(&unused.compute_rgb()), Some(&unused));
+
+        // Strategies with a fixed order are not reordered
+        let mut palette = create(StackingStrategy::DarkFirst);
+        let report = palette.optimize_stack_order(&used);
+        assert_eq!(report.before, report.after);
+        assert_eq!(palette.get_combi(&b.compute_rgb()), Some(&b));
+    }
+
+    #[test]
+    fn test_merge_similar_keeps_cheapest() {
+        let mut palette = Palette::new(2);
+        let white = |n| ColorLayer::from_cmyk("#FFFFFF".to_string(), n, 0.0, 0.0, 0.0, 0.0);
+        let gray = |n, k| ColorLayer::from_cmyk("#808080".to_string(), n, 0.0, 0.0, 0.0, k);
+
+        // Two nearly identical light grays: a two-filament stack and a single filament
+        let mut two = ColorCombi::new(white(1));
+        two.add_layer(gray(1, 0.10));
+        let one = ColorCombi::new(gray(2, 0.11));
+        // A clearly different dark gray
+        let dark = ColorCombi::new(gray(2, 0.8));
+
+        palette.add_combi(two);
+        palette.add_combi(one.clone());
+        palette.add_combi(dark.clone());
+        assert_eq!(palette.color_count(), 3);
+
+        assert_eq!(palette.merge_similar(0.0), 0);
+        let merged = palette.merge_similar(5.0);
+
+        assert_eq!(merged, 1);
+        assert_eq!(palette.color_count(), 2);
+        assert_eq!(palette.get_combi(&one.compute_rgb()), Some(&one));
+        assert_eq!(palette.get_combi(&dark.compute_rgb()), Some(&dark));
+    }
+
     #[test]
     fn test_palette_hex_codes() {
         let mut palette = Palette::new(5);
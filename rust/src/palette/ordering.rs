@@ -0,0 +1,441 @@
+//! Tool-change-aware ordering of the layers inside color stacks
+//!
+//! Every color pixel is a stack of filament runs (e.g. `White[2]+Cyan[2]+Red[1]`).
+//! The printer prints Z layer by Z layer, so every filament that appears at a
+//! given height anywhere in the image costs a tool change on that layer. The
+//! order of the runs inside a stack comes from the generator and is decided
+//! one combination at a time, which scatters the filaments over the heights.
+//!
+//! The additive CMYK mixing model does not depend on the order of the layers,
+//! so every permutation of a stack's runs yields the same color. This module
+//! reorders the runs of all stacks used by an image together so that each Z
+//! layer contains as few distinct filaments as possible:
+//!
+//! - stacks with up to [`EXHAUSTIVE_MAX_RUNS`] runs try every permutation,
+//!   larger stacks are placed greedily from the bottom up,
+//! - each stack is placed against the layers occupied by all other stacks,
+//!   and the passes repeat until nothing improves,
+//! - ties keep the current order, so the result is deterministic,
+//! - [`optimize_stack_order_ranked`] restricts the permutations, e.g. to keep
+//!   the runs placed by a [`StackingStrategy`](super::StackingStrategy).
+
+use super::ColorCombi;
+use std::collections::{HashMap, HashSet};
+
+/// Stacks with up to this many runs are ordered exhaustively (6! = 720 orders)
+const EXHAUSTIVE_MAX_RUNS: usize = 6;
+
+/// Maximum number of improvement passes over all stacks
+const MAX_PASSES: usize = 8;
+
+/// Estimated tool changes before and after reordering
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToolChangeReport {
+    /// Estimated tool changes with the original stack order
+    pub before: usize,
+    /// Estimated tool changes after reordering
+    pub after: usize,
+}
+
+/// Estimates the tool changes needed to print the given stacks
+///
+/// A Z layer that contains `n` distinct filaments needs at least `n - 1`
+/// tool changes; the estimate is the sum over all color layers.
+pub fn estimate_tool_changes<'a>(combis: impl IntoIterator<Item = &'a ColorCombi>) -> usize {
+    let mut layers: Vec<HashSet<&str>> = Vec::new();
+    for combi in combis {
+        let mut z = 0;
+        for layer in combi.layers() {
+            for _ in 0..layer.layer() {
+                if layers.len() <= z {
+                    layers.push(HashSet::new());
+                }
+                layers[z].insert(layer.hex_code());
+                z += 1;
+            }
+        }
+    }
+    layers.iter().map(|l| l.len().saturating_sub(1)).sum()
+}
+
+/// Reorders the layers of the given stacks to minimize the distinct filaments per Z layer
+///
+/// # Arguments
+///
+/// * `combis` - Stacks to reorder together (typically all stacks used by an image),
+///   in a deterministic order
+///
+/// # Returns
+///
+/// The estimated tool changes before and after
+pub fn optimize_stack_order(combis: &mut [&mut ColorCombi]) -> ToolChangeReport {
+    optimize_stack_order_ranked(combis, |combi| vec![0; combi.layers().len()])
+}
+
+/// Reorders the layers of the given stacks within the allowed permutations
+///
+/// # Arguments
+///
+/// * `combis` - Stacks to reorder together, in a deterministic order
+/// * `ranks` - Rank of every run of a stack (non-decreasing from bottom to top);
+///   only runs of equal rank may swap places
+///
+/// # Returns
+///
+/// The estimated tool changes before and after
+pub fn optimize_stack_order_ranked(
+    combis: &mut [&mut ColorCombi],
+    ranks: impl Fn(&ColorCombi) -> Vec<usize>,
+) -> ToolChangeReport {
+    let ranks: Vec<Vec<usize>> = combis.iter().map(|c| ranks(c)).collect();
+    let before = estimate_tool_changes(combis.iter().map(|c| &**c));
+
+    // Intern the filaments and describe every stack as (filament, run length)
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    let stacks: Vec<Vec<(usize, usize)>> = combis
+        .iter()
+        .map(|combi| {
+            combi
+                .layers()
+                .iter()
+                .map(|layer| {
+                    let next_id = ids.len();
+                    let id = *ids.entry(layer.hex_code().to_string()).or_insert(next_id);
+                    (id, layer.layer() as usize)
+                })
+                .collect()
+        })
+        .collect();
+    let height = stacks
+        .iter()
+        .map(|runs| runs.iter().map(|r| r.1).sum::<usize>())
+        .max()
+        .unwrap_or(0);
+
+    let mut occupancy = Occupancy::new(height, ids.len());
+    let mut orders: Vec<Vec<usize>> = stacks
+        .iter()
+        .map(|runs| (0..runs.len()).collect())
+        .collect();
+    for (runs, order) in stacks.iter().zip(&orders) {
+        occupancy.update(runs, order, true);
+    }
+
+    for _ in 0..MAX_PASSES {
+        let mut changed = false;
+        for ((runs, ranks), order) in stacks.iter().zip(&ranks).zip(orders.iter_mut()) {
+            if !ranks.windows(2).any(|w| w[0] == w[1]) {
+                // Nothing may move
+                continue;
+            }
+            occupancy.update(runs, order, false);
+            if let Some(better) = occupancy.best_order(runs, ranks, order) {
+                *order = better;
+                changed = true;
+            }
+            occupancy.update(runs, order, true);
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for (combi, order) in combis.iter_mut().zip(&orders) {
+        combi.reorder_layers(order);
+    }
+
+    ToolChangeReport {
+        before,
+        after: estimate_tool_changes(combis.iter().map(|c| &**c)),
+    }
+}
+
+/// Number of stacks using each filament on each Z layer
+struct Occupancy {
+    /// `counts[z][filament]`
+    counts: Vec<Vec<u32>>,
+}
+
+/// Placement cost: (filaments new to a Z layer, shared layers); lower is better
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cost {
+    new: usize,
+    shared: u64,
+}
+
+impl Cost {
+    fn is_better_than(&self, other: &Self) -> bool {
+        (self.new, std::cmp::Reverse(self.shared)) < (other.new, std::cmp::Reverse(other.shared))
+    }
+}
+
+impl Occupancy {
+    fn new(height: usize, nb_filaments: usize) -> Self {
+        Self {
+            counts: vec![vec![0; nb_filaments]; height],
+        }
+    }
+
+    /// Adds or removes a stack in the given order
+    fn update(&mut self, runs: &[(usize, usize)], order: &[usize], add: bool) {
+        let mut z = 0;
+        for &i in order {
+            let (filament, len) = runs[i];
+            for count in self.counts[z..z + len].iter_mut() {
+                if add {
+                    count[filament] += 1;
+                } else {
+                    count[filament] -= 1;
+                }
+            }
+            z += len;
+        }
+    }
+
+    /// Cost of placing one run starting at height `z`
+    fn run_cost(&self, filament: usize, z: usize, len: usize) -> Cost {
+        let mut cost = Cost { new: 0, shared: 0 };
+        for count in &self.counts[z..z + len] {
+            match count[filament] {
+                0 => cost.new += 1,
+                n => cost.shared += u64::from(n),
+            }
+        }
+        cost
+    }
+
+    fn order_cost(&self, runs: &[(usize, usize)], order: &[usize]) -> Cost {
+        let mut total = Cost { new: 0, shared: 0 };
+        let mut z = 0;
+        for &i in order {
+            let (filament, len) = runs[i];
+            let cost = self.run_cost(filament, z, len);
+            total.new += cost.new;
+            total.shared += cost.shared;
+            z += len;
+        }
+        total
+    }
+
+    /// Finds an order that is strictly cheaper than the current one
+    fn best_order(
+        &self,
+        runs: &[(usize, usize)],
+        ranks: &[usize],
+        current: &[usize],
+    ) -> Option<Vec<usize>> {
+        let current_cost = self.order_cost(runs, current);
+        let candidate = if runs.len() <= EXHAUSTIVE_MAX_RUNS {
+            self.exhaustive_order(runs, ranks)
+        } else {
+            self.greedy_order(runs, ranks)
+        };
+        let cost = self.order_cost(runs, &candidate);
+        cost.is_better_than(&current_cost).then_some(candidate)
+    }
+
+    /// Tries every allowed permutation (lexicographic, first best wins)
+    fn exhaustive_order(&self, runs: &[(usize, usize)], ranks: &[usize]) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..runs.len()).collect();
+        let mut best = order.clone();
+        let mut best_cost = self.order_cost(runs, &order);
+        while next_permutation(&mut order) {
+            if order.windows(2).any(|w| ranks[w[0]] > ranks[w[1]]) {
+                continue;
+            }
+            let cost = self.order_cost(runs, &order);
+            if cost.is_better_than(&best_cost) {
+                best_cost = cost;
+                best.clone_from(&order);
+            }
+        }
+        best
+    }
+
+    /// Places the cheapest remaining run of the lowest rank from the bottom up
+    fn greedy_order(&self, runs: &[(usize, usize)], ranks: &[usize]) -> Vec<usize> {
+        let mut remaining: Vec<usize> = (0..runs.len()).collect();
+        let mut order = Vec::with_capacity(runs.len());
+        let mut z = 0;
+        while !remaining.is_empty() {
+            let rank = remaining.iter().map(|&i| ranks[i]).min().unwrap_or(0);
+            let mut best = 0;
+            let mut best_cost = None;
+            for (pos, &i) in remaining.iter().enumerate() {
+                if ranks[i] != rank {
+                    continue;
+                }
+                let (filament, len) = runs[i];
+                let cost = self.run_cost(filament, z, len);
+                if best_cost.is_none_or(|best: Cost| cost.is_better_than(&best)) {
+                    best = pos;
+                    best_cost = Some(cost);
+                }
+            }
+            let i = remaining.remove(best);
+            z += runs[i].1;
+            order.push(i);
+        }
+        order
+    }
+}
+
+/// Advances to the next lexicographic permutation; returns false after the last
+fn next_permutation(order: &mut [usize]) -> bool {
+    let Some(i) = (1..order.len()).rev().find(|&i| order[i - 1] < order[i]) else {
+        return false;
+    };
+    let j = (i..order.len())
+        .rev()
+        .find(|&j| order[j] > order[i - 1])
+        .unwrap_or(i);
+    order.swap(i - 1, j);
+    order[i..].reverse();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::ColorLayer;
+
+    fn layer(hex: &str, count: u32, c: f64, m: f64, y: f64) -> ColorLayer {
+        ColorLayer::from_cmyk(hex.to_string(), count, c, m, y, 0.0)
+    }
+
+    fn hex_at(combi: &ColorCombi, z: usize) -> &str {
+        let mut top = 0;
+        for l in combi.layers() {
+            top += l.layer() as usize;
+            if z < top {
+                return l.hex_code();
+            }
+        }
+        panic!("z out of range");
+    }
+
+    #[test]
+    fn test_next_permutation() {
+        let mut order = vec![0, 1, 2];
+        let mut count = 1;
+        while next_permutation(&mut order) {
+            count += 1;
+        }
+        assert_eq!(count, 6);
+        assert_eq!(order, vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn test_estimate_tool_changes() {
+        let a = ColorCombi::from_layers(vec![
+            layer("#FFFFFF", 2, 0.0, 0.0, 0.0),
+            layer("#FF0000", 1, 0.0, 0.5, 0.5),
+        ]);
+        let b = ColorCombi::from_layers(vec![
+            layer("#FF0000", 1, 0.0, 0.5, 0.5),
+            layer("#FFFFFF", 2, 0.0, 0.0, 0.0),
+        ]);
+        // z0: W,R  z1: W  z2: R,W
+        assert_eq!(estimate_tool_changes([&a, &b]), 2);
+        assert_eq!(estimate_tool_changes([&a]), 0);
+    }
+
+    #[test]
+    fn test_optimize_aligns_filaments() {
+        let mut a = ColorCombi::from_layers(vec![
+            layer("#FFFFFF", 2, 0.0, 0.0, 0.0),
+            layer("#FF0000", 1, 0.0, 0.5, 0.5),
+            layer("#00FFFF", 2, 0.5, 0.0, 0.0),
+        ]);
+        let mut b = ColorCombi::from_layers(vec![
+            layer("#00FFFF", 2, 0.4, 0.0, 0.0),
+            layer("#FF0000", 1, 0.0, 0.4, 0.4),
+            layer("#FFFFFF", 2, 0.0, 0.0, 0.0),
+        ]);
+        let colors = (a.compute_rgb(), b.compute_rgb());
+
+        let report = optimize_stack_order(&mut [&mut a, &mut b]);
+
+        assert_eq!(report.before, 4);
+        assert_eq!(report.after, 0);
+        assert!(report.after < report.before);
+        for z in 0..5 {
+            assert_eq!(hex_at(&a, z), hex_at(&b, z));
+        }
+        // The mixed color does not depend on the order
+        assert_eq!((a.compute_rgb(), b.compute_rgb()), colors);
+    }
+
+    #[test]
+    fn test_optimize_keeps_order_without_gain() {
+        let original = ColorCombi::from_layers(vec![
+            layer("#FFFFFF", 3, 0.0, 0.0, 0.0),
+            layer("#FF0000", 2, 0.0, 0.5, 0.5),
+        ]);
+        let mut combi = original.clone();
+
+        let report = optimize_stack_order(&mut [&mut combi]);
+
+        assert_eq!(report, ToolChangeReport::default());
+        assert_eq!(combi, original);
+    }
+
+    #[test]
+    fn test_ranks_restrict_permutations() {
+        let mut a = ColorCombi::from_layers(vec![
+            layer("#FFFFFF", 1, 0.0, 0.0, 0.0),
+            layer("#FF0000", 1, 0.0, 0.5, 0.5),
+            layer("#00FFFF", 1, 0.5, 0.0, 0.0),
+        ]);
+        let mut b = ColorCombi::from_layers(vec![
+            layer("#FF0000", 1, 0.0, 0.4, 0.4),
+            layer("#00FFFF", 1, 0.4, 0.0, 0.0),
+            layer("#FFFFFF", 1, 0.0, 0.0, 0.0),
+        ]);
+        let original_b = b.clone();
+
+        // White is pinned: only the colored runs of `a` may swap
+        let report = optimize_stack_order_ranked(&mut [&mut a, &mut b], |combi| {
+            let mut rank = 0;
+            combi
+                .layers()
+                .iter()
+                .map(|l| {
+                    if l.hex_code() == "#FFFFFF" {
+                        rank += 2;
+                        rank - 1
+                    } else {
+                        rank
+                    }
+                })
+                .collect()
+        });
+
+        assert_eq!(report.before, 3);
+        assert_eq!(report.after, 2);
+        assert_eq!(a.layers()[0].hex_code(), "#FFFFFF");
+        assert_eq!(a.layers()[1].hex_code(), "#00FFFF");
+        assert_eq!(b, original_b);
+    }
+
+    #[test]
+    fn test_greedy_for_long_stacks() {
+        let hexes = [
+            "#000001", "#000002", "#000003", "#000004", "#000005", "#000006", "#000007",
+        ];
+        let mut a =
+            ColorCombi::from_layers(hexes.iter().map(|h| layer(h, 1, 0.1, 0.0, 0.0)).collect());
+        let mut b = ColorCombi::from_layers(
+            hexes
+                .iter()
+                .rev()
+                .map(|h| layer(h, 1, 0.1, 0.0, 0.0))
+                .collect(),
+        );
+
+        let report = optimize_stack_order(&mut [&mut a, &mut b]);
+
+        assert_eq!(report.before, 6);
+        assert_eq!(report.after, 0);
+    }
+}
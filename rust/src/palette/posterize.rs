@@ -0,0 +1,306 @@
+//! Posterization: reducing an image to a few representative colors
+//!
+//! For poster-style prints the image colors are clustered in CIELab before
+//! palette matching. Each cluster centre is mapped to its closest palette
+//! color, and quantization is then restricted to those stacks. The result has
+//! large, clean areas and only a handful of different stacks.
+//!
+//! ## Algorithm
+//!
+//! 1. The opaque pixels are reduced to their distinct colors with pixel counts
+//!    as weights.
+//! 2. [`PosterizeMethod::MedianCut`] repeatedly splits the box with the widest
+//!    Lab extent at the weighted median of that axis until there are K boxes;
+//!    the weighted means of the boxes are the centres.
+//! 3. [`PosterizeMethod::KMeans`] starts from the median-cut centres and refines
+//!    them with Lloyd iterations (assign to the nearest centre, recompute the
+//!    weighted means). The seeding makes the result deterministic.
+
+use crate::color::{CieLab, ColorDistanceMethod, Rgb};
+use crate::palette::Palette;
+use rayon::prelude::*;
+use std::collections::{BTreeSet, HashMap};
+
+/// Maximum number of k-means refinement iterations
+pub const MAX_KMEANS_ITERATIONS: usize = 20;
+
+/// Clustering algorithm used to find the representative colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PosterizeMethod {
+    /// Median-cut seeding refined with k-means
+    #[default]
+    KMeans,
+    /// Median cut only (faster, boxes follow the color distribution)
+    MedianCut,
+}
+
+/// A distinct image color in CIELab with its pixel count
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    lab: [f64; 3],
+    weight: f64,
+}
+
+/// Finds up to `k` representative colors of the given pixels
+///
+/// Returns fewer colors if the pixels contain fewer distinct colors, and an
+/// empty list for `k == 0` or no pixels.
+#[must_use]
+pub fn cluster_colors(pixels: &[Rgb], k: usize, method: PosterizeMethod) -> Vec<Rgb> {
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let samples = histogram(pixels);
+    let centres = median_cut(&samples, k);
+    let centres = match method {
+        PosterizeMethod::KMeans => kmeans(&samples, centres),
+        PosterizeMethod::MedianCut => centres,
+    };
+
+    centres
+        .iter()
+        .map(|c| CieLab::new(c[0], c[1], c[2]).to_rgb())
+        .collect()
+}
+
+/// Finds the palette colors that represent the `k` clusters of the pixels
+///
+/// Each cluster centre is matched to its closest palette color with the given
+/// distance method; centres that map to the same color share it. The result is
+/// sorted and free of duplicates.
+#[must_use]
+pub fn posterize_palette_colors(
+    palette: &Palette,
+    pixels: &[Rgb],
+    k: usize,
+    method: PosterizeMethod,
+    distance: ColorDistanceMethod,
+) -> Vec<Rgb> {
+    let colors: BTreeSet<(u8, u8, u8)> = cluster_colors(pixels, k, method)
+        .iter()
+        .filter_map(|centre| palette.find_closest(centre, distance))
+        .map(|c| (c.r, c.g, c.b))
+        .collect();
+    colors
+        .into_iter()
+        .map(|(r, g, b)| Rgb::new(r, g, b))
+        .collect()
+}
+
+/// Collects the distinct colors with their counts, sorted for determinism
+fn histogram(pixels: &[Rgb]) -> Vec<Sample> {
+    let mut counts: HashMap<Rgb, usize> = HashMap::new();
+    for pixel in pixels {
+        *counts.entry(*pixel).or_insert(0) += 1;
+    }
+
+    let mut colors: Vec<(Rgb, usize)> = counts.into_iter().collect();
+    colors.sort_by_key(|(c, _)| (c.r, c.g, c.b));
+    colors
+        .into_iter()
+        .map(|(color, count)| {
+            let lab = CieLab::from(color);
+            Sample {
+                lab: [lab.l, lab.a, lab.b],
+                weight: count as f64,
+            }
+        })
+        .collect()
+}
+
+/// Splits the samples into up to `k` boxes and returns their weighted means
+fn median_cut(samples: &[Sample], k: usize) -> Vec<[f64; 3]> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+
+    let mut boxes: Vec<Vec<Sample>> = vec![samples.to_vec()];
+    while boxes.len() < k {
+        // Widest splittable box first
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let (axis, extent) = widest_axis(b);
+                (i, axis, extent)
+            })
+            .max_by(|a, b| a.2.total_cmp(&b.2));
+        let Some((index, axis, _)) = widest else {
+            break;
+        };
+
+        let mut splitting = boxes.swap_remove(index);
+        splitting.sort_by(|a, b| a.lab[axis].total_cmp(&b.lab[axis]));
+        let half = splitting.iter().map(|s| s.weight).sum::<f64>() / 2.0;
+        let mut cumulative = 0.0;
+        let mut cut = 1;
+        for (i, sample) in splitting.iter().enumerate() {
+            cumulative += sample.weight;
+            if cumulative >= half {
+                cut = i + 1;
+                break;
+            }
+        }
+        let cut = cut.clamp(1, splitting.len() - 1);
+        let upper = splitting.split_off(cut);
+        boxes.push(splitting);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map(|b| weighted_mean(b)).collect()
+}
+
+/// Refines the centres with Lloyd iterations
+///
+/// Centres without samples keep their position.
+fn kmeans(samples: &[Sample], mut centres: Vec<[f64; 3]>) -> Vec<[f64; 3]> {
+    if centres.is_empty() {
+        return centres;
+    }
+
+    let mut assignment: Vec<usize> = vec![usize::MAX; samples.len()];
+    for _ in 0..MAX_KMEANS_ITERATIONS {
+        let next: Vec<usize> = samples
+            .par_iter()
+            .map(|s| nearest_centre(&s.lab, &centres))
+            .collect();
+        if next == assignment {
+            break;
+        }
+        assignment = next;
+
+        let mut sums = vec![([0.0; 3], 0.0); centres.len()];
+        for (sample, &cluster) in samples.iter().zip(&assignment) {
+            let (sum, weight) = &mut sums[cluster];
+            for (total, value) in sum.iter_mut().zip(sample.lab) {
+                *total += value * sample.weight;
+            }
+            *weight += sample.weight;
+        }
+        for (centre, (sum, weight)) in centres.iter_mut().zip(sums) {
+            if weight > 0.0 {
+                *centre = [sum[0] / weight, sum[1] / weight, sum[2] / weight];
+            }
+        }
+    }
+
+    centres
+}
+
+/// Index of the centre closest to `lab` (squared Euclidean distance, ties → lower index)
+fn nearest_centre(lab: &[f64; 3], centres: &[[f64; 3]]) -> usize {
+    let distance = |c: &[f64; 3]| (0..3).map(|i| (lab[i] - c[i]).powi(2)).sum::<f64>();
+    centres
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Axis with the largest extent of a box and that extent
+fn widest_axis(samples: &[Sample]) -> (usize, f64) {
+    (0..3)
+        .map(|axis| {
+            let (min, max) = samples.iter().fold((f64::MAX, f64::MIN), |(min, max), s| {
+                (min.min(s.lab[axis]), max.max(s.lab[axis]))
+            });
+            (axis, max - min)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((0, 0.0))
+}
+
+/// Weighted mean of a box
+fn weighted_mean(samples: &[Sample]) -> [f64; 3] {
+    let total: f64 = samples.iter().map(|s| s.weight).sum();
+    let mut mean = [0.0; 3];
+    for sample in samples {
+        for (m, value) in mean.iter_mut().zip(sample.lab) {
+            *m += value * sample.weight / total;
+        }
+    }
+    mean
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::{ColorCombi, ColorLayer};
+
+    fn two_tone_pixels() -> Vec<Rgb> {
+        // Slightly noisy red and blue halves
+        let mut pixels = Vec::new();
+        for i in 0..50u8 {
+            pixels.push(Rgb::new(250 - i % 5, i % 3, 0));
+            pixels.push(Rgb::new(i % 4, 0, 245 + i % 7));
+        }
+        pixels
+    }
+
+    fn is_reddish(c: &Rgb) -> bool {
+        c.r > 200 && c.b < 50
+    }
+
+    fn is_bluish(c: &Rgb) -> bool {
+        c.b > 200 && c.r < 50
+    }
+
+    #[test]
+    fn test_zero_clusters_or_no_pixels() {
+        assert!(cluster_colors(&two_tone_pixels(), 0, PosterizeMethod::KMeans).is_empty());
+        assert!(cluster_colors(&[], 4, PosterizeMethod::KMeans).is_empty());
+    }
+
+    #[test]
+    fn test_two_clusters_find_both_tones() {
+        for method in [PosterizeMethod::KMeans, PosterizeMethod::MedianCut] {
+            let centres = cluster_colors(&two_tone_pixels(), 2, method);
+            assert_eq!(centres.len(), 2);
+            assert!(centres.iter().any(is_reddish), "{method:?}: {centres:?}");
+            assert!(centres.iter().any(is_bluish), "{method:?}: {centres:?}");
+        }
+    }
+
+    #[test]
+    fn test_fewer_distinct_colors_than_clusters() {
+        let pixels = vec![Rgb::new(255, 0, 0), Rgb::new(255, 0, 0), Rgb::new(0, 0, 0)];
+        let centres = cluster_colors(&pixels, 8, PosterizeMethod::KMeans);
+        assert_eq!(centres.len(), 2);
+        assert!(centres.contains(&Rgb::new(255, 0, 0)));
+        assert!(centres.contains(&Rgb::new(0, 0, 0)));
+    }
+
+    #[test]
+    fn test_clustering_is_deterministic() {
+        let pixels: Vec<Rgb> = (0..=255u8).map(|v| Rgb::new(v, 255 - v, v / 2)).collect();
+        let first = cluster_colors(&pixels, 5, PosterizeMethod::KMeans);
+        let second = cluster_colors(&pixels, 5, PosterizeMethod::KMeans);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_posterize_palette_colors_restricts_to_closest_stacks() {
+        let mut palette = Palette::new(1);
+        for color in [
+            Rgb::new(255, 0, 0),
+            Rgb::new(0, 0, 255),
+            Rgb::new(0, 255, 0),
+            Rgb::new(255, 255, 255),
+        ] {
+            let hex = format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b);
+            let layer = ColorLayer::new(hex, 1, 0.0, 0.0, 0.5);
+            palette.insert_combi(color, ColorCombi::new(layer));
+        }
+
+        let colors = posterize_palette_colors(
+            &palette,
+            &two_tone_pixels(),
+            2,
+            PosterizeMethod::KMeans,
+            ColorDistanceMethod::CieLab,
+        );
+        assert_eq!(colors, vec![Rgb::new(0, 0, 255), Rgb::new(255, 0, 0)]);
+    }
+}
@@ -1,12 +1,14 @@
 //! Image quantization to palette colors with parallel processing
 
-use crate::color::{find_closest_color_precomputed, CieLab, ColorDistanceMethod, Rgb};
+use crate::color::{CieLab, ColorDistanceMethod, Rgb};
 use crate::error::Result;
+use crate::palette::ColorIndex;
 use rayon::prelude::*;
 
 /// Quantizes pixels to the closest palette colors in parallel
 ///
-/// Based on Java Palette.quantizeColors with ExecutorService
+/// Based on Java Palette.quantizeColors with ExecutorService. The lookup uses a
+/// [`ColorIndex`] and yields the same colors as a brute-force search.
 ///
 /// # Arguments
 ///
@@ -47,16 +49,13 @@ pub fn quantize_pixels(
         return Ok(pixels.to_vec());
     }
 
-    // Pre-compute CIELab values for palette colors once
-    let palette_labs: Vec<CieLab> = palette_colors.iter().map(|c| CieLab::from(*c)).collect();
+    // Build the spatial index over the palette once
+    let index = ColorIndex::new(palette_colors, method);
 
     // Use Rayon for parallel processing
     let quantized: Vec<Rgb> = pixels
         .par_iter()
-        .map(|pixel| {
-            find_closest_color_precomputed(pixel, palette_colors, &palette_labs, method)
-                .expect("palette is non-empty")
-        })
+        .map(|pixel| index.nearest(pixel).expect("palette is non-empty"))
         .collect();
 
     Ok(quantized)
@@ -84,18 +83,15 @@ pub fn quantize_image(
         return Ok(image_data.to_vec());
     }
 
-    // Pre-compute CIELab values for palette colors once
-    let palette_labs: Vec<CieLab> = palette_colors.iter().map(|c| CieLab::from(*c)).collect();
+    // Build the spatial index over the palette once
+    let index = ColorIndex::new(palette_colors, method);
 
     // Process each row in parallel
     let quantized: Vec<Vec<Rgb>> = image_data
         .par_iter()
         .map(|row| {
             row.iter()
-                .map(|pixel| {
-                    find_closest_color_precomputed(pixel, palette_colors, &palette_labs, method)
-                        .expect("palette is non-empty")
-                })
+                .map(|pixel| index.nearest(pixel).expect("palette is non-empty"))
                 .collect()
         })
         .collect();
@@ -103,6 +99,163 @@ pub fn quantize_image(
     Ok(quantized)
 }
 
+/// Flag marking an occupied slot in the [`ColorLookup`] table
+const LOOKUP_FILLED: u32 = 1 << 24;
+
+/// Dense RGB → palette color lookup table
+///
+/// Remembers the quantization result for every RGB value seen so far, so that
+/// each distinct image color is matched against the palette only once, across
+/// images and (via [`PaletteCache`](crate::palette::PaletteCache)) across runs.
+/// The table covers the full 24-bit RGB cube; pages are only touched for
+/// colors that actually occur.
+///
+/// A lookup is only valid for the palette colors and distance method it was
+/// filled with.
+#[derive(Debug, Clone)]
+pub struct ColorLookup {
+    method: ColorDistanceMethod,
+    table: Vec<u32>,
+    len: usize,
+}
+
+impl ColorLookup {
+    /// Creates an empty lookup for the given distance method
+    #[must_use]
+    pub fn new(method: ColorDistanceMethod) -> Self {
+        Self {
+            method,
+            table: vec![0; 1 << 24],
+            len: 0,
+        }
+    }
+
+    /// Gets the distance method the lookup was filled with
+    #[must_use]
+    pub fn method(&self) -> ColorDistanceMethod {
+        self.method
+    }
+
+    /// Gets the number of stored colors
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks whether no color has been stored yet
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the palette color stored for an image color
+    #[must_use]
+    pub fn get(&self, color: &Rgb) -> Option<Rgb> {
+        let value = self.table[pack(color) as usize];
+        (value & LOOKUP_FILLED != 0).then(|| unpack(value))
+    }
+
+    /// Stores the palette color for an image color
+    pub fn insert(&mut self, color: Rgb, palette_color: Rgb) {
+        let slot = &mut self.table[pack(&color) as usize];
+        if *slot & LOOKUP_FILLED == 0 {
+            self.len += 1;
+        }
+        *slot = LOOKUP_FILLED | pack(&palette_color);
+    }
+
+    /// Raw table, one packed value per RGB color (used by the palette cache)
+    pub(crate) fn table(&self) -> &[u32] {
+        &self.table
+    }
+
+    /// Rebuilds a lookup from a raw table, or `None` if its size is wrong
+    pub(crate) fn from_table(method: ColorDistanceMethod, table: Vec<u32>) -> Option<Self> {
+        if table.len() != 1 << 24 {
+            return None;
+        }
+        let len = table
+            .iter()
+            .filter(|value| **value & LOOKUP_FILLED != 0)
+            .count();
+        Some(Self { method, table, len })
+    }
+
+    /// Iterates over all stored (image color, palette color) pairs
+    pub fn entries(&self) -> impl Iterator<Item = (Rgb, Rgb)> + '_ {
+        self.table
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| **value & LOOKUP_FILLED != 0)
+            .map(|(key, value)| (unpack(key as u32), unpack(*value)))
+    }
+}
+
+pub(super) fn pack(color: &Rgb) -> u32 {
+    (u32::from(color.r) << 16) | (u32::from(color.g) << 8) | u32::from(color.b)
+}
+
+pub(super) fn unpack(value: u32) -> Rgb {
+    Rgb::new((value >> 16) as u8, (value >> 8) as u8, value as u8)
+}
+
+/// Quantizes an image using and extending a [`ColorLookup`]
+///
+/// Colors already present in the lookup are taken from it; all other distinct
+/// colors are matched once against the palette (in parallel) and added.
+///
+/// # Arguments
+///
+/// * `image_data` - 2D array of pixels (row-major order)
+/// * `palette_colors` - Available palette colors
+/// * `method` - Color distance method to use
+/// * `lookup` - Lookup table filled with the same palette and method
+///
+/// # Returns
+///
+/// Quantized image data in row-major order, identical to [`quantize_image`]
+pub fn quantize_image_cached(
+    image_data: &[Vec<Rgb>],
+    palette_colors: &[Rgb],
+    method: ColorDistanceMethod,
+    lookup: &mut ColorLookup,
+) -> Result<Vec<Vec<Rgb>>> {
+    if palette_colors.is_empty() {
+        return Ok(image_data.to_vec());
+    }
+
+    // Collect distinct colors that are not in the lookup yet
+    let mut missing: Vec<Rgb> = image_data
+        .iter()
+        .flatten()
+        .filter(|pixel| lookup.get(pixel).is_none())
+        .copied()
+        .collect();
+    missing.sort_unstable_by_key(pack);
+    missing.dedup();
+
+    if !missing.is_empty() {
+        let index = ColorIndex::new(palette_colors, method);
+        let resolved: Vec<Rgb> = missing
+            .par_iter()
+            .map(|pixel| index.nearest(pixel).expect("palette is non-empty"))
+            .collect();
+        for (pixel, palette_color) in missing.into_iter().zip(resolved) {
+            lookup.insert(pixel, palette_color);
+        }
+    }
+
+    let lookup = &*lookup;
+    Ok(image_data
+        .par_iter()
+        .map(|row| {
+            row.iter()
+                .map(|pixel| lookup.get(pixel).expect("all colors resolved"))
+                .collect()
+        })
+        .collect())
+}
+
 /// Information about quantization results
 #[derive(Debug, Clone)]
 pub struct QuantizationStats {
@@ -112,6 +265,70 @@ pub struct QuantizationStats {
     pub total_pixels: usize,
     /// Color usage histogram (color -> count)
     pub color_usage: std::collections::HashMap<Rgb, usize>,
+    /// ΔE (CIELab) between each source pixel and its palette color, in pixel order
+    pub delta_e: Vec<f64>,
+}
+
+/// Summary of the color error of a quantization (ΔE, CIELab)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeltaESummary {
+    pub mean: f64,
+    pub median: f64,
+    /// 95th percentile (nearest rank)
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl QuantizationStats {
+    /// Collects usage and error statistics of already quantized pixels
+    ///
+    /// `source` and `quantized` are matched by index.
+    #[must_use]
+    pub fn new(source: &[Rgb], quantized: &[Rgb]) -> Self {
+        let mut color_usage = std::collections::HashMap::new();
+        for color in quantized {
+            *color_usage.entry(*color).or_insert(0) += 1;
+        }
+
+        let delta_e = source
+            .par_iter()
+            .zip(quantized)
+            .map(|(source, color)| CieLab::from(*source).delta_e(&CieLab::from(*color)))
+            .collect();
+
+        Self {
+            colors_used: color_usage.len(),
+            total_pixels: source.len(),
+            color_usage,
+            delta_e,
+        }
+    }
+
+    /// Mean, median, 95th percentile and maximum of the per-pixel ΔE
+    ///
+    /// Returns `None` if no pixels were quantized.
+    #[must_use]
+    pub fn delta_e_summary(&self) -> Option<DeltaESummary> {
+        if self.delta_e.is_empty() {
+            return None;
+        }
+        let mut sorted = self.delta_e.clone();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len();
+        let median = if n.is_multiple_of(2) {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+        } else {
+            sorted[n / 2]
+        };
+        let p95_rank = ((n as f64 * 0.95).ceil() as usize).clamp(1, n);
+
+        Some(DeltaESummary {
+            mean: sorted.iter().sum::<f64>() / n as f64,
+            median,
+            p95: sorted[p95_rank - 1],
+            max: sorted[n - 1],
+        })
+    }
 }
 
 /// Quantizes pixels and collects statistics
@@ -131,19 +348,7 @@ pub fn quantize_with_stats(
     method: ColorDistanceMethod,
 ) -> Result<(Vec<Rgb>, QuantizationStats)> {
     let quantized = quantize_pixels(pixels, palette_colors, method)?;
-
-    // Count color usage
-    let mut color_usage = std::collections::HashMap::new();
-    for color in &quantized {
-        *color_usage.entry(*color).or_insert(0) += 1;
-    }
-
-    let stats = QuantizationStats {
-        colors_used: color_usage.len(),
-        total_pixels: pixels.len(),
-        color_usage,
-    };
-
+    let stats = QuantizationStats::new(pixels, &quantized);
     Ok((quantized, stats))
 }
 
@@ -220,6 +425,28 @@ mod tests {
         assert_eq!(stats.colors_used, 2); // Red and Green used
         assert_eq!(stats.color_usage[&Rgb::new(255, 0, 0)], 2); // Red used 2 times
         assert_eq!(stats.color_usage[&Rgb::new(0, 255, 0)], 1); // Green used 1 time
+        assert!(stats.delta_e.iter().all(|&d| d < 1e-9)); // Exact matches
+    }
+
+    #[test]
+    fn test_delta_e_summary() {
+        let source = vec![Rgb::new(0, 0, 0); 20];
+        let mut quantized = vec![Rgb::new(0, 0, 0); 18];
+        quantized.push(Rgb::new(255, 255, 255));
+        quantized.push(Rgb::new(128, 128, 128));
+
+        let summary = QuantizationStats::new(&source, &quantized)
+            .delta_e_summary()
+            .unwrap();
+        let white = CieLab::from(Rgb::new(255, 255, 255)).l;
+        let gray = CieLab::from(Rgb::new(128, 128, 128)).l;
+
+        assert!((summary.max - white).abs() < 1e-9);
+        // Nearest rank: the 19th of 20 sorted errors
+        assert!((summary.p95 - gray).abs() < 1e-9);
+        assert!(summary.median.abs() < 1e-9);
+        assert!((summary.mean - (white + gray) / 20.0).abs() < 1e-9);
+        assert!(QuantizationStats::new(&[], &[]).delta_e_summary().is_none());
     }
 
     #[test]
@@ -244,6 +471,88 @@ mod tests {
         assert!(quantized.iter().all(|c| palette.contains(c)));
     }
 
+    #[test]
+    fn test_quantize_image_matches_brute_force() {
+        use crate::color::find_closest_color;
+
+        let palette: Vec<Rgb> = (0..200)
+            .map(|i| {
+                Rgb::new(
+                    (i * 37 % 256) as u8,
+                    (i * 73 % 256) as u8,
+                    (i * 113 % 256) as u8,
+                )
+            })
+            .collect();
+        let image: Vec<Vec<Rgb>> = (0..16)
+            .map(|y| {
+                (0..16)
+                    .map(|x| Rgb::new((x * 16) as u8, (y * 16) as u8, ((x + y) * 8) as u8))
+                    .collect()
+            })
+            .collect();
+
+        for method in [ColorDistanceMethod::Rgb, ColorDistanceMethod::CieLab] {
+            let quantized = quantize_image(&image, &palette, method).unwrap();
+            for (row, q_row) in image.iter().zip(&quantized) {
+                for (pixel, q) in row.iter().zip(q_row) {
+                    assert_eq!(*q, find_closest_color(pixel, &palette, method).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_color_lookup_insert_and_get() {
+        let mut lookup = ColorLookup::new(ColorDistanceMethod::Rgb);
+        assert!(lookup.is_empty());
+        assert_eq!(lookup.get(&Rgb::new(0, 0, 0)), None);
+
+        lookup.insert(Rgb::new(0, 0, 0), Rgb::new(1, 2, 3));
+        lookup.insert(Rgb::new(255, 255, 255), Rgb::new(0, 0, 0));
+        lookup.insert(Rgb::new(0, 0, 0), Rgb::new(4, 5, 6));
+
+        assert_eq!(lookup.len(), 2);
+        assert_eq!(lookup.get(&Rgb::new(0, 0, 0)), Some(Rgb::new(4, 5, 6)));
+        assert_eq!(
+            lookup.get(&Rgb::new(255, 255, 255)),
+            Some(Rgb::new(0, 0, 0))
+        );
+        assert_eq!(lookup.entries().count(), 2);
+    }
+
+    #[test]
+    fn test_quantize_image_cached_matches_uncached() {
+        let image = vec![
+            vec![
+                Rgb::new(255, 0, 0),
+                Rgb::new(128, 0, 0),
+                Rgb::new(255, 0, 0),
+            ],
+            vec![
+                Rgb::new(0, 255, 0),
+                Rgb::new(0, 128, 0),
+                Rgb::new(10, 10, 10),
+            ],
+        ];
+        let palette = vec![Rgb::new(255, 0, 0), Rgb::new(0, 255, 0), Rgb::new(0, 0, 0)];
+
+        let mut lookup = ColorLookup::new(ColorDistanceMethod::CieLab);
+        let expected = quantize_image(&image, &palette, ColorDistanceMethod::CieLab).unwrap();
+        let first =
+            quantize_image_cached(&image, &palette, ColorDistanceMethod::CieLab, &mut lookup)
+                .unwrap();
+        assert_eq!(first, expected);
+        assert_eq!(lookup.len(), 5);
+
+        // Second run is served entirely from the lookup
+        let second =
+            quantize_image_cached(&image, &palette, ColorDistanceMethod::CieLab, &mut lookup)
+                .unwrap();
+        assert_eq!(second, expected);
+        assert_eq!(lookup.len(), 5);
+    }
+
     #[test]
     fn test_quantize_cielab_vs_rgb() {
         let pixel = Rgb::new(200, 100, 50);
@@ -0,0 +1,355 @@
+//! Stacking strategies and color mixing models
+//!
+//! The generator only decides which filament runs form a color stack. The
+//! order of the runs from the support plate (bottom) to the viewing side (top)
+//! is chosen by a [`StackingStrategy`]:
+//!
+//! - `Classic`: base at the bottom and on top, colors in between (original behavior),
+//! - `DiffuserTop` / `DiffuserBottom`: all base runs on the viewing side / on the plate side,
+//! - `DarkFirst` / `LightFirst`: runs sorted by K value,
+//! - `AsMeasured`: the generator order is kept.
+//!
+//! The base filament is the white (or natural / off-white) filler of the
+//! palette, see [`PaletteColorEntry::base`](super::PaletteColorEntry::base).
+//!
+//! The [`MixingModel`] predicts the color of a stack. `Additive` sums the CMYK
+//! values and ignores the order. `OrderAware` lets the layers between a run
+//! and the viewer scatter its color: C, M and Y of every run are attenuated by
+//! [`ORDER_FALLOFF`] per layer above it, while K (absorbed light) is kept.
+
+use super::ColorLayer;
+use crate::color::Cmyk;
+use serde::{Deserialize, Serialize};
+
+/// Hex code of the base filament if the palette does not mark one
+pub const DEFAULT_BASE_HEX: &str = "#FFFFFF";
+
+/// Remaining chroma (C, M, Y) of a run per print layer between it and the viewer
+pub const ORDER_FALLOFF: f64 = 0.93;
+
+/// Order of the filament runs inside a color stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum StackingStrategy {
+    /// Base at the bottom and on top, colored runs in between (Java behavior)
+    #[default]
+    Classic,
+    /// All base runs on the viewing side as a diffuser
+    DiffuserTop,
+    /// All base runs on the plate side
+    DiffuserBottom,
+    /// Darkest runs (highest K) first, next to the plate
+    DarkFirst,
+    /// Lightest runs (lowest K) first, next to the plate
+    LightFirst,
+    /// The order of the generator (lightest palette entry first)
+    AsMeasured,
+}
+
+impl StackingStrategy {
+    /// Gets the CLI name of the strategy
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Classic => "classic",
+            Self::DiffuserTop => "diffuser-top",
+            Self::DiffuserBottom => "diffuser-bottom",
+            Self::DarkFirst => "dark-first",
+            Self::LightFirst => "light-first",
+            Self::AsMeasured => "as-measured",
+        }
+    }
+
+    /// Arranges the runs of a stack (bottom to top)
+    ///
+    /// # Arguments
+    ///
+    /// * `layers` - Runs of the stack in generator order
+    /// * `base_hex` - Hex code of the base filament
+    /// * `nb_color_pool` - Colors per AMS group (used by `Classic`)
+    pub(crate) fn arrange(
+        &self,
+        layers: Vec<ColorLayer>,
+        base_hex: &str,
+        nb_color_pool: usize,
+    ) -> Vec<ColorLayer> {
+        match self {
+            Self::Classic => arrange_classic(layers, base_hex, nb_color_pool),
+            Self::DiffuserTop => {
+                let (white, colored): (Vec<_>, Vec<_>) =
+                    layers.into_iter().partition(|l| l.hex_code() == base_hex);
+                colored.into_iter().chain(white).collect()
+            }
+            Self::DiffuserBottom => {
+                let (white, colored): (Vec<_>, Vec<_>) =
+                    layers.into_iter().partition(|l| l.hex_code() == base_hex);
+                white.into_iter().chain(colored).collect()
+            }
+            Self::DarkFirst => {
+                let mut layers = layers;
+                layers.sort_by(ColorLayer::compare_by_k);
+                layers
+            }
+            Self::LightFirst => {
+                let mut layers = layers;
+                layers.sort_by(|a, b| b.compare_by_k(a));
+                layers
+            }
+            Self::AsMeasured => layers,
+        }
+    }
+
+    /// Gets the permutation ranks of arranged runs for stack reordering
+    ///
+    /// Runs may only be swapped with neighbours of the same rank. Base runs
+    /// keep their place; the strategies that sort every run, `AsMeasured`
+    /// and the order-aware mixing model fix the whole stack.
+    #[must_use]
+    pub(crate) fn permutation_ranks(
+        &self,
+        layers: &[ColorLayer],
+        base_hex: &str,
+        model: MixingModel,
+    ) -> Vec<usize> {
+        let fixed = model == MixingModel::OrderAware
+            || matches!(self, Self::DarkFirst | Self::LightFirst | Self::AsMeasured);
+        if fixed {
+            return (0..layers.len()).collect();
+        }
+
+        let mut rank = 0;
+        layers
+            .iter()
+            .map(|layer| {
+                if layer.hex_code() == base_hex {
+                    rank += 2;
+                    rank - 1
+                } else {
+                    rank
+                }
+            })
+            .collect()
+    }
+}
+
+/// Base runs inside the first AMS pool go to the bottom, later ones to the top
+///
+/// Based on Java Palette.optimizeWhiteLayer
+fn arrange_classic(
+    layers: Vec<ColorLayer>,
+    base_hex: &str,
+    nb_color_pool: usize,
+) -> Vec<ColorLayer> {
+    let mut bottom_white = Vec::new();
+    let mut middle_colored = Vec::new();
+    let mut top_white = Vec::new();
+
+    let mut layer_count = 0;
+
+    for layer in layers {
+        let count = layer.layer() as usize;
+        if layer.hex_code() == base_hex {
+            if layer_count <= nb_color_pool {
+                bottom_white.push(layer);
+            } else {
+                top_white.push(layer);
+            }
+        } else {
+            middle_colored.push(layer);
+        }
+        layer_count += count;
+    }
+
+    bottom_white
+        .into_iter()
+        .chain(middle_colored)
+        .chain(top_white)
+        .collect()
+}
+
+/// Gets the thinnest filament run of an arranged stack (0 if empty)
+///
+/// Neighbouring runs of the same filament print as one run and are counted
+/// together.
+#[must_use]
+pub(crate) fn shortest_run(layers: &[ColorLayer]) -> u32 {
+    let mut shortest = None;
+    let mut run = 0;
+    for (i, layer) in layers.iter().enumerate() {
+        run += layer.layer();
+        let run_ends = layers
+            .get(i + 1)
+            .is_none_or(|next| next.hex_code() != layer.hex_code());
+        if run_ends {
+            shortest = Some(shortest.map_or(run, |s: u32| s.min(run)));
+            run = 0;
+        }
+    }
+    shortest.unwrap_or(0)
+}
+
+/// Model used to predict the color of a stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MixingModel {
+    /// Sum of the CMYK values, independent of the order (Java behavior)
+    #[default]
+    Additive,
+    /// Chroma of runs further away from the viewing side (top) is attenuated
+    OrderAware,
+}
+
+impl MixingModel {
+    /// Gets the CLI name of the model
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Additive => "additive",
+            Self::OrderAware => "order-aware",
+        }
+    }
+
+    /// Mixes the runs of a stack (bottom to top) into an unclamped CMYK sum
+    #[must_use]
+    pub fn mix(&self, layers: &[ColorLayer]) -> Cmyk {
+        let mut sum = Cmyk::new(0.0, 0.0, 0.0, 0.0);
+        let mut layers_above: u32 = layers.iter().map(ColorLayer::layer).sum();
+
+        for layer in layers {
+            layers_above -= layer.layer();
+            let weight = match self {
+                Self::Additive => 1.0,
+                Self::OrderAware => ORDER_FALLOFF.powi(layers_above as i32),
+            };
+            sum.c += layer.c() * weight;
+            sum.m += layer.m() * weight;
+            sum.y += layer.y() * weight;
+            sum.k += layer.k();
+        }
+
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white(n: u32) -> ColorLayer {
+        ColorLayer::from_cmyk(DEFAULT_BASE_HEX.to_string(), n, 0.0, 0.0, 0.0, 0.0)
+    }
+
+    fn red(n: u32) -> ColorLayer {
+        ColorLayer::from_cmyk("#FF0000".to_string(), n, 0.0, 0.8, 0.8, 0.1)
+    }
+
+    fn black(n: u32) -> ColorLayer {
+        ColorLayer::from_cmyk("#000000".to_string(), n, 0.0, 0.0, 0.0, 0.9)
+    }
+
+    fn hexes(layers: &[ColorLayer]) -> Vec<&str> {
+        layers.iter().map(|l| l.hex_code()).collect()
+    }
+
+    #[test]
+    fn test_diffuser_strategies() {
+        let layers = vec![white(1), red(2), white(1), black(1)];
+
+        let top = StackingStrategy::DiffuserTop.arrange(layers.clone(), DEFAULT_BASE_HEX, 5);
+        assert_eq!(
+            hexes(&top),
+            ["#FF0000", "#000000", DEFAULT_BASE_HEX, DEFAULT_BASE_HEX]
+        );
+
+        let bottom = StackingStrategy::DiffuserBottom.arrange(layers, DEFAULT_BASE_HEX, 5);
+        assert_eq!(
+            hexes(&bottom),
+            [DEFAULT_BASE_HEX, DEFAULT_BASE_HEX, "#FF0000", "#000000"]
+        );
+    }
+
+    #[test]
+    fn test_dark_and_light_first() {
+        let layers = vec![white(1), black(1), red(2)];
+
+        let dark = StackingStrategy::DarkFirst.arrange(layers.clone(), DEFAULT_BASE_HEX, 5);
+        assert_eq!(hexes(&dark), ["#000000", "#FF0000", DEFAULT_BASE_HEX]);
+
+        let light = StackingStrategy::LightFirst.arrange(layers.clone(), DEFAULT_BASE_HEX, 5);
+        assert_eq!(hexes(&light), [DEFAULT_BASE_HEX, "#FF0000", "#000000"]);
+
+        let measured = StackingStrategy::AsMeasured.arrange(layers.clone(), DEFAULT_BASE_HEX, 5);
+        assert_eq!(measured, layers);
+    }
+
+    #[test]
+    fn test_classic_splits_white() {
+        // The second white run starts above the first AMS pool (2 layers)
+        let layers = vec![white(1), red(2), white(2)];
+        let classic = StackingStrategy::Classic.arrange(layers, DEFAULT_BASE_HEX, 2);
+        assert_eq!(
+            hexes(&classic),
+            [DEFAULT_BASE_HEX, "#FF0000", DEFAULT_BASE_HEX]
+        );
+    }
+
+    #[test]
+    fn test_shortest_run() {
+        assert_eq!(shortest_run(&[]), 0);
+        assert_eq!(shortest_run(&[white(2), red(3), white(1)]), 1);
+        // Neighbouring runs of one filament count together
+        assert_eq!(shortest_run(&[red(3), white(1), white(2)]), 3);
+    }
+
+    #[test]
+    fn test_custom_base_filament() {
+        let natural = ColorLayer::from_cmyk("#F5F0E6".to_string(), 2, 0.0, 0.0, 0.02, 0.0);
+        let layers = vec![natural, red(2), white(1)];
+
+        let top = StackingStrategy::DiffuserTop.arrange(layers, "#F5F0E6", 5);
+        assert_eq!(hexes(&top), ["#FF0000", DEFAULT_BASE_HEX, "#F5F0E6"]);
+    }
+
+    #[test]
+    fn test_permutation_ranks() {
+        let layers = vec![red(1), black(1), white(1), red(1), white(1)];
+
+        let ranks = StackingStrategy::Classic.permutation_ranks(
+            &layers,
+            DEFAULT_BASE_HEX,
+            MixingModel::Additive,
+        );
+        assert_eq!(ranks, [0, 0, 1, 2, 3]);
+
+        let fixed = StackingStrategy::DarkFirst.permutation_ranks(
+            &layers,
+            DEFAULT_BASE_HEX,
+            MixingModel::Additive,
+        );
+        assert_eq!(fixed, [0, 1, 2, 3, 4]);
+
+        let order_aware = StackingStrategy::DiffuserTop.permutation_ranks(
+            &layers,
+            DEFAULT_BASE_HEX,
+            MixingModel::OrderAware,
+        );
+        assert_eq!(order_aware, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_additive_mix_ignores_order() {
+        let a = MixingModel::Additive.mix(&[white(1), red(2)]);
+        let b = MixingModel::Additive.mix(&[red(2), white(1)]);
+        assert_eq!(a, b);
+        assert!((a.m - 0.8).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_order_aware_mix_fades_covered_color() {
+        let covered = MixingModel::OrderAware.mix(&[red(2), white(3)]);
+        let visible = MixingModel::OrderAware.mix(&[white(3), red(2)]);
+
+        assert!((visible.m - 0.8).abs() < 1e-12);
+        assert!((covered.m - 0.8 * ORDER_FALLOFF.powi(3)).abs() < 1e-12);
+        // Absorbed light does not depend on the order
+        assert_eq!(covered.k, visible.k);
+    }
+}
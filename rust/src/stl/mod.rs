@@ -10,7 +10,7 @@
 //! erstellt, das je eine `.stl`-Datei pro Layer enthält.
 
 use crate::error::{PixestlError, Result};
-use crate::filament::FilamentMapping;
+use crate::filament::{FilamentMapping, FilamentProperties};
 use crate::lithophane::geometry::{Mesh, Triangle, Vector3};
 use crate::lithophane::layer::NamedLayer;
 use std::collections::HashMap;
@@ -259,13 +259,34 @@ fn generate_model_settings_config(layers: &[NamedLayer], colors: &[&str]) -> Str
 /// * `output_path` - Pfad zur Ausgabe-3MF-Datei
 /// * `_format`     - Wird ignoriert; 3MF verwendet kein STL-Format intern
 ///
+/// Der Filamentverbrauch (`used_m`, `used_g`) wird mit den Standardwerten für
+/// PLA geschätzt, siehe [`export_to_3mf_with_properties`].
+///
 /// # Errors
 ///
 /// Gibt `PixestlError::Io` oder `PixestlError::Other` zurück bei Schreibfehlern.
 pub fn export_to_3mf<P: AsRef<std::path::Path>>(
+    layers: &[NamedLayer],
+    output_path: P,
+    format: StlFormat,
+) -> Result<()> {
+    export_to_3mf_with_properties(layers, output_path, format, &HashMap::new())
+}
+
+/// Exportiert mehrere Layer in eine `.3mf`-Datei wie [`export_to_3mf`].
+///
+/// Der geschätzte Filamentverbrauch pro Slot wird mit den Materialeigenschaften
+/// (Dichte, Durchmesser) aus `properties` berechnet; Hex-Codes ohne Eintrag
+/// verwenden die Standardwerte.
+///
+/// # Errors
+///
+/// Gibt `PixestlError::Io` oder `PixestlError::Other` zurück bei Schreibfehlern.
+pub fn export_to_3mf_with_properties<P: AsRef<std::path::Path>>(
     layers: &[NamedLayer],
     output_path: P,
     _format: StlFormat,
+    properties: &HashMap<String, FilamentProperties>,
 ) -> Result<()> {
     use lib3mf_core::model::{
         BuildItem, Color, ColorGroup, Geometry, Mesh as Lib3mfMesh, Model, Object, ObjectType,
@@ -275,7 +296,7 @@ pub fn export_to_3mf<P: AsRef<std::path::Path>>(
     use std::io::Cursor;
 
     // Filament-Zuordnung zentral berechnen
-    let filament_mapping = FilamentMapping::from_layers(layers);
+    let filament_mapping = FilamentMapping::from_layers_with_properties(layers, properties);
 
     let mut model = Model {
         unit: Unit::Millimeter,
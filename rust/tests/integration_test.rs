@@ -302,3 +302,819 @@ fn test_full_pipeline_both_layers() {
         "ZIP must contain one file per layer"
     );
 }
+
+/// Stack reordering reports tool changes and never makes them worse.
+#[test]
+fn test_pipeline_optimize_tool_changes() {
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    let image = test_image(8, 8);
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+
+    let config = LithophaneConfig {
+        dest_width_mm: 8.0,
+        dest_height_mm: 8.0,
+        color_pixel_width: 1.0,
+        texture_layer: false,
+        optimize_tool_changes: true,
+        ..LithophaneConfig::default()
+    };
+    let generator = LithophaneGenerator::new(config).expect("config must be valid");
+    let (layers, report) = generator
+        .generate_with_report(&image, &palette)
+        .expect("generation must succeed");
+
+    assert!(!layers.is_empty());
+    let tool_changes = report.tool_changes.expect("reordering must be reported");
+    assert!(tool_changes.after <= tool_changes.before);
+}
+
+/// Shorter stacks are topped up by the plate or the texture layer.
+#[test]
+fn test_pipeline_variable_depth_stacks() {
+    use pixestl::lithophane::{DepthCompensation, Mesh, NamedLayer};
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    // Red only exists as a 3-layer run, so red pixels lack 2 of 5 layers
+    let json = r##"{
+  "#FF0000": { "name": "Red", "layers": { "3": { "H": 0, "S": 100, "L": 50 } } },
+  "#FFFFFF": { "name": "White", "layers": { "5": { "H": 0, "S": 0, "L": 100 } } }
+}"##;
+    let palette = PaletteLoader::load_from_str(
+        json,
+        PaletteLoaderConfig {
+            min_stack_layers: 3,
+            ..PaletteLoaderConfig::default()
+        },
+    )
+    .expect("palette must load");
+
+    let z_range = |layers: &[NamedLayer], name: &str| {
+        let mesh: &Mesh = &layers.iter().find(|l| l.name == name).unwrap().mesh;
+        mesh.triangles
+            .iter()
+            .flat_map(|t| [t.v0.z, t.v1.z, t.v2.z])
+            .fold((f64::MAX, f64::MIN), |(lo, hi), z| (lo.min(z), hi.max(z)))
+    };
+    let generate = |depth_compensation| {
+        let config = LithophaneConfig {
+            dest_width_mm: 8.0,
+            dest_height_mm: 8.0,
+            color_pixel_width: 1.0,
+            texture_pixel_width: 1.0,
+            depth_compensation,
+            ..LithophaneConfig::default()
+        };
+        LithophaneGenerator::new(config)
+            .expect("config must be valid")
+            .generate(&test_image(8, 8), &palette)
+            .expect("generation must succeed")
+    };
+
+    // Plate: red stacks sit on 2 layers of plate material and end at full height
+    let plate = generate(DepthCompensation::Plate);
+    let (red_low, red_high) = z_range(&plate, "layer-Red");
+    assert!((red_low - 0.2).abs() < 1e-9);
+    assert!((red_high - 0.5).abs() < 1e-9);
+    assert!((z_range(&plate, "layer-plate").1 - 0.2).abs() < 1e-9);
+
+    // Texture: stacks stay on the plate, the texture gets the 2 layers instead
+    let texture = generate(DepthCompensation::Texture);
+    let (red_low, _) = z_range(&texture, "layer-Red");
+    assert!(red_low.abs() < 1e-9);
+    let extra = z_range(&texture, "layer-texture").1 - z_range(&plate, "layer-texture").1;
+    assert!((extra - 0.2).abs() < 1e-9);
+}
+
+/// The height method prints one relief with a filament band per swap.
+#[test]
+fn test_pipeline_height_method() {
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig, PixelCreationMethod as Method};
+    use pixestl::LithophaneGenerator;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(
+        palette_file.path(),
+        PaletteLoaderConfig {
+            creation_method: Method::Height,
+            ..PaletteLoaderConfig::default()
+        },
+    )
+    .expect("palette must load");
+
+    let config = LithophaneConfig {
+        dest_width_mm: 4.0,
+        dest_height_mm: 4.0,
+        texture_pixel_width: 0.5,
+        pixel_creation_method: PixelCreationMethod::Height,
+        ..LithophaneConfig::default()
+    };
+    let generator = LithophaneGenerator::new(config).expect("config must be valid");
+    let (layers, report) = generator
+        .generate_with_report(&test_image(8, 8), &palette)
+        .expect("generation must succeed");
+
+    let schedule = report.swap_schedule.expect("schedule must be reported");
+    assert!(!schedule.swaps.is_empty());
+    assert_eq!(schedule.swaps[0].start_layer, 0);
+    assert!(schedule
+        .swaps
+        .windows(2)
+        .all(|w| w[0].start_layer < w[1].start_layer));
+
+    // One mesh per band, named after the filament
+    assert_eq!(layers.len(), schedule.swaps.len());
+    for (layer, swap) in layers.iter().zip(&schedule.swaps) {
+        assert_eq!(layer.hex_color.as_deref(), Some(swap.hex_code.as_str()));
+    }
+    assert!(layers.iter().all(|l| l.name != "layer-texture"));
+}
+
+#[test]
+fn test_pipeline_pixel_art() {
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+
+    // 4 source pixels at 10 mm / 0.8 mm → 3x3 color pixels each (9.6 mm)
+    let config = LithophaneConfig {
+        dest_width_mm: 10.0,
+        texture_layer: false,
+        pixel_art: true,
+        exact_color_match: true,
+        ..LithophaneConfig::default()
+    };
+    let generator = LithophaneGenerator::new(config).expect("config must be valid");
+    let layers = generator
+        .generate(&test_image(4, 4), &palette)
+        .expect("generation must succeed");
+
+    let max_x = layers
+        .iter()
+        .flat_map(|l| &l.mesh.triangles)
+        .flat_map(|t| [t.v0.x, t.v1.x, t.v2.x])
+        .fold(f64::MIN, f64::max);
+    assert!((max_x - 9.6).abs() < 1e-6, "width was {max_x}");
+
+    // The red half is printed with the red filament alone
+    let red = layers
+        .iter()
+        .find(|l| l.hex_color.as_deref() == Some("#FF0000"))
+        .expect("red layer must exist");
+    assert!(red.mesh.triangle_count() > 0);
+}
+
+#[test]
+fn test_pipeline_transparent_edges() {
+    use image::{ImageBuffer, Rgba};
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    // Left two columns transparent, third column anti-aliased, rest opaque red
+    let buf = ImageBuffer::from_fn(8, 4, |x, _| match x {
+        0 | 1 => Rgba([0u8, 0, 0, 0]),
+        2 => Rgba([255, 0, 0, 160]),
+        _ => Rgba([255, 0, 0, 255]),
+    });
+    let image = image::DynamicImage::ImageRgba8(buf);
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+
+    let min_x = |config: LithophaneConfig| {
+        let generator = LithophaneGenerator::new(config).expect("config must be valid");
+        let layers = generator
+            .generate(&image, &palette)
+            .expect("generation must succeed");
+        layers
+            .iter()
+            .filter(|l| l.name != "layer-plate")
+            .flat_map(|l| &l.mesh.triangles)
+            .flat_map(|t| [t.v0.x, t.v1.x, t.v2.x])
+            .fold(f64::MAX, f64::min)
+    };
+    let base = LithophaneConfig {
+        color_pixel_width: 1.0,
+        texture_layer: false,
+        ..LithophaneConfig::default()
+    };
+
+    // Transparent pixels stay in place; the first opaque column is eroded
+    assert!((min_x(base.clone()) - 4.0).abs() < 1e-9);
+    // The anti-aliased column counts as opaque and the edge is kept
+    let kept = LithophaneConfig {
+        alpha_threshold: 128,
+        color_mask_radius: 0,
+        ..base.clone()
+    };
+    assert!((min_x(kept) - 2.0).abs() < 1e-9);
+    // Dilation grows the color area into the transparent columns
+    let dilated = LithophaneConfig {
+        color_mask_radius: 1,
+        ..base
+    };
+    assert!((min_x(dilated) - 2.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_pipeline_posterize() {
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig, PosterizeMethod};
+    use pixestl::LithophaneGenerator;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+    let image = test_image(8, 8);
+
+    let stacks = |posterize_colors: usize, posterize_method: PosterizeMethod| {
+        let config = LithophaneConfig {
+            dest_width_mm: 4.0,
+            dest_height_mm: 4.0,
+            texture_layer: false,
+            posterize_colors,
+            posterize_method,
+            ..LithophaneConfig::default()
+        };
+        let generator = LithophaneGenerator::new(config).expect("config must be valid");
+        let (_, report) = generator
+            .generate_with_report(&image, &palette)
+            .expect("generation must succeed");
+        report.posterized_stacks
+    };
+
+    assert_eq!(stacks(0, PosterizeMethod::KMeans), None);
+    assert_eq!(stacks(1, PosterizeMethod::KMeans), Some(1));
+    // Red and white halves end up on two different stacks
+    assert_eq!(stacks(2, PosterizeMethod::KMeans), Some(2));
+    assert_eq!(stacks(2, PosterizeMethod::MedianCut), Some(2));
+}
+
+#[test]
+fn test_pipeline_color_locks() {
+    use pixestl::palette::{ColorLocks, PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+    let image = test_image(8, 8);
+
+    let generate = |locks: &str| {
+        let config = LithophaneConfig {
+            dest_width_mm: 4.0,
+            dest_height_mm: 4.0,
+            color_pixel_width: 0.5,
+            texture_layer: false,
+            color_locks: ColorLocks::from_json(locks).expect("locks must parse"),
+            ..LithophaneConfig::default()
+        };
+        LithophaneGenerator::new(config)
+            .expect("config must be valid")
+            .generate_with_report(&image, &palette)
+    };
+    let red_triangles = |layers: &[pixestl::NamedLayer]| {
+        layers
+            .iter()
+            .filter(|l| l.hex_color.as_deref() == Some("#FF0000"))
+            .map(|l| l.mesh.triangles.len())
+            .sum::<usize>()
+    };
+
+    // Locking red to the solid white stack removes all red from the print
+    let (layers, report) =
+        generate(r##"{ "#FF0000": { "stack": { "#FFFFFF": 5 } } }"##).expect("lock must resolve");
+    assert_eq!(red_triangles(&layers), 0);
+    assert_eq!(report.locked_colors.len(), 1);
+    assert_eq!(report.locked_colors[0].stack, "White x5");
+
+    // The nearest color is reported with its distance
+    let (layers, report) = generate(r##"{ "#FF0000": {} }"##).expect("lock must resolve");
+    assert!(red_triangles(&layers) > 0);
+    assert!(report.locked_colors[0].delta_e >= 0.0);
+
+    // An unreachable color within the limit fails the run
+    assert!(generate(r##"{ "#0000FF": { "max_delta_e": 2.0 } }"##).is_err());
+}
+
+#[test]
+fn test_pipeline_region_mask() {
+    use image::{ImageBuffer, Rgba};
+    use pixestl::lithophane::RegionMask;
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+    let image = test_image(8, 8);
+
+    // Left half labelled with `left`, right half with `right`
+    let generate = |left: [u8; 3], right: [u8; 3]| {
+        let mask = ImageBuffer::from_fn(2, 1, |x, _| {
+            let [r, g, b] = if x == 0 { left } else { right };
+            Rgba([r, g, b, 255])
+        });
+        let config = LithophaneConfig {
+            dest_width_mm: 4.0,
+            dest_height_mm: 4.0,
+            color_pixel_width: 0.5,
+            color_mask_radius: 0,
+            region_mask: Some(RegionMask::new(&image::DynamicImage::ImageRgba8(mask))),
+            ..LithophaneConfig::default()
+        };
+        LithophaneGenerator::new(config)
+            .expect("config must be valid")
+            .generate(&image, &palette)
+            .expect("generation must succeed")
+    };
+    let color_max_x = |layers: &[pixestl::NamedLayer]| {
+        layers
+            .iter()
+            .filter(|l| l.hex_color.is_some() && l.name != "layer-texture")
+            .flat_map(|l| &l.mesh.triangles)
+            .flat_map(|t| [t.v0.x, t.v1.x, t.v2.x])
+            .fold(f64::MIN, f64::max)
+    };
+    let has_plate = |layers: &[pixestl::NamedLayer]| layers.iter().any(|l| l.name == "layer-plate");
+
+    // Color on the left, grayscale texture on the right: the plate stays
+    let layers = generate([255, 255, 255], [128, 128, 128]);
+    assert!((color_max_x(&layers) - 2.0).abs() < 1e-9);
+    assert!(layers.iter().any(|l| l.name == "layer-texture"));
+    assert!(has_plate(&layers));
+
+    // Excluding the right half drops the full plate
+    let layers = generate([255, 255, 255], [0, 0, 0]);
+    assert!((color_max_x(&layers) - 2.0).abs() < 1e-9);
+    assert!(!has_plate(&layers));
+}
+
+/// Short stacks in a color-only region are filled by the plate even when the
+/// texture compensates the depth elsewhere.
+#[test]
+fn test_pipeline_region_mask_depth_compensation() {
+    use image::{ImageBuffer, Rgba};
+    use pixestl::lithophane::{DepthCompensation, RegionMask};
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    // Red only exists as a 3-layer run, so red pixels lack 2 of 5 layers
+    let json = r##"{
+  "#FF0000": { "name": "Red", "layers": { "3": { "H": 0, "S": 100, "L": 50 } } },
+  "#FFFFFF": { "name": "White", "layers": { "5": { "H": 0, "S": 0, "L": 100 } } }
+}"##;
+    let palette = PaletteLoader::load_from_str(
+        json,
+        PaletteLoaderConfig {
+            min_stack_layers: 3,
+            ..PaletteLoaderConfig::default()
+        },
+    )
+    .expect("palette must load");
+
+    // Left half color only, right half color and texture
+    let mask = ImageBuffer::from_fn(2, 1, |x, _| {
+        if x == 0 {
+            Rgba([255u8, 0, 0, 255])
+        } else {
+            Rgba([255u8, 255, 255, 255])
+        }
+    });
+    let config = LithophaneConfig {
+        dest_width_mm: 8.0,
+        dest_height_mm: 8.0,
+        color_pixel_width: 1.0,
+        texture_pixel_width: 1.0,
+        color_mask_radius: 0,
+        depth_compensation: DepthCompensation::Texture,
+        region_mask: Some(RegionMask::new(&image::DynamicImage::ImageRgba8(mask))),
+        ..LithophaneConfig::default()
+    };
+    let layers = LithophaneGenerator::new(config)
+        .expect("config must be valid")
+        .generate(&test_image(8, 8), &palette)
+        .expect("generation must succeed");
+
+    // Red cubes: (lowest z, x range) per cube of 12 triangles
+    let red = &layers.iter().find(|l| l.name == "layer-Red").unwrap().mesh;
+    let cubes: Vec<(f64, f64, f64)> = red
+        .triangles
+        .chunks(12)
+        .map(|cube| {
+            let points = cube.iter().flat_map(|t| [t.v0, t.v1, t.v2]);
+            points.fold((f64::MAX, f64::MAX, f64::MIN), |(z, lo, hi), p| {
+                (z.min(p.z), lo.min(p.x), hi.max(p.x))
+            })
+        })
+        .collect();
+    // Color-only stacks are raised onto the plate fill, the others are not
+    assert!(cubes.iter().any(|&(z, _, _)| (z - 0.2).abs() < 1e-9));
+    assert!(cubes.iter().any(|&(z, _, _)| z.abs() < 1e-9));
+    for &(z, lo, hi) in &cubes {
+        if (z - 0.2).abs() < 1e-9 {
+            assert!(hi <= 4.0 + 1e-9);
+        } else {
+            assert!(lo >= 4.0 - 1e-9);
+        }
+    }
+    let plate = &layers
+        .iter()
+        .find(|l| l.name == "layer-plate")
+        .unwrap()
+        .mesh;
+    let plate_top = plate
+        .triangles
+        .iter()
+        .flat_map(|t| [t.v0.z, t.v1.z, t.v2.z])
+        .fold(f64::MIN, f64::max);
+    assert!((plate_top - 0.2).abs() < 1e-9);
+}
+
+#[test]
+fn test_pipeline_outline_layer() {
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+
+    let config = LithophaneConfig {
+        dest_width_mm: 4.0,
+        dest_height_mm: 4.0,
+        color_pixel_width: 0.5,
+        texture_layer: false,
+        outline_width: 2,
+        ..LithophaneConfig::default()
+    };
+    let generator = LithophaneGenerator::new(config).expect("config must be valid");
+    let layers = generator
+        .generate(&test_image(8, 8), &palette)
+        .expect("generation must succeed");
+
+    // The red/white boundary becomes one two-pixel band in black filament
+    let outline = layers
+        .iter()
+        .find(|l| l.name == "layer-outline")
+        .expect("outline layer must exist");
+    assert_eq!(outline.hex_color.as_deref(), Some("#000000"));
+    assert_eq!(outline.mesh.triangle_count(), 2 * 12);
+    let ys: Vec<f64> = outline
+        .mesh
+        .triangles
+        .iter()
+        .flat_map(|t| [t.v0.y, t.v1.y, t.v2.y])
+        .collect();
+    let min_y = ys.iter().copied().fold(f64::MAX, f64::min);
+    let max_y = ys.iter().copied().fold(f64::MIN, f64::max);
+    assert!((min_y - 1.5).abs() < 1e-9 && (max_y - 2.5).abs() < 1e-9);
+}
+
+#[test]
+fn test_pipeline_texture_attenuation_compensation() {
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+
+    let texture_volume = |texture_attenuation_compensation: bool| {
+        let config = LithophaneConfig {
+            dest_width_mm: 4.0,
+            dest_height_mm: 4.0,
+            color_pixel_width: 0.5,
+            texture_pixel_width: 0.5,
+            texture_attenuation_compensation,
+            ..LithophaneConfig::default()
+        };
+        let generator = LithophaneGenerator::new(config).expect("config must be valid");
+        let layers = generator
+            .generate(&test_image(8, 8), &palette)
+            .expect("generation must succeed");
+        let texture = layers
+            .iter()
+            .find(|l| l.name == "layer-texture")
+            .expect("texture layer must exist");
+        texture
+            .mesh
+            .triangles
+            .iter()
+            .map(|t| t.v0.z + t.v1.z + t.v2.z)
+            .sum::<f64>()
+    };
+
+    // The red stack already darkens the light, so the texture above it is thinner
+    assert!(texture_volume(true) < texture_volume(false));
+}
+
+#[test]
+fn test_pipeline_simulation() {
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+
+    let config = LithophaneConfig {
+        dest_width_mm: 4.0,
+        dest_height_mm: 4.0,
+        color_pixel_width: 0.5,
+        texture_pixel_width: 0.25,
+        simulate: true,
+        ..LithophaneConfig::default()
+    };
+    let generator = LithophaneGenerator::new(config).expect("config must be valid");
+    let (_, report) = generator
+        .generate_with_report(&test_image(8, 8), &palette)
+        .expect("generation must succeed");
+    let simulation = report.simulation.expect("simulation must be rendered");
+
+    // Rendered at the finer texture resolution, in image orientation
+    assert_eq!(simulation.dimensions(), (16, 16));
+    let top = simulation.get_pixel(8, 2);
+    let bottom = simulation.get_pixel(8, 13);
+    assert_eq!(top[3], 255);
+    assert!(top[0] > top[1] && top[0] > top[2], "top half must look red");
+    assert!(bottom[1] > top[1], "bottom half must look white");
+}
+
+#[test]
+fn test_pipeline_accuracy_report() {
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+
+    let config = LithophaneConfig {
+        dest_width_mm: 4.0,
+        dest_height_mm: 4.0,
+        color_pixel_width: 0.5,
+        ..LithophaneConfig::default()
+    };
+    let generator = LithophaneGenerator::new(config).expect("config must be valid");
+    let (_, report) = generator
+        .generate_with_report(&test_image(8, 8), &palette)
+        .expect("generation must succeed");
+    let accuracy = report.accuracy.expect("accuracy must be reported");
+    let summary = accuracy.summary().expect("pixels must be compared");
+
+    assert_eq!(accuracy.stats.total_pixels, 64);
+    assert_eq!(accuracy.heatmap.dimensions(), (8, 8));
+    assert!(summary.mean <= summary.p95 && summary.p95 <= summary.max);
+    assert!(summary.median >= 0.0);
+    let used: usize = accuracy.stats.color_usage.values().sum();
+    assert_eq!(used, 64);
+
+    // Outline pixels print no stack and are not compared
+    let config = LithophaneConfig {
+        dest_width_mm: 4.0,
+        dest_height_mm: 4.0,
+        color_pixel_width: 0.5,
+        outline_width: 2,
+        ..LithophaneConfig::default()
+    };
+    let (_, report) = LithophaneGenerator::new(config)
+        .expect("config must be valid")
+        .generate_with_report(&test_image(8, 8), &palette)
+        .expect("generation must succeed");
+    let accuracy = report.accuracy.expect("accuracy must be reported");
+    assert_eq!(accuracy.stats.total_pixels, 48);
+    assert_eq!(accuracy.heatmap.get_pixel(0, 4)[3], 0);
+}
+
+#[test]
+fn test_pipeline_filament_usage() {
+    use pixestl::filament::{FilamentMapping, FilamentProperties, DEFAULT_DENSITY};
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+    use std::collections::HashMap;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+
+    let config = LithophaneConfig {
+        dest_width_mm: 4.0,
+        dest_height_mm: 4.0,
+        color_pixel_width: 0.5,
+        ..LithophaneConfig::default()
+    };
+    let plate_volume = 4.0 * 4.0 * config.plate_thickness;
+    let generator = LithophaneGenerator::new(config).expect("config must be valid");
+    let layers = generator
+        .generate(&test_image(8, 8), &palette)
+        .expect("generation must succeed");
+
+    let mapping = FilamentMapping::from_layers_with_properties(&layers, &HashMap::new());
+    let plate = layers
+        .iter()
+        .position(|l| l.name == "layer-plate")
+        .expect("plate layer must exist");
+    let usage = mapping.layer_usage()[plate];
+    assert!((usage.volume_mm3 - plate_volume).abs() < 1e-6);
+    assert!((usage.grams - plate_volume / 1000.0 * DEFAULT_DENSITY).abs() < 1e-9);
+
+    // Every layer is a closed solid with a positive volume
+    assert!(mapping.layer_usage().iter().all(|u| u.volume_mm3 > 0.0));
+
+    // A price per kg adds the cost
+    let properties: HashMap<String, FilamentProperties> = mapping
+        .colors()
+        .iter()
+        .map(|hex| {
+            let filament = FilamentProperties {
+                price_per_kg: Some(20.0),
+                ..FilamentProperties::default()
+            };
+            (hex.clone(), filament)
+        })
+        .collect();
+    let priced = FilamentMapping::from_layers_with_properties(&layers, &properties);
+    let total: f64 = priced.filament_usage().iter().map(|(_, u)| u.grams).sum();
+    let cost: f64 = priced
+        .filament_usage()
+        .iter()
+        .filter_map(|(_, u)| u.cost)
+        .sum();
+    assert!((cost - total / 1000.0 * 20.0).abs() < 1e-9);
+
+    // The plate has its own entry and is not part of a filament
+    let plate_usage = priced.plate_usage().expect("plate usage must be reported");
+    assert!((plate_usage.volume_mm3 - plate_volume).abs() < 1e-6);
+}
+
+#[test]
+fn test_pipeline_filament_usage_of_group_layer() {
+    use pixestl::filament::{FilamentMapping, FilamentProperties, DEFAULT_DENSITY};
+    use pixestl::palette::{PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+    use std::collections::HashMap;
+
+    // One color per AMS group: Green and Red share the first color layer
+    let json = r##"{
+        "#FF0000": { "name": "Red", "layers": { "5": { "H": 0, "S": 100, "L": 50 } } },
+        "#00FF00": { "name": "Green", "layers": { "5": { "H": 120, "S": 100, "L": 50 } } },
+        "#FFFFFF": { "name": "White", "layers": { "5": { "H": 0, "S": 0, "L": 100 } } }
+    }"##;
+    let palette = PaletteLoader::load_from_str(
+        json,
+        PaletteLoaderConfig {
+            color_number: 2,
+            ..PaletteLoaderConfig::default()
+        },
+    )
+    .expect("palette must load");
+
+    let config = LithophaneConfig {
+        dest_width_mm: 4.0,
+        dest_height_mm: 4.0,
+        color_pixel_width: 0.5,
+        texture_layer: false,
+        ..LithophaneConfig::default()
+    };
+    let generator = LithophaneGenerator::new(config).expect("config must be valid");
+    let layers = generator
+        .generate(&test_image(8, 8), &palette)
+        .expect("generation must succeed");
+
+    let group = layers
+        .iter()
+        .find(|l| l.name == "layer-Green+Red")
+        .expect("group layer must exist");
+    assert_eq!(group.filament_volumes.len(), 2);
+    let split: f64 = group.filament_volumes.iter().map(|(_, v)| v).sum();
+    assert!((split - group.mesh.volume()).abs() < 1e-6);
+
+    let properties = HashMap::from([(
+        "#00FF00".to_string(),
+        FilamentProperties {
+            density: 2.0,
+            ..FilamentProperties::default()
+        },
+    )]);
+    let mapping = FilamentMapping::from_layers_with_properties(&layers, &properties);
+    let grams = |hex: &str| {
+        mapping
+            .filament_usage()
+            .iter()
+            .find(|(h, _)| h == hex)
+            .map(|(_, usage)| usage.grams)
+    };
+    let volume = |hex: &str| {
+        group
+            .filament_volumes
+            .iter()
+            .find(|(h, _)| h == hex)
+            .map(|(_, v)| *v)
+            .unwrap()
+    };
+    // The red half of the image uses red, nothing is charged to the wrong filament
+    assert!(volume("#FF0000") > 0.0);
+    let red = grams("#FF0000").expect("red must be listed");
+    assert!((red - volume("#FF0000") / 1000.0 * DEFAULT_DENSITY).abs() < 1e-9);
+    let green = grams("#00FF00").expect("green must be listed");
+    assert!((green - volume("#00FF00") / 1000.0 * 2.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_pipeline_color_locks_after_resize() {
+    use image::{ImageBuffer, Rgba};
+    use pixestl::palette::{ColorLocks, PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+    // A brand red slightly off the lock color, downscaled from 16×16 to 8×8
+    let image = image::DynamicImage::ImageRgba8(ImageBuffer::from_fn(16, 16, |_, y| {
+        if y < 8 {
+            Rgba([228u8, 7, 20, 255])
+        } else {
+            Rgba([255u8, 255, 255, 255])
+        }
+    }));
+
+    let locked_pixels = |tolerance: f64| {
+        let config = LithophaneConfig {
+            dest_width_mm: 4.0,
+            dest_height_mm: 4.0,
+            color_pixel_width: 0.5,
+            texture_layer: false,
+            color_locks: ColorLocks::from_json(r##"{ "#E30613": { "stack": { "#FFFFFF": 5 } } }"##)
+                .expect("locks must parse")
+                .with_tolerance(tolerance),
+            ..LithophaneConfig::default()
+        };
+        let (_, report) = LithophaneGenerator::new(config)
+            .expect("config must be valid")
+            .generate_with_report(&image, &palette)
+            .expect("generation must succeed");
+        report.locked_colors[0].pixels
+    };
+
+    // The rows outside the reach of the Lanczos kernel at the edge are locked
+    assert!(locked_pixels(3.0) >= 16);
+    assert_eq!(locked_pixels(0.0), 0);
+}
+
+#[test]
+fn test_pipeline_color_locks_survive_cleanup() {
+    use image::{ImageBuffer, Rgba};
+    use pixestl::palette::{ColorLocks, PaletteLoader, PaletteLoaderConfig};
+    use pixestl::LithophaneGenerator;
+
+    let palette_file = test_palette_file();
+    let palette = PaletteLoader::load(palette_file.path(), PaletteLoaderConfig::default())
+        .expect("palette must load");
+    // A single red pixel in white: a speckle for the cleanup
+    let image = image::DynamicImage::ImageRgba8(ImageBuffer::from_fn(8, 8, |x, y| {
+        if (x, y) == (3, 3) {
+            Rgba([255u8, 0, 0, 255])
+        } else {
+            Rgba([255u8, 255, 255, 255])
+        }
+    }));
+
+    let red_triangles = |locks: &str| {
+        let config = LithophaneConfig {
+            dest_width_mm: 4.0,
+            dest_height_mm: 4.0,
+            color_pixel_width: 0.5,
+            texture_layer: false,
+            mode_filter_radius: 1,
+            min_island_size: 4,
+            color_locks: ColorLocks::from_json(locks).expect("locks must parse"),
+            ..LithophaneConfig::default()
+        };
+        let (layers, report) = LithophaneGenerator::new(config)
+            .expect("config must be valid")
+            .generate_with_report(&image, &palette)
+            .expect("generation must succeed");
+        let triangles = layers
+            .iter()
+            .filter(|l| l.hex_color.as_deref() == Some("#FF0000"))
+            .map(|l| l.mesh.triangles.len())
+            .sum::<usize>();
+        (triangles, report.cleaned_pixels)
+    };
+
+    let (triangles, cleaned) = red_triangles("{}");
+    assert_eq!(triangles, 0);
+    assert_eq!(cleaned, 1);
+
+    let (triangles, cleaned) = red_triangles(r##"{ "#FF0000": {} }"##);
+    assert!(triangles > 0);
+    assert_eq!(cleaned, 0);
+}